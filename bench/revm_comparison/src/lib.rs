@@ -1,6 +1,13 @@
 use evm_mlir::{
-    context::Context, db::Db, executor::Executor, journal::Journal, primitives::Bytes,
-    program::Program, syscall::SyscallContext, Env,
+    context::Context,
+    db::Db,
+    executor::Executor,
+    journal::Journal,
+    primitives::Bytes,
+    program::Program,
+    spec::SpecId,
+    syscall::{CallFrame, SyscallContext},
+    Env,
 };
 use revm::{
     db::BenchmarkDB,
@@ -16,23 +23,35 @@ pub const FACTORIAL_BYTECODE: &str =
 
 pub fn run_with_evm_mlir(program: &str, runs: usize, number_of_iterations: u32) {
     let bytes = hex::decode(program).unwrap();
-    let program = Program::from_bytecode(&bytes);
+    let program = Program::from_bytecode(&bytes, SpecId::default());
 
     let context = Context::new();
     let module = context
-        .compile(&program, Default::default())
+        .compile(&program, SpecId::default(), Default::default())
         .expect("failed to compile program");
 
     let mut env: Env = Default::default();
-    env.tx.gas_limit = 999_999;
+    env.tx.gas_limit = 999_999_999;
     let mut calldata = vec![0x00; 32];
     calldata[28..32].copy_from_slice(&number_of_iterations.to_be_bytes());
     env.tx.data = Bytes::from(calldata);
+    // The frame of the transaction, as built by `Evm::transact`
+    let address = env.tx.get_address();
+    let call_frame = CallFrame {
+        code_address: address,
+        address,
+        caller: env.tx.caller,
+        value: env.tx.value,
+        calldata: env.tx.data.clone(),
+        bytecode: Bytes::from(bytes),
+        gas_limit: env.tx.gas_limit,
+        ..Default::default()
+    };
+    let initial_gas = env.tx.gas_limit;
     let mut db = Db::default();
     let journal = Journal::new(&mut db);
-    let mut context = SyscallContext::new(env, journal, Default::default());
+    let mut context = SyscallContext::new(env, journal, call_frame);
     let executor = Executor::new(&module, &context, Default::default());
-    let initial_gas = 999_999_999;
 
     for _ in 0..runs - 1 {
         black_box(executor.execute(black_box(&mut context), black_box(initial_gas)));
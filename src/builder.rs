@@ -1,18 +1,35 @@
-use crate::{db::Database, env::Env, Evm};
+use std::sync::Arc;
+
+use crate::{db::Database, env::Env, executor::ExecutorCache, Evm};
 
 #[derive(Default)]
 pub struct EvmBuilder<DB: Database> {
     db: DB,
     env: Env,
+    executor_cache_capacity: Option<usize>,
 }
 
 impl<DB: Database + Default> EvmBuilder<DB> {
     /// Sets the [`Database`] that will be used by [`Evm`].
     pub fn with_db(self, db: DB) -> EvmBuilder<DB> {
-        EvmBuilder { db, env: self.env }
+        EvmBuilder { db, ..self }
+    }
+
+    /// Sets how many compiled executors [`Evm`] keeps around for later transactions.
+    ///
+    /// Defaults to [`crate::executor::DEFAULT_EXECUTOR_CACHE_CAPACITY`].
+    pub fn with_executor_cache_capacity(self, capacity: usize) -> EvmBuilder<DB> {
+        EvmBuilder {
+            executor_cache_capacity: Some(capacity),
+            ..self
+        }
     }
 
     pub fn build(self) -> Evm<DB> {
-        Evm::new(self.env, self.db)
+        let mut evm = Evm::new(self.env, self.db);
+        if let Some(capacity) = self.executor_cache_capacity {
+            evm.executor_cache = Arc::new(ExecutorCache::with_capacity(capacity));
+        }
+        evm
     }
 }
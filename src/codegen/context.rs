@@ -15,11 +15,13 @@ use melior::{
 
 use crate::{
     constants::{
-        CallType, CALLDATA_PTR_GLOBAL, CALLDATA_SIZE_GLOBAL, GAS_COUNTER_GLOBAL, MAX_STACK_SIZE,
-        MEMORY_PTR_GLOBAL, MEMORY_SIZE_GLOBAL, STACK_BASEPTR_GLOBAL, STACK_PTR_GLOBAL,
+        CallType, CALLDATA_PTR_GLOBAL, CALLDATA_SIZE_GLOBAL, CONTEXT_IS_STATIC_GLOBAL,
+        GAS_COUNTER_GLOBAL, MAX_STACK_SIZE, MEMORY_LIMIT_GLOBAL, MEMORY_PTR_GLOBAL,
+        MEMORY_SIZE_GLOBAL, STACK_BASEPTR_GLOBAL, STACK_PTR_GLOBAL,
     },
     errors::CodegenError,
     program::{Operation, Program},
+    spec::SpecId,
     syscall::{self, ExitStatusCode},
     utils::{
         allocate_and_store_value, constant_value_from_i64, consume_gas_as_value, get_remaining_gas,
@@ -33,11 +35,25 @@ pub(crate) struct OperationCtx<'c> {
     pub mlir_context: &'c MeliorContext,
     /// The program IR.
     pub program: &'c Program,
+    /// The spec whose rules the program is compiled with.
+    pub spec_id: SpecId,
     /// The syscall context to be passed to syscalls.
     pub syscall_ctx: Value<'c, 'c>,
     /// Reference to the revert block.
     /// This block takes care of reverts.
     pub revert_block: BlockRef<'c, 'c>,
+    /// Reference to the static call violation block.
+    /// This block halts the execution when a state-modifying operation runs in a static context.
+    pub static_call_violation_block: BlockRef<'c, 'c>,
+    /// Reference to the out of offset block.
+    /// This block halts the execution when an operation reads past the end of the return data.
+    pub out_of_offset_block: BlockRef<'c, 'c>,
+    /// Reference to the memory limit block.
+    /// This block halts the execution when a memory expansion exceeds the configured limit.
+    pub memory_limit_block: BlockRef<'c, 'c>,
+    /// Reference to the out of gas block.
+    /// This block halts the execution when a memory expansion can't be paid for.
+    pub out_of_gas_block: BlockRef<'c, 'c>,
     /// Reference to the jump table block.
     /// This block receives the PC as an argument and jumps to the block corresponding to that PC,
     /// or reverts in case the destination is not a JUMPDEST.
@@ -53,6 +69,7 @@ impl<'c> OperationCtx<'c> {
         region: &'c Region,
         setup_block: &'c Block<'c>,
         program: &'c Program,
+        spec_id: SpecId,
     ) -> Result<Self, CodegenError> {
         let location = Location::unknown(context);
         let ptr_type = pointer(context, 0);
@@ -63,21 +80,51 @@ impl<'c> OperationCtx<'c> {
 
         // Append setup code to be run at the start
         generate_stack_setup_code(context, module, setup_block)?;
-        generate_memory_setup_code(context, module, setup_block)?;
+        generate_memory_setup_code(context, syscall_ctx, module, setup_block)?;
         generate_calldata_setup_code(context, syscall_ctx, module, setup_block)?;
+        generate_frame_setup_code(context, syscall_ctx, module, setup_block)?;
         generate_gas_counter_setup_code(context, module, setup_block, initial_gas)?;
 
         syscall::mlir::declare_symbols(context, module);
 
         // Generate helper blocks
-        let revert_block = region.append_block(generate_revert_block(context, syscall_ctx)?);
+        let revert_block = region.append_block(generate_revert_block(
+            context,
+            syscall_ctx,
+            ExitStatusCode::Error,
+        )?);
+        let static_call_violation_block = region.append_block(generate_revert_block(
+            context,
+            syscall_ctx,
+            ExitStatusCode::StaticCallViolation,
+        )?);
+        let out_of_offset_block = region.append_block(generate_revert_block(
+            context,
+            syscall_ctx,
+            ExitStatusCode::OutOfOffset,
+        )?);
+        let memory_limit_block = region.append_block(generate_revert_block(
+            context,
+            syscall_ctx,
+            ExitStatusCode::MemoryLimit,
+        )?);
+        let out_of_gas_block = region.append_block(generate_revert_block(
+            context,
+            syscall_ctx,
+            ExitStatusCode::OutOfGas,
+        )?);
         let jumptable_block = region.append_block(create_jumptable_landing_block(context));
 
         let op_ctx = OperationCtx {
             mlir_context: context,
             program,
+            spec_id,
             syscall_ctx,
             revert_block,
+            static_call_violation_block,
+            out_of_offset_block,
+            memory_limit_block,
+            out_of_gas_block,
             jumptable_block,
             jumpdest_blocks: Default::default(),
         };
@@ -285,6 +332,7 @@ fn generate_stack_setup_code<'c>(
 
 fn generate_memory_setup_code<'c>(
     context: &'c MeliorContext,
+    syscall_ctx: Value<'c, 'c>,
     module: &'c Module,
     block: &'c Block<'c>,
 ) -> Result<(), CodegenError> {
@@ -338,6 +386,27 @@ fn generate_memory_setup_code<'c>(
     ));
     assert!(res.verify());
 
+    // The memory never moves, so its pointer is only loaded once. A failed reservation
+    // halts the frame, which is checked right after the setup code.
+    let memory_ptr_value =
+        syscall::mlir::get_memory_ptr_syscall(context, syscall_ctx, block, location)?;
+    let memory_ptr_ptr = block
+        .append_operation(llvm_mlir::addressof(
+            context,
+            MEMORY_PTR_GLOBAL,
+            ptr_type,
+            location,
+        ))
+        .result(0)?;
+    let res = block.append_operation(llvm::store(
+        context,
+        memory_ptr_value,
+        memory_ptr_ptr.into(),
+        location,
+        LoadStoreOptions::default(),
+    ));
+    assert!(res.verify());
+
     Ok(())
 }
 
@@ -413,6 +482,83 @@ fn generate_calldata_setup_code<'c>(
     Ok(())
 }
 
+/// Loads the properties of the frame that the generated code reads into globals.
+///
+/// They are read on every run instead of being bound to a frame when the code is compiled,
+/// so a compiled program can be reused by any frame running the same code.
+fn generate_frame_setup_code<'c>(
+    context: &'c MeliorContext,
+    syscall_ctx: Value<'c, 'c>,
+    module: &'c Module,
+    block: &'c Block<'c>,
+) -> Result<(), CodegenError> {
+    let location = Location::unknown(context);
+    let ptr_type = pointer(context, 0);
+    let uint1 = IntegerType::new(context, 1).into();
+    let uint64 = IntegerType::new(context, 64).into();
+
+    // Declare globals
+    let body = module.body();
+    let res = body.append_operation(llvm_mlir::global(
+        context,
+        CONTEXT_IS_STATIC_GLOBAL,
+        uint1,
+        Linkage::Internal,
+        location,
+    ));
+    assert!(res.verify());
+    let res = body.append_operation(llvm_mlir::global(
+        context,
+        MEMORY_LIMIT_GLOBAL,
+        uint64,
+        Linkage::Internal,
+        location,
+    ));
+    assert!(res.verify());
+
+    // Setup CONTEXT_IS_STATIC_GLOBAL
+    let is_static = syscall::mlir::is_static_syscall(context, syscall_ctx, block, location)?;
+    let is_static = block
+        .append_operation(arith::trunci(is_static, uint1, location))
+        .result(0)?;
+    let is_static_ptr = block
+        .append_operation(llvm_mlir::addressof(
+            context,
+            CONTEXT_IS_STATIC_GLOBAL,
+            ptr_type,
+            location,
+        ))
+        .result(0)?;
+    block.append_operation(llvm::store(
+        context,
+        is_static.into(),
+        is_static_ptr.into(),
+        location,
+        LoadStoreOptions::default(),
+    ));
+
+    // Setup MEMORY_LIMIT_GLOBAL
+    let memory_limit =
+        syscall::mlir::get_memory_limit_syscall(context, syscall_ctx, block, location)?;
+    let memory_limit_ptr = block
+        .append_operation(llvm_mlir::addressof(
+            context,
+            MEMORY_LIMIT_GLOBAL,
+            ptr_type,
+            location,
+        ))
+        .result(0)?;
+    block.append_operation(llvm::store(
+        context,
+        memory_limit,
+        memory_limit_ptr.into(),
+        location,
+        LoadStoreOptions::default(),
+    ));
+
+    Ok(())
+}
+
 /// Create the jumptable landing block. This is the main entrypoint
 /// for JUMP and JUMPI operations.
 fn create_jumptable_landing_block(context: &MeliorContext) -> Block {
@@ -421,9 +567,11 @@ fn create_jumptable_landing_block(context: &MeliorContext) -> Block {
     Block::new(&[(uint256.into(), location)])
 }
 
+/// Generates a block that consumes all the remaining gas and exits with the given status.
 pub fn generate_revert_block<'c>(
     context: &'c MeliorContext,
     syscall_ctx: Value<'c, 'c>,
+    exit_status: ExitStatusCode,
 ) -> Result<Block<'c>, CodegenError> {
     let location = Location::unknown(context);
     let uint32 = IntegerType::new(context, 32).into();
@@ -453,7 +601,7 @@ pub fn generate_revert_block<'c>(
     let reason = revert_block
         .append_operation(arith::constant(
             context,
-            integer_constant_from_u8(context, ExitStatusCode::Error.to_u8()).into(),
+            integer_constant_from_u8(context, exit_status.to_u8()).into(),
             location,
         ))
         .result(0)?
@@ -628,12 +776,19 @@ impl<'c> OperationCtx<'c> {
         )
     }
 
-    pub(crate) fn get_gaslimit(
+    pub(crate) fn store_in_gaslimit_ptr(
         &'c self,
         block: &'c Block,
         location: Location<'c>,
-    ) -> Result<Value, CodegenError> {
-        syscall::mlir::get_gaslimit(self.mlir_context, self.syscall_ctx, block, location)
+        gaslimit_ptr: Value<'c, 'c>,
+    ) {
+        syscall::mlir::store_in_gaslimit_ptr(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            location,
+            gaslimit_ptr,
+        )
     }
 
     pub(crate) fn store_in_selfbalance_ptr(
@@ -656,7 +811,7 @@ impl<'c> OperationCtx<'c> {
         block: &'c Block,
         new_size: Value<'c, 'c>,
         location: Location<'c>,
-    ) -> Result<Value, CodegenError> {
+    ) {
         syscall::mlir::extend_memory_syscall(
             self.mlir_context,
             self.syscall_ctx,
@@ -672,7 +827,7 @@ impl<'c> OperationCtx<'c> {
         key: Value<'c, 'c>,
         value: Value<'c, 'c>,
         location: Location<'c>,
-    ) {
+    ) -> Result<Value, CodegenError> {
         syscall::mlir::storage_read_syscall(
             self.mlir_context,
             self.syscall_ctx,
@@ -1127,6 +1282,49 @@ impl<'c> OperationCtx<'c> {
         Ok(result)
     }
 
+    pub(crate) fn access_account_syscall(
+        &'c self,
+        block: &'c Block,
+        address: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value, CodegenError> {
+        syscall::mlir::access_account_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            address,
+            location,
+        )
+    }
+
+    /// Returns the gas cost of accessing an account.
+    ///
+    /// Before Berlin, the cost is the fixed one given by `cost_before_berlin` for the
+    /// program's spec. Since EIP-2929 it depends on whether the account is cold or warm,
+    /// so the account is marked as accessed through a syscall.
+    pub(crate) fn account_access_cost(
+        &'c self,
+        block: &'c Block,
+        address: Value<'c, 'c>,
+        cost_before_berlin: fn(SpecId) -> i64,
+        location: Location<'c>,
+    ) -> Result<Value, CodegenError> {
+        let spec_id = self.spec_id;
+        if spec_id.is_enabled_in(SpecId::Berlin) {
+            self.access_account_syscall(block, address, location)
+        } else {
+            let uint64 = IntegerType::new(self.mlir_context, 64).into();
+            let gas_cost = block
+                .append_operation(arith::constant(
+                    self.mlir_context,
+                    IntegerAttribute::new(uint64, cost_before_berlin(spec_id)).into(),
+                    location,
+                ))
+                .result(0)?;
+            Ok(gas_cost.into())
+        }
+    }
+
     pub(crate) fn get_code_hash_syscall(
         &'c self,
         block: &'c Block,
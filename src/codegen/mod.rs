@@ -7,6 +7,7 @@ use std::{
 
 use crate::module::MLIRModule;
 use crate::program::Program;
+use crate::spec::SpecId;
 use crate::{context::Session, errors::CodegenError};
 use llvm_sys::{
     core::{
@@ -33,13 +34,17 @@ pub(crate) mod operations;
 mod pass_manager;
 pub use pass_manager::run_pass_manager;
 
-pub fn compile(program: &Program, output_file: impl AsRef<Path>) -> Result<PathBuf, CodegenError> {
+pub fn compile(
+    program: &Program,
+    spec_id: SpecId,
+    output_file: impl AsRef<Path>,
+) -> Result<PathBuf, CodegenError> {
     let context = Context::new();
     let session = Session {
         raw_mlir_path: Some(output_file.as_ref().to_path_buf()),
         ..Default::default()
     };
-    let mlir_module = context.compile(program, session)?;
+    let mlir_module = context.compile(program, spec_id, session)?;
     compile_to_object(&mlir_module, output_file)
 }
 
@@ -267,9 +272,10 @@ pub fn link_binary(
 
 pub fn compile_binary(
     program: &Program,
+    spec_id: SpecId,
     output_file: impl AsRef<Path>,
 ) -> Result<(), CodegenError> {
-    let object_file = compile(program, &output_file)?;
+    let object_file = compile(program, spec_id, &output_file)?;
     link_binary(&[object_file], output_file)?;
     Ok(())
 }
@@ -339,9 +345,10 @@ pub fn get_platform_library_ext() -> &'static str {
 
 pub fn compile_shared_lib(
     program: &Program,
+    spec_id: SpecId,
     output_file: impl AsRef<Path>,
 ) -> Result<(), CodegenError> {
-    let object_file = compile(program, &output_file)?;
+    let object_file = compile(program, spec_id, &output_file)?;
     link_shared_lib(&[object_file], output_file)?;
     Ok(())
 }
@@ -18,16 +18,18 @@ use crate::{
     constants::{gas_cost, CallType, GAS_COUNTER_GLOBAL, MEMORY_PTR_GLOBAL, MEMORY_SIZE_GLOBAL},
     errors::CodegenError,
     program::Operation,
+    spec::SpecId,
     syscall::ExitStatusCode,
     utils::{
-        allocate_and_store_value, check_context_is_not_static, check_if_zero,
-        check_stack_has_at_least, check_stack_has_space_for, compare_values, compute_copy_cost,
-        compute_log_dynamic_gas, constant_value_from_i64, consume_gas, consume_gas_as_value,
-        context_is_static, extend_memory, get_basefee, get_blob_hash_at_index, get_block_number,
-        get_calldata_ptr, get_calldata_size, get_memory_pointer, get_nth_from_stack,
-        get_prevrandao, get_remaining_gas, get_stack_pointer, inc_stack_pointer,
-        integer_constant_from_i64, llvm_mlir, return_empty_result, return_result_from_stack,
-        stack_pop, stack_push, swap_stack_elements,
+        allocate_and_store_value, check_if_zero, check_stack_has_at_least,
+        check_stack_has_space_for, check_syscall_status, compare_values, compute_copy_cost,
+        compute_log_dynamic_gas, compute_required_memory_size, constant_value_from_i64,
+        consume_gas, consume_gas_as_value, context_is_static, ensure_context_is_not_static,
+        extend_memory, get_basefee, get_blob_hash_at_index, get_block_number, get_calldata_ptr,
+        get_calldata_size, get_memory_pointer, get_nth_from_stack, get_prevrandao,
+        get_remaining_gas, get_stack_pointer, inc_stack_pointer, integer_constant_from_i64,
+        llvm_mlir, return_empty_result, return_result_from_stack, stack_pop, stack_push,
+        swap_stack_elements, truncate_to_u32_saturating, truncate_to_u64_saturating,
     },
 };
 
@@ -163,6 +165,7 @@ fn codegen_blockhash<'c, 'r>(
 
     // Syscall loads the hash into the block_number pointer
     op_ctx.get_block_hash_syscall(&ok_block, block_number_ptr, location);
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     let block_hash_value = ok_block
         .append_operation(llvm::load(
@@ -234,6 +237,7 @@ fn codegen_origin<'c, 'r>(
 
     //Get the 20-byte address of the sender of the transaction
     op_ctx.get_origin_syscall(&ok_block, address_ptr, location);
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     let address_value = ok_block
         .append_operation(llvm::load(
@@ -297,6 +301,7 @@ fn codegen_callvalue<'c, 'r>(
         .into();
 
     op_ctx.store_in_callvalue_ptr(&ok_block, location, callvalue_ptr);
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     let callvalue = ok_block
         .append_operation(llvm::load(
@@ -321,7 +326,6 @@ fn codegen_keccak256<'c, 'r>(
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
     let location = Location::unknown(context);
-    let uint32 = IntegerType::new(context, 32);
     let uint64 = IntegerType::new(context, 64);
     let flag = check_stack_has_at_least(context, &start_block, 2)?;
 
@@ -341,21 +345,12 @@ fn codegen_keccak256<'c, 'r>(
     let size = stack_pop(context, &ok_block)?;
 
     //Truncate offset to 32 bits
-    let offset = ok_block
-        .append_operation(arith::trunci(offset, uint32.into(), location))
-        .result(0)?
-        .into();
+    let offset = truncate_to_u32_saturating(context, &ok_block, offset)?;
 
     //Truncate size to 32 bits
-    let size = ok_block
-        .append_operation(arith::trunci(size, uint32.into(), location))
-        .result(0)?
-        .into();
+    let size = truncate_to_u32_saturating(context, &ok_block, size)?;
 
-    let required_size = ok_block
-        .append_operation(arith::addi(offset, size, location))
-        .result(0)?
-        .into();
+    let required_size = compute_required_memory_size(context, &ok_block, offset, size)?;
 
     let memory_access_block = region.append_block(Block::new(&[]));
 
@@ -422,6 +417,7 @@ fn codegen_keccak256<'c, 'r>(
         .into();
 
     op_ctx.keccak256_syscall(&memory_access_block, offset, size, hash_ptr, location);
+    let memory_access_block = check_syscall_status(op_ctx, region, &memory_access_block)?;
 
     let read_value = memory_access_block
         .append_operation(llvm::load(
@@ -467,8 +463,6 @@ fn codegen_calldatacopy<'c, 'r>(
         &[],
         location,
     ));
-
-    let uint32 = IntegerType::new(context, 32);
     let uint8 = IntegerType::new(context, 8);
     let ptr_type = pointer(context, 0);
 
@@ -480,29 +474,14 @@ fn codegen_calldatacopy<'c, 'r>(
     let size = stack_pop(context, &ok_block)?;
 
     // truncate offsets and size to 32 bits
-    let call_data_offset = ok_block
-        .append_operation(arith::trunci(call_data_offset, uint32.into(), location))
-        .result(0)
-        .unwrap()
-        .into();
+    let call_data_offset = truncate_to_u32_saturating(context, &ok_block, call_data_offset)?;
 
-    let dest_offset = ok_block
-        .append_operation(arith::trunci(dest_offset, uint32.into(), location))
-        .result(0)
-        .unwrap()
-        .into();
+    let dest_offset = truncate_to_u32_saturating(context, &ok_block, dest_offset)?;
 
-    let size = ok_block
-        .append_operation(arith::trunci(size, uint32.into(), location))
-        .result(0)
-        .unwrap()
-        .into();
+    let size = truncate_to_u32_saturating(context, &ok_block, size)?;
 
     //required size = des_offset + size
-    let required_memory_size = ok_block
-        .append_operation(arith::addi(dest_offset, size, location))
-        .result(0)?
-        .into();
+    let required_memory_size = compute_required_memory_size(context, &ok_block, dest_offset, size)?;
 
     let continue_memory_block = region.append_block(Block::new(&[]));
     extend_memory(
@@ -548,6 +527,7 @@ fn codegen_calldatacopy<'c, 'r>(
     );
 
     let calldatasize = op_ctx.get_calldata_size_syscall(&continue_memory_block, location)?;
+    let continue_memory_block = check_syscall_status(op_ctx, region, &continue_memory_block)?;
     let offset_bigger_than_size_flag = continue_memory_block
         .append_operation(arith::cmpi(
             context,
@@ -586,6 +566,7 @@ fn codegen_calldatacopy<'c, 'r>(
         .into();
 
     let calldata_ptr = op_ctx.get_calldata_ptr_syscall(&valid_offset_block, location)?;
+    let valid_offset_block = check_syscall_status(op_ctx, region, &valid_offset_block)?;
     let calldata_src = valid_offset_block
         .append_operation(llvm::get_element_ptr_dynamic(
             context,
@@ -640,6 +621,7 @@ fn codegen_calldatasize<'c, 'r>(
     // Get the calldata size using a syscall
     let uint256 = IntegerType::new(context, 256).into();
     let calldatasize = op_ctx.get_calldata_size_syscall(&ok_block, location)?;
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
     let extended_size = ok_block
         .append_operation(arith::extui(calldatasize, uint256, location))
         .result(0)?
@@ -721,7 +703,7 @@ fn codegen_exp<'c, 'r>(
     let dynamic_gas_cost = ok_block
         .append_operation(arith::muli(
             number_of_bytes,
-            constant_value_from_i64(context, &ok_block, 50)?,
+            constant_value_from_i64(context, &ok_block, gas_cost::exp_byte_cost(op_ctx.spec_id))?,
             location,
         ))
         .result(0)?
@@ -2070,6 +2052,7 @@ fn codegen_number<'c, 'r>(
     ));
 
     let block_number = get_block_number(op_ctx, &ok_block)?;
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     stack_push(context, &ok_block, block_number)?;
 
@@ -2139,11 +2122,7 @@ fn codegen_mload<'c, 'r>(
     let offset = stack_pop(context, &ok_block)?;
 
     // Compute required memory size
-    let offset = ok_block
-        .append_operation(arith::trunci(offset, uint32.into(), location))
-        .result(0)
-        .unwrap()
-        .into();
+    let offset = truncate_to_u32_saturating(context, &ok_block, offset)?;
     let value_size = ok_block
         .append_operation(arith::constant(
             context,
@@ -2152,10 +2131,7 @@ fn codegen_mload<'c, 'r>(
         ))
         .result(0)?
         .into();
-    let required_size = ok_block
-        .append_operation(arith::addi(offset, value_size, location))
-        .result(0)?
-        .into();
+    let required_size = compute_required_memory_size(context, &ok_block, offset, value_size)?;
 
     let memory_access_block = region.append_block(Block::new(&[]));
 
@@ -2250,20 +2226,12 @@ fn codegen_sload<'c, 'r>(
 
     // Check there's enough elements in the stack
     let flag = check_stack_has_at_least(context, &start_block, 1)?;
-    //TODO: We have to take into account key warm/cold access
-    // Check there's enough gas
-    let gas_flag = consume_gas(context, &start_block, gas_cost::SLOAD)?;
-
-    let condition = start_block
-        .append_operation(arith::andi(gas_flag, flag, location))
-        .result(0)?
-        .into();
 
     let ok_block = region.append_block(Block::new(&[]));
 
     start_block.append_operation(cf::cond_br(
         context,
-        condition,
+        flag,
         &ok_block,
         &op_ctx.revert_block,
         &[],
@@ -2306,11 +2274,27 @@ fn codegen_sload<'c, 'r>(
         .result(0)?
         .into();
 
-    // storage_read_syscall returns a pointer to the value
-    op_ctx.storage_read_syscall(&ok_block, key_ptr, read_value_ptr, location);
+    // storage_read_syscall writes the value into the pointer and returns the access cost
+    let gas_cost = op_ctx.storage_read_syscall(&ok_block, key_ptr, read_value_ptr, location)?;
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
+
+    // Check there's enough gas for the (warm or cold) access
+    let gas_flag = consume_gas_as_value(context, &ok_block, gas_cost)?;
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
 
     // get the value from the pointer
-    let read_value = ok_block
+    let read_value = end_block
         .append_operation(llvm::load(
             context,
             read_value_ptr,
@@ -2321,9 +2305,9 @@ fn codegen_sload<'c, 'r>(
         .result(0)?
         .into();
 
-    stack_push(context, &ok_block, read_value)?;
+    stack_push(context, &end_block, read_value)?;
 
-    Ok((start_block, ok_block))
+    Ok((start_block, end_block))
 }
 
 fn codegen_sstore<'c, 'r>(
@@ -2336,17 +2320,13 @@ fn codegen_sstore<'c, 'r>(
     let uint64 = IntegerType::new(context, 64).into();
     let ptr_type = pointer(context, 0);
     //Check current context is not static
-    let ok_context_flag = check_context_is_not_static(op_ctx, &start_block)?;
+    let context_ok_block = ensure_context_is_not_static(op_ctx, region, &start_block)?;
     //Check there are enough arguments in stack
-    let ok_stack_flag = check_stack_has_at_least(context, &start_block, 2)?;
+    let ok_stack_flag = check_stack_has_at_least(context, &context_ok_block, 2)?;
     let ok_block = region.append_block(Block::new(&[]));
-    let ok_flag = start_block
-        .append_operation(arith::andi(ok_context_flag, ok_stack_flag, location))
-        .result(0)?
-        .into();
-    start_block.append_operation(cf::cond_br(
+    context_ok_block.append_operation(cf::cond_br(
         context,
-        ok_flag,
+        ok_stack_flag,
         &ok_block,
         &op_ctx.revert_block,
         &[],
@@ -2362,11 +2342,18 @@ fn codegen_sstore<'c, 'r>(
 
     // Write storage and get the gas cost
     let gas_cost = op_ctx.storage_write_syscall(&ok_block, key_ptr, value_ptr, location)?;
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
+    // EIP-2200: SSTORE can't use the gas reserved for the stipend since Istanbul
+    let min_remaining_gas = if op_ctx.spec_id.is_enabled_in(SpecId::Istanbul) {
+        gas_cost::SSTORE_MIN_REMAINING_GAS
+    } else {
+        0
+    };
     let min_remaining_gas = ok_block
         .append_operation(arith::constant(
             context,
-            IntegerAttribute::new(uint64, gas_cost::SSTORE_MIN_REMAINING_GAS).into(),
+            IntegerAttribute::new(uint64, min_remaining_gas).into(),
             location,
         ))
         .result(0)?
@@ -2555,29 +2542,21 @@ fn codegen_balance<'c, 'r>(
     // Check there's enough elements in stack
     let flag = check_stack_has_at_least(context, &start_block, 1)?;
 
-    // Check there's enough gas
-    let gas_flag = consume_gas(context, &start_block, gas_cost::BALANCE)?;
-
-    let condition = start_block
-        .append_operation(arith::andi(gas_flag, flag, location))
-        .result(0)?
-        .into();
-
-    let ok_block = region.append_block(Block::new(&[]));
+    let access_block = region.append_block(Block::new(&[]));
 
     start_block.append_operation(cf::cond_br(
         context,
-        condition,
-        &ok_block,
+        flag,
+        &access_block,
         &op_ctx.revert_block,
         &[],
         &[],
         location,
     ));
 
-    let address = stack_pop(context, &ok_block)?;
+    let address = stack_pop(context, &access_block)?;
 
-    let address_ptr = ok_block
+    let address_ptr = access_block
         .append_operation(llvm::alloca(
             context,
             pointer_size,
@@ -2588,7 +2567,7 @@ fn codegen_balance<'c, 'r>(
         .result(0)?
         .into();
 
-    let res = ok_block.append_operation(llvm::store(
+    let res = access_block.append_operation(llvm::store(
         context,
         address,
         address_ptr,
@@ -2597,6 +2576,24 @@ fn codegen_balance<'c, 'r>(
     ));
     assert!(res.verify());
 
+    // Check there's enough gas for the (warm or cold) account access
+    let gas_cost =
+        op_ctx.account_access_cost(&access_block, address_ptr, gas_cost::balance_cost, location)?;
+    let access_block = check_syscall_status(op_ctx, region, &access_block)?;
+    let gas_flag = consume_gas_as_value(context, &access_block, gas_cost)?;
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    access_block.append_operation(cf::cond_br(
+        context,
+        gas_flag,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
     let balance_ptr = ok_block
         .append_operation(llvm::alloca(
             context,
@@ -2609,6 +2606,7 @@ fn codegen_balance<'c, 'r>(
         .into();
 
     op_ctx.store_in_balance_syscall(&ok_block, address_ptr, balance_ptr, location);
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     // get the value from the pointer
     let balance = ok_block
@@ -3321,11 +3319,7 @@ fn codegen_mstore<'c, 'r>(
     let value = stack_pop(context, &ok_block)?;
 
     // truncate offset to 32 bits
-    let offset = ok_block
-        .append_operation(arith::trunci(offset, uint32.into(), location))
-        .result(0)
-        .unwrap()
-        .into();
+    let offset = truncate_to_u32_saturating(context, &ok_block, offset)?;
 
     let value_width_in_bytes = 32;
     // value_size = 32
@@ -3339,10 +3333,7 @@ fn codegen_mstore<'c, 'r>(
         .into();
 
     // required_size = offset + value_size
-    let required_size = ok_block
-        .append_operation(arith::addi(offset, value_size, location))
-        .result(0)?
-        .into();
+    let required_size = compute_required_memory_size(context, &ok_block, offset, value_size)?;
 
     let memory_access_block = region.append_block(Block::new(&[]));
 
@@ -3456,11 +3447,7 @@ fn codegen_mstore8<'c, 'r>(
         .into();
 
     // truncate offset to 32 bits
-    let offset = ok_block
-        .append_operation(arith::trunci(offset, uint32.into(), location))
-        .result(0)
-        .unwrap()
-        .into();
+    let offset = truncate_to_u32_saturating(context, &ok_block, offset)?;
 
     let value_width_in_bytes = 1;
     // value_size = 1
@@ -3474,10 +3461,7 @@ fn codegen_mstore8<'c, 'r>(
         .into();
 
     // required_size = offset + size
-    let required_size = ok_block
-        .append_operation(arith::addi(offset, value_size, location))
-        .result(0)?
-        .into();
+    let required_size = compute_required_memory_size(context, &ok_block, offset, value_size)?;
 
     let memory_access_block = region.append_block(Block::new(&[]));
 
@@ -3543,7 +3527,6 @@ fn codegen_mcopy<'c, 'r>(
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
     let location = Location::unknown(context);
-    let uint32 = IntegerType::new(context, 32);
     let uint8 = IntegerType::new(context, 8);
     let ptr_type = pointer(context, 0);
 
@@ -3568,32 +3551,17 @@ fn codegen_mcopy<'c, 'r>(
     let size = stack_pop(context, &ok_block)?;
 
     // truncate offset and dest_offset to 32 bits
-    let offset = ok_block
-        .append_operation(arith::trunci(offset, uint32.into(), location))
-        .result(0)?
-        .into();
+    let offset = truncate_to_u32_saturating(context, &ok_block, offset)?;
 
-    let dest_offset = ok_block
-        .append_operation(arith::trunci(dest_offset, uint32.into(), location))
-        .result(0)?
-        .into();
+    let dest_offset = truncate_to_u32_saturating(context, &ok_block, dest_offset)?;
 
-    let size = ok_block
-        .append_operation(arith::trunci(size, uint32.into(), location))
-        .result(0)?
-        .into();
+    let size = truncate_to_u32_saturating(context, &ok_block, size)?;
 
     // required_size = offset + size
-    let src_required_size = ok_block
-        .append_operation(arith::addi(offset, size, location))
-        .result(0)?
-        .into();
+    let src_required_size = compute_required_memory_size(context, &ok_block, offset, size)?;
 
     // dest_required_size = dest_offset + size
-    let dest_required_size = ok_block
-        .append_operation(arith::addi(dest_offset, size, location))
-        .result(0)?
-        .into();
+    let dest_required_size = compute_required_memory_size(context, &ok_block, dest_offset, size)?;
 
     let required_size = ok_block
         .append_operation(arith::maxui(
@@ -3863,26 +3831,21 @@ fn codegen_log<'c, 'r>(
     nth: u8,
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     debug_assert!(nth <= 4);
-    // TODO: check if the current execution context is from a STATICCALL (since Byzantium fork).
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
     let location = Location::unknown(context);
-    let uint32 = IntegerType::new(context, 32);
     let required_elements = 2 + nth;
-    // Check there's enough elements in stack
-    let stack_flag = check_stack_has_at_least(context, &start_block, required_elements.into())?;
     // Check current context is not static
-    let context_flag = check_context_is_not_static(op_ctx, &start_block)?;
-    let ok_flag = start_block
-        .append_operation(arith::andi(context_flag, stack_flag, location))
-        .result(0)?
-        .into();
+    let context_ok_block = ensure_context_is_not_static(op_ctx, region, &start_block)?;
+    // Check there's enough elements in stack
+    let stack_flag =
+        check_stack_has_at_least(context, &context_ok_block, required_elements.into())?;
 
     let ok_block = region.append_block(Block::new(&[]));
 
-    start_block.append_operation(cf::cond_br(
+    context_ok_block.append_operation(cf::cond_br(
         context,
-        ok_flag,
+        stack_flag,
         &ok_block,
         &op_ctx.revert_block,
         &[],
@@ -3893,20 +3856,11 @@ fn codegen_log<'c, 'r>(
     let offset_u256 = stack_pop(context, &ok_block)?;
     let size_u256 = stack_pop(context, &ok_block)?;
 
-    let offset = ok_block
-        .append_operation(arith::trunci(offset_u256, uint32.into(), location))
-        .result(0)?
-        .into();
-    let size = ok_block
-        .append_operation(arith::trunci(size_u256, uint32.into(), location))
-        .result(0)?
-        .into();
+    let offset = truncate_to_u32_saturating(context, &ok_block, offset_u256)?;
+    let size = truncate_to_u32_saturating(context, &ok_block, size_u256)?;
 
     // required_size = offset + value_size
-    let required_size = ok_block
-        .append_operation(arith::addi(offset, size, location))
-        .result(0)?
-        .into();
+    let required_size = compute_required_memory_size(context, &ok_block, offset, size)?;
 
     let log_block = region.append_block(Block::new(&[]));
     let dynamic_gas = compute_log_dynamic_gas(op_ctx, &ok_block, nth, size_u256, location)?;
@@ -3975,6 +3929,7 @@ fn codegen_log<'c, 'r>(
         }
         _ => unreachable!("nth should satisfy 0 <= nth <= 4"),
     }
+    let log_block = check_syscall_status(op_ctx, region, &log_block)?;
 
     Ok((start_block, log_block))
 }
@@ -4010,6 +3965,7 @@ fn codegen_coinbase<'c, 'r>(
     ));
 
     let coinbase_ptr = op_ctx.get_coinbase_ptr_syscall(&ok_block, location)?;
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     let coinbase = ok_block
         .append_operation(llvm::load(
@@ -4089,6 +4045,7 @@ fn codegen_timestamp<'c, 'r>(
         .into();
 
     op_ctx.store_in_timestamp_ptr(&ok_block, location, timestamp_ptr);
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     let timestamp = ok_block
         .append_operation(llvm::load(
@@ -4152,6 +4109,7 @@ fn codegen_gasprice<'c, 'r>(
         .into();
 
     op_ctx.store_in_gasprice_ptr(&ok_block, location, gasprice_ptr);
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     let gasprice = ok_block
         .append_operation(llvm::load(
@@ -4178,18 +4136,32 @@ fn codegen_extcodesize<'c, 'r>(
     let location = Location::unknown(context);
     let uint256 = IntegerType::new(context, 256).into();
     let flag = check_stack_has_at_least(context, &start_block, 1)?;
-    // TODO: handle cold and warm accesses for dynamic gas computation
-    let gas_flag = consume_gas(context, &start_block, gas_cost::EXTCODESIZE_WARM)?;
+    let access_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        flag,
+        &access_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let address = stack_pop(context, &access_block)?;
+    let address_ptr = allocate_and_store_value(op_ctx, &access_block, address, location)?;
+
+    // Check there's enough gas for the (warm or cold) account access
+    let gas_cost =
+        op_ctx.account_access_cost(&access_block, address_ptr, gas_cost::extcode_cost, location)?;
+    let access_block = check_syscall_status(op_ctx, region, &access_block)?;
+    let gas_flag = consume_gas_as_value(context, &access_block, gas_cost)?;
 
-    let condition = start_block
-        .append_operation(arith::andi(gas_flag, flag, location))
-        .result(0)?
-        .into();
     let ok_block = region.append_block(Block::new(&[]));
 
-    start_block.append_operation(cf::cond_br(
+    access_block.append_operation(cf::cond_br(
         context,
-        condition,
+        gas_flag,
         &ok_block,
         &op_ctx.revert_block,
         &[],
@@ -4197,10 +4169,8 @@ fn codegen_extcodesize<'c, 'r>(
         location,
     ));
 
-    let address = stack_pop(context, &ok_block)?;
-    let address_ptr = allocate_and_store_value(op_ctx, &ok_block, address, location)?;
-
     let codesize = op_ctx.get_codesize_from_address_syscall(&ok_block, address_ptr, location)?;
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
     let codesize = ok_block
         .append_operation(arith::extui(codesize, uint256, location))
         .result(0)?
@@ -4236,6 +4206,7 @@ fn codegen_chaind<'c, 'r>(
         location,
     ));
     let chainid = op_ctx.get_chainid_syscall(&ok_block, location)?;
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
     let uint256 = IntegerType::new(context, 256);
     // Convert calldata_size from u32 to u256
     let chainid = ok_block
@@ -4300,6 +4271,7 @@ fn codegen_caller<'c, 'r>(
         .into();
 
     op_ctx.store_in_caller_ptr(&ok_block, location, caller_ptr);
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     let caller = ok_block
         .append_operation(llvm::load(
@@ -4347,6 +4319,7 @@ fn codegen_basefee<'c, 'r>(
     ));
 
     let basefee = get_basefee(op_ctx, &ok_block)?;
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
     stack_push(context, &ok_block, basefee)?;
 
     Ok((start_block, ok_block))
@@ -4436,6 +4409,7 @@ fn codegen_address<'c, 'r>(
     ));
 
     let address_ptr = op_ctx.get_address_ptr_syscall(&ok_block, location)?;
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     let address = ok_block
         .append_operation(llvm::load(
@@ -4476,7 +4450,6 @@ fn codegen_codecopy<'c, 'r>(
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
     let location = Location::unknown(context);
-    let uint32 = IntegerType::new(context, 32);
 
     let flag = check_stack_has_at_least(context, &start_block, 3)?;
 
@@ -4498,25 +4471,13 @@ fn codegen_codecopy<'c, 'r>(
     let offset_u256 = stack_pop(context, &ok_block)?;
     let size_u256 = stack_pop(context, &ok_block)?;
 
-    let offset = ok_block
-        .append_operation(arith::trunci(offset_u256, uint32.into(), location))
-        .result(0)?
-        .into();
+    let offset = truncate_to_u32_saturating(context, &ok_block, offset_u256)?;
 
-    let size = ok_block
-        .append_operation(arith::trunci(size_u256, uint32.into(), location))
-        .result(0)?
-        .into();
+    let size = truncate_to_u32_saturating(context, &ok_block, size_u256)?;
 
-    let dest_offset = ok_block
-        .append_operation(arith::trunci(dest_offset, uint32.into(), location))
-        .result(0)?
-        .into();
+    let dest_offset = truncate_to_u32_saturating(context, &ok_block, dest_offset)?;
 
-    let required_size = ok_block
-        .append_operation(arith::addi(dest_offset, size, location))
-        .result(0)?
-        .into();
+    let required_size = compute_required_memory_size(context, &ok_block, dest_offset, size)?;
 
     // consume 3 * (size + 31) / 32 gas
     let dynamic_gas_cost = compute_copy_cost(op_ctx, &ok_block, size)?;
@@ -4545,6 +4506,7 @@ fn codegen_codecopy<'c, 'r>(
     )?;
 
     op_ctx.copy_code_to_memory_syscall(&copy_block, offset, size, dest_offset, location);
+    let copy_block = check_syscall_status(op_ctx, region, &copy_block)?;
 
     Ok((start_block, copy_block))
 }
@@ -4609,6 +4571,7 @@ fn codegen_selfbalance<'c, 'r>(
         .into();
 
     op_ctx.store_in_selfbalance_ptr(&ok_block, location, selfbalance_ptr);
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     let selfbalance = ok_block
         .append_operation(llvm::load(
@@ -4681,6 +4644,7 @@ fn codegen_blobbasefee<'c, 'r>(
         .into();
 
     op_ctx.store_in_blobbasefee_ptr(&ok_block, location, blob_base_fee_ptr);
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     let blob_base_fee = ok_block
         .append_operation(llvm::load(
@@ -4732,15 +4696,37 @@ fn codegen_gaslimit<'c, 'r>(
         location,
     ));
 
-    let gaslimit = op_ctx.get_gaslimit(&ok_block, location)?;
-
     let uint256 = IntegerType::new(context, 256);
-    let result = ok_block
-        .append_operation(arith::extui(gaslimit, uint256.into(), location))
+    let ptr_type = pointer(context, 0);
+
+    let pointer_size = constant_value_from_i64(context, &ok_block, 1_i64)?;
+
+    let gaslimit_ptr = ok_block
+        .append_operation(llvm::alloca(
+            context,
+            pointer_size,
+            ptr_type,
+            location,
+            AllocaOptions::new().elem_type(Some(TypeAttribute::new(uint256.into()))),
+        ))
         .result(0)?
         .into();
 
-    stack_push(context, &ok_block, result)?;
+    op_ctx.store_in_gaslimit_ptr(&ok_block, location, gaslimit_ptr);
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
+
+    let gaslimit = ok_block
+        .append_operation(llvm::load(
+            context,
+            gaslimit_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, gaslimit)?;
 
     Ok((start_block, ok_block))
 }
@@ -4752,7 +4738,6 @@ fn codegen_extcodecopy<'c, 'r>(
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
     let location = Location::unknown(context);
-    let uint32 = IntegerType::new(context, 32);
 
     let flag = check_stack_has_at_least(context, &start_block, 4)?;
 
@@ -4774,30 +4759,25 @@ fn codegen_extcodecopy<'c, 'r>(
     let offset_u256 = stack_pop(context, &ok_block)?;
     let size_u256 = stack_pop(context, &ok_block)?;
 
-    let offset = ok_block
-        .append_operation(arith::trunci(offset_u256, uint32.into(), location))
-        .result(0)?
-        .into();
+    let offset = truncate_to_u32_saturating(context, &ok_block, offset_u256)?;
 
-    let size = ok_block
-        .append_operation(arith::trunci(size_u256, uint32.into(), location))
-        .result(0)?
-        .into();
+    let size = truncate_to_u32_saturating(context, &ok_block, size_u256)?;
 
-    let dest_offset = ok_block
-        .append_operation(arith::trunci(dest_offset, uint32.into(), location))
-        .result(0)?
-        .into();
+    let dest_offset = truncate_to_u32_saturating(context, &ok_block, dest_offset)?;
 
-    let required_size = ok_block
-        .append_operation(arith::addi(dest_offset, size, location))
-        .result(0)?
-        .into();
+    let required_size = compute_required_memory_size(context, &ok_block, dest_offset, size)?;
 
-    // TODO: compute address access cost (cold and warm accesses)
+    let address_ptr = allocate_and_store_value(op_ctx, &ok_block, address, location)?;
+    let access_cost =
+        op_ctx.account_access_cost(&ok_block, address_ptr, gas_cost::extcode_cost, location)?;
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
-    // consume 3 * (size + 31) / 32 gas
-    let dynamic_gas_cost = compute_copy_cost(op_ctx, &ok_block, size)?;
+    // consume 3 * (size + 31) / 32 gas plus the (warm or cold) account access cost
+    let copy_cost = compute_copy_cost(op_ctx, &ok_block, size)?;
+    let dynamic_gas_cost = ok_block
+        .append_operation(arith::addi(copy_cost, access_cost, location))
+        .result(0)?
+        .into();
     let flag = consume_gas_as_value(context, &ok_block, dynamic_gas_cost)?;
 
     let memory_extension_block = region.append_block(Block::new(&[]));
@@ -4820,10 +4800,9 @@ fn codegen_extcodecopy<'c, 'r>(
         &end_block,
         region,
         required_size,
-        gas_cost::EXTCODECOPY_WARM,
+        0,
     )?;
 
-    let address_ptr = allocate_and_store_value(op_ctx, &end_block, address, location)?;
     op_ctx.copy_ext_code_to_memory_syscall(
         &end_block,
         address_ptr,
@@ -4832,6 +4811,7 @@ fn codegen_extcodecopy<'c, 'r>(
         dest_offset,
         location,
     );
+    let end_block = check_syscall_status(op_ctx, region, &end_block)?;
 
     Ok((start_block, end_block))
 }
@@ -4867,6 +4847,7 @@ fn codegen_prevrandao<'c, 'r>(
     ));
 
     let prevrandao = get_prevrandao(op_ctx, &ok_block)?;
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     stack_push(context, &ok_block, prevrandao)?;
 
@@ -4903,6 +4884,7 @@ fn codegen_blobhash<'c, 'r>(
     let index = stack_pop(context, &ok_block)?;
     let index_ptr = allocate_and_store_value(op_ctx, &ok_block, index, location)?;
     let blobhash = get_blob_hash_at_index(op_ctx, &ok_block, index_ptr)?;
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
     stack_push(context, &ok_block, blobhash)?;
 
     Ok((start_block, ok_block))
@@ -4916,8 +4898,6 @@ fn codegen_call<'c, 'r>(
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
     let location = Location::unknown(context);
-    let uint64 = IntegerType::new(context, 64);
-    let uint32 = IntegerType::new(context, 32);
 
     let nargs = match call_type {
         CallType::Call | CallType::CallCode => 7,
@@ -4937,8 +4917,6 @@ fn codegen_call<'c, 'r>(
         location,
     ));
 
-    //NOTE: Here, we could optimize this case and only perform
-    // the "context_is_static" check for CALL and CALLCODE
     let gas = stack_pop(context, &stack_ok_block)?;
     let address = stack_pop(context, &stack_ok_block)?;
     let value = match call_type {
@@ -4952,66 +4930,52 @@ fn codegen_call<'c, 'r>(
     let ret_offset = stack_pop(context, &stack_ok_block)?;
     let ret_size = stack_pop(context, &stack_ok_block)?;
 
-    // If the current context is static, value must be zero
+    // If the current context is static, CALL can't transfer value.
+    // CALLCODE doesn't change the balances, so it's allowed to send value.
     let ok_block = region.append_block(Block::new(&[]));
-    let ctx_is_static = context_is_static(op_ctx, &stack_ok_block)?;
-    let zero_value = constant_value_from_i64(context, &stack_ok_block, 0)?;
-    let value_is_not_zero = stack_ok_block
-        .append_operation(arith::cmpi(
+    if matches!(call_type, CallType::Call) {
+        let ctx_is_static = context_is_static(op_ctx, &stack_ok_block)?;
+        let zero_value = constant_value_from_i64(context, &stack_ok_block, 0)?;
+        let value_is_not_zero = stack_ok_block
+            .append_operation(arith::cmpi(
+                context,
+                CmpiPredicate::Ne,
+                value,
+                zero_value,
+                location,
+            ))
+            .result(0)?
+            .into();
+        let violation_flag = stack_ok_block
+            .append_operation(arith::andi(ctx_is_static, value_is_not_zero, location))
+            .result(0)?
+            .into();
+        stack_ok_block.append_operation(cf::cond_br(
             context,
-            CmpiPredicate::Ne,
-            value,
-            zero_value,
+            violation_flag,
+            &op_ctx.static_call_violation_block,
+            &ok_block,
+            &[],
+            &[],
             location,
-        ))
-        .result(0)?
-        .into();
-    let revert_flag = stack_ok_block
-        .append_operation(arith::andi(ctx_is_static, value_is_not_zero, location))
-        .result(0)?
-        .into();
-    stack_ok_block.append_operation(cf::cond_br(
-        context,
-        revert_flag,
-        &op_ctx.revert_block,
-        &ok_block,
-        &[],
-        &[],
-        location,
-    ));
+        ));
+    } else {
+        stack_ok_block.append_operation(cf::br(&ok_block, &[], location));
+    }
 
     // Truncate arguments to their corresponding sizes
-    let gas = ok_block
-        .append_operation(arith::trunci(gas, uint64.into(), location))
-        .result(0)?
-        .into();
-    let args_offset = ok_block
-        .append_operation(arith::trunci(args_offset, uint32.into(), location))
-        .result(0)?
-        .into();
-    let args_size = ok_block
-        .append_operation(arith::trunci(args_size, uint32.into(), location))
-        .result(0)?
-        .into();
-    let ret_offset = ok_block
-        .append_operation(arith::trunci(ret_offset, uint32.into(), location))
-        .result(0)?
-        .into();
-    let ret_size = ok_block
-        .append_operation(arith::trunci(ret_size, uint32.into(), location))
-        .result(0)?
-        .into();
+    // Gas over `u64::MAX` means all the available gas
+    let gas = truncate_to_u64_saturating(context, &ok_block, gas)?;
+    let args_offset = truncate_to_u32_saturating(context, &ok_block, args_offset)?;
+    let args_size = truncate_to_u32_saturating(context, &ok_block, args_size)?;
+    let ret_offset = truncate_to_u32_saturating(context, &ok_block, ret_offset)?;
+    let ret_size = truncate_to_u32_saturating(context, &ok_block, ret_size)?;
 
     // Alloc required memory size for both arguments and return value
     let mem_ext_block = region.append_block(Block::new(&[]));
-    let req_arg_mem_size = ok_block
-        .append_operation(arith::addi(args_offset, args_size, location))
-        .result(0)?
-        .into();
-    let req_ret_mem_size = ok_block
-        .append_operation(arith::addi(ret_offset, ret_size, location))
-        .result(0)?
-        .into();
+    let req_arg_mem_size =
+        compute_required_memory_size(context, &ok_block, args_offset, args_size)?;
+    let req_ret_mem_size = compute_required_memory_size(context, &ok_block, ret_offset, ret_size)?;
     let req_mem_size = ok_block
         .append_operation(arith::maxui(req_arg_mem_size, req_ret_mem_size, location))
         .result(0)?
@@ -5040,6 +5004,7 @@ fn codegen_call<'c, 'r>(
         ret_size,
         call_type,
     )?;
+    let finish_block = check_syscall_status(op_ctx, region, &finish_block)?;
 
     // Push return value into stack
     stack_push(context, &finish_block, call_result)?;
@@ -5057,22 +5022,45 @@ fn codegen_extcodehash<'c, 'r>(
     let uint256 = IntegerType::new(context, 256);
 
     let flag = check_stack_has_at_least(context, &start_block, 1)?;
-    let ok_block = region.append_block(Block::new(&[]));
+    let access_block = region.append_block(Block::new(&[]));
 
     start_block.append_operation(cf::cond_br(
         context,
         flag,
-        &ok_block,
+        &access_block,
         &op_ctx.revert_block,
         &[],
         &[],
         location,
     ));
 
-    let address = stack_pop(context, &ok_block)?;
-    let address_ptr = allocate_and_store_value(op_ctx, &ok_block, address, location)?;
+    let address = stack_pop(context, &access_block)?;
+    let address_ptr = allocate_and_store_value(op_ctx, &access_block, address, location)?;
+
+    // Check there's enough gas for the (warm or cold) account access
+    let gas_cost = op_ctx.account_access_cost(
+        &access_block,
+        address_ptr,
+        gas_cost::extcodehash_cost,
+        location,
+    )?;
+    let access_block = check_syscall_status(op_ctx, region, &access_block)?;
+    let gas_flag = consume_gas_as_value(context, &access_block, gas_cost)?;
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    access_block.append_operation(cf::cond_br(
+        context,
+        gas_flag,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
 
     op_ctx.get_code_hash_syscall(&ok_block, address_ptr, location);
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     let code_hash_value = ok_block
         .append_operation(llvm::load(
@@ -5085,8 +5073,6 @@ fn codegen_extcodehash<'c, 'r>(
         .result(0)?
         .into();
 
-    // TODO: add gas consumption (once access lists are implemented)
-
     stack_push(context, &ok_block, code_hash_value)?;
 
     Ok((start_block, ok_block))
@@ -5123,6 +5109,7 @@ fn codegen_returndatasize<'c, 'r>(
     ));
 
     let data_size = op_ctx.get_return_data_size(&ok_block, location)?;
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     // Extend the 32 bits result to 256 bits
     let uint256 = IntegerType::new(context, 256);
@@ -5143,7 +5130,7 @@ fn codegen_returndatacopy<'c, 'r>(
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
     let location = Location::unknown(context);
-    let uint32 = IntegerType::new(context, 32);
+    let uint256 = IntegerType::new(context, 256);
 
     let flag = check_stack_has_at_least(context, &start_block, 3)?;
     let ok_block = region.append_block(Block::new(&[]));
@@ -5163,22 +5150,9 @@ fn codegen_returndatacopy<'c, 'r>(
     let offset = stack_pop(context, &ok_block)?;
     let size = stack_pop(context, &ok_block)?;
 
-    // Truncate values to u32
-    let dest_offset = ok_block
-        .append_operation(arith::trunci(dest_offset, uint32.into(), location))
-        .result(0)?
-        .into();
-    let offset = ok_block
-        .append_operation(arith::trunci(offset, uint32.into(), location))
-        .result(0)?
-        .into();
-    let size = ok_block
-        .append_operation(arith::trunci(size, uint32.into(), location))
-        .result(0)?
-        .into();
-
     //gas_cost = copy_gas_cost + memory_expansion_cost + static_gas_cost
-    let copy_gas_cost = compute_copy_cost(op_ctx, &ok_block, size)?;
+    let size_u32 = truncate_to_u32_saturating(context, &ok_block, size)?;
+    let copy_gas_cost = compute_copy_cost(op_ctx, &ok_block, size_u32)?;
     let copy_gas_cost_flag = consume_gas_as_value(context, &ok_block, copy_gas_cost)?;
     let gas_ok_block = region.append_block(Block::new(&[]));
 
@@ -5192,52 +5166,67 @@ fn codegen_returndatacopy<'c, 'r>(
         location,
     ));
 
-    // Extend memory to required size
-    let req_mem_size = gas_ok_block
-        .append_operation(arith::addi(dest_offset, size, location))
+    // Check that offset + size <= return_data_size before truncating the values (EIP-211)
+    let return_data_size = op_ctx.get_return_data_size(&gas_ok_block, location)?;
+    let gas_ok_block = check_syscall_status(op_ctx, region, &gas_ok_block)?;
+    let return_data_size = gas_ok_block
+        .append_operation(arith::extui(return_data_size, uint256.into(), location))
         .result(0)?
         .into();
-    // Calculate required return_data_size based on provided arguments
     let req_return_data_size = gas_ok_block
         .append_operation(arith::addi(offset, size, location))
         .result(0)?
         .into();
-
-    let ext_mem_block = region.append_block(Block::new(&[]));
-    extend_memory(
-        op_ctx,
+    let no_overflow_flag = compare_values(
+        context,
         &gas_ok_block,
-        &ext_mem_block,
-        region,
-        req_mem_size,
-        gas_cost::RETURNDATACOPY,
+        CmpiPredicate::Uge,
+        req_return_data_size,
+        offset,
     )?;
-
-    //Check that offset + size < return_data_size
-    let end_block = region.append_block(Block::new(&[]));
-    let return_data_size = op_ctx.get_return_data_size(&ext_mem_block, location)?;
-    let req_mem_size_ok = ext_mem_block
-        .append_operation(arith::cmpi(
-            context,
-            arith::CmpiPredicate::Ule,
-            req_return_data_size,
-            return_data_size,
-            location,
-        ))
+    let in_bounds_flag = compare_values(
+        context,
+        &gas_ok_block,
+        CmpiPredicate::Ule,
+        req_return_data_size,
+        return_data_size,
+    )?;
+    let bounds_ok_flag = gas_ok_block
+        .append_operation(arith::andi(no_overflow_flag, in_bounds_flag, location))
         .result(0)?
         .into();
+    let bounds_ok_block = region.append_block(Block::new(&[]));
 
-    ext_mem_block.append_operation(cf::cond_br(
+    gas_ok_block.append_operation(cf::cond_br(
         context,
-        req_mem_size_ok,
-        &end_block,
-        &op_ctx.revert_block,
+        bounds_ok_flag,
+        &bounds_ok_block,
+        &op_ctx.out_of_offset_block,
         &[],
         &[],
         location,
     ));
 
-    op_ctx.copy_return_data_into_memory(&end_block, dest_offset, offset, size, location);
+    // Truncate values to u32
+    let dest_offset = truncate_to_u32_saturating(context, &bounds_ok_block, dest_offset)?;
+    let offset = truncate_to_u32_saturating(context, &bounds_ok_block, offset)?;
+
+    // Extend memory to required size
+    let req_mem_size =
+        compute_required_memory_size(context, &bounds_ok_block, dest_offset, size_u32)?;
+
+    let end_block = region.append_block(Block::new(&[]));
+    extend_memory(
+        op_ctx,
+        &bounds_ok_block,
+        &end_block,
+        region,
+        req_mem_size,
+        gas_cost::RETURNDATACOPY,
+    )?;
+
+    op_ctx.copy_return_data_into_memory(&end_block, dest_offset, offset, size_u32, location);
+    let end_block = check_syscall_status(op_ctx, region, &end_block)?;
 
     Ok((start_block, end_block))
 }
@@ -5256,19 +5245,15 @@ fn codegen_create<'c, 'r>(
     let uint256 = IntegerType::new(context, 256);
     let ptr_type = pointer(context, 0);
 
+    // Check current context is not static
+    let context_ok_block = ensure_context_is_not_static(op_ctx, region, &start_block)?;
     // Check there's enough elements in stack
     let stack_size = if is_create2 { 4 } else { 3 };
-    let stack_flag = check_stack_has_at_least(context, &start_block, stack_size)?;
-    // Check current context is not static
-    let context_flag = check_context_is_not_static(op_ctx, &start_block)?;
-    let ok_flag = start_block
-        .append_operation(arith::andi(context_flag, stack_flag, location))
-        .result(0)?
-        .into();
+    let stack_flag = check_stack_has_at_least(context, &context_ok_block, stack_size)?;
     let ok_block = region.append_block(Block::new(&[]));
-    start_block.append_operation(cf::cond_br(
+    context_ok_block.append_operation(cf::cond_br(
         context,
-        ok_flag,
+        stack_flag,
         &ok_block,
         &op_ctx.revert_block,
         &[],
@@ -5280,20 +5265,12 @@ fn codegen_create<'c, 'r>(
     let offset = stack_pop(context, &ok_block)?;
     let size = stack_pop(context, &ok_block)?;
 
-    let offset_as_u32 = ok_block
-        .append_operation(arith::trunci(offset, uint32.into(), location))
-        .result(0)?
-        .into();
+    let offset_as_u32 = truncate_to_u32_saturating(context, &ok_block, offset)?;
 
-    let size_as_u32 = ok_block
-        .append_operation(arith::trunci(size, uint32.into(), location))
-        .result(0)?
-        .into();
+    let size_as_u32 = truncate_to_u32_saturating(context, &ok_block, size)?;
 
-    let req_mem_size = ok_block
-        .append_operation(arith::addi(offset_as_u32, size_as_u32, location))
-        .result(0)?
-        .into();
+    let req_mem_size =
+        compute_required_memory_size(context, &ok_block, offset_as_u32, size_as_u32)?;
 
     let create_block = region.append_block(Block::new(&[]));
 
@@ -5376,6 +5353,7 @@ fn codegen_create<'c, 'r>(
             location,
         )?
     };
+    let create_block = check_syscall_status(op_ctx, region, &create_block)?;
 
     // Check if the return code is error
     let zero_constant_value = create_block
@@ -5450,20 +5428,17 @@ fn codegen_selfdestruct<'c, 'r>(
     let context = &op_ctx.mlir_context;
     let location = Location::unknown(context);
 
-    let gas_flag = consume_gas(context, &start_block, gas_cost::SELFDESTRUCT)?;
-    let stack_flag = check_stack_has_at_least(context, &start_block, 1)?;
-    let gas_stack_flag = start_block
+    let context_ok_block = ensure_context_is_not_static(op_ctx, region, &start_block)?;
+    let static_gas = gas_cost::selfdestruct_cost(op_ctx.spec_id);
+    let gas_flag = consume_gas(context, &context_ok_block, static_gas)?;
+    let stack_flag = check_stack_has_at_least(context, &context_ok_block, 1)?;
+    let condition = context_ok_block
         .append_operation(arith::andi(gas_flag, stack_flag, location))
         .result(0)?
         .into();
-    let context_flag = check_context_is_not_static(op_ctx, &start_block)?;
-    let condition = start_block
-        .append_operation(arith::andi(gas_stack_flag, context_flag, location))
-        .result(0)?
-        .into();
 
     let ok_block = region.append_block(Block::new(&[]));
-    start_block.append_operation(cf::cond_br(
+    context_ok_block.append_operation(cf::cond_br(
         context,
         condition,
         &ok_block,
@@ -5477,20 +5452,31 @@ fn codegen_selfdestruct<'c, 'r>(
     let address_ptr = allocate_and_store_value(op_ctx, &ok_block, address, location)?;
 
     let gas_cost = op_ctx.selfdestruct_syscall(&ok_block, address_ptr, location)?;
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
     let gas_flag = consume_gas_as_value(context, &ok_block, gas_cost)?;
 
-    let end_block = region.append_block(Block::new(&[]));
+    let return_block = region.append_block(Block::new(&[]));
     ok_block.append_operation(cf::cond_br(
         context,
         gas_flag,
-        &end_block,
+        &return_block,
         &op_ctx.revert_block,
         &[],
         &[],
         location,
     ));
 
-    Ok((start_block, end_block))
+    // SELFDESTRUCT halts the execution
+    return_empty_result(
+        op_ctx,
+        &return_block,
+        ExitStatusCode::SelfDestruct,
+        location,
+    )?;
+
+    let empty_block = region.append_block(Block::new(&[]));
+
+    Ok((start_block, empty_block))
 }
 
 fn codegen_tload<'c, 'r>(
@@ -5549,6 +5535,7 @@ fn codegen_tload<'c, 'r>(
         .into();
 
     op_ctx.transient_storage_read_syscall(&ok_block, key_ptr, read_value_ptr, location);
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     // Load the value pointer
     let read_value = ok_block
@@ -5585,17 +5572,19 @@ fn codegen_tstore<'c, 'r>(
         .result(0)?
         .into();
 
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::TSTORE)?;
+    // Check current context is not static
+    let context_ok_block = ensure_context_is_not_static(op_ctx, region, &start_block)?;
+    let flag = check_stack_has_at_least(context, &context_ok_block, 2)?;
+    let gas_flag = consume_gas(context, &context_ok_block, gas_cost::TSTORE)?;
 
-    let condition = start_block
+    let condition = context_ok_block
         .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
         .into();
 
     let ok_block = region.append_block(Block::new(&[]));
 
-    start_block.append_operation(cf::cond_br(
+    context_ok_block.append_operation(cf::cond_br(
         context,
         condition,
         &ok_block,
@@ -5649,6 +5638,7 @@ fn codegen_tstore<'c, 'r>(
     assert!(res.verify());
 
     op_ctx.transient_storage_write_syscall(&ok_block, key_ptr, value_ptr, location);
+    let ok_block = check_syscall_status(op_ctx, region, &ok_block)?;
 
     Ok((start_block, ok_block))
 }
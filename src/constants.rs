@@ -9,19 +9,40 @@ pub const MEMORY_PTR_GLOBAL: &str = "evm_mlir__memory_ptr";
 pub const MEMORY_SIZE_GLOBAL: &str = "evm_mlir__memory_size";
 pub const CALLDATA_PTR_GLOBAL: &str = "evm_mlir__calldata_ptr";
 pub const CALLDATA_SIZE_GLOBAL: &str = "evm_mlir__calldata_size";
+pub const CONTEXT_IS_STATIC_GLOBAL: &str = "evm_mlir__context_is_static";
+pub const MEMORY_LIMIT_GLOBAL: &str = "evm_mlir__memory_limit";
 pub const MAIN_ENTRYPOINT: &str = "main";
+/// Maximum size of the memory of a frame, the biggest multiple of 32 that fits in 32 bits.
+///
+/// Memory offsets and sizes are passed to the syscalls as `u32`, so memory can't grow past
+/// 4 GiB. Growing the memory this much costs around 3.5e13 gas, which transactions with a
+/// bigger gas limit can pay for. Expanding past the cap halts with an out of gas error
+/// regardless of the gas left, so the outcome is still deterministic.
+pub const MAX_MEMORY_SIZE: u64 = u32::MAX as u64 - 31;
 
 // An empty bytecode has the following Keccak256 hash
 pub const EMPTY_CODE_HASH_STR: &str =
     "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470";
 
 pub const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
-pub const MAX_BLOB_NUMBER_PER_BLOCK: u8 = 0x01;
+// EIP-4844 blob parameters (Cancun)
+pub const GAS_PER_BLOB: u64 = 1 << 17;
+pub const TARGET_BLOB_NUMBER_PER_BLOCK: u8 = 3;
+pub const MAX_BLOB_NUMBER_PER_BLOCK: u8 = 6;
+pub const TARGET_BLOB_GAS_PER_BLOCK: u64 = TARGET_BLOB_NUMBER_PER_BLOCK as u64 * GAS_PER_BLOB;
+pub const MAX_BLOB_GAS_PER_BLOCK: u64 = MAX_BLOB_NUMBER_PER_BLOCK as u64 * GAS_PER_BLOB;
 
 //TODO: Add missing opcodes gas consumption costs
 //  -> This implies refactoring codegen/operations.rs
 /// Contains the gas costs of the EVM instructions
+///
+/// The constants are the costs of the latest spec. The functions taking a [`SpecId`]
+/// give the costs that were repriced by previous hardforks.
+///
+/// [`SpecId`]: crate::spec::SpecId
 pub mod gas_cost {
+    use crate::spec::SpecId;
+
     pub const ADD: i64 = 3;
     pub const MUL: i64 = 5;
     pub const SUB: i64 = 3;
@@ -46,7 +67,6 @@ pub mod gas_cost {
     pub const BYTE: i64 = 3;
     pub const SHL: i64 = 3;
     pub const SAR: i64 = 3;
-    pub const BALANCE: i64 = 100;
     pub const ORIGIN: i64 = 2;
     pub const CALLER: i64 = 2;
     pub const CALLVALUE: i64 = 2;
@@ -67,7 +87,6 @@ pub mod gas_cost {
     pub const MLOAD: i64 = 3;
     pub const MSTORE: i64 = 3;
     pub const MSTORE8: i64 = 3;
-    pub const SLOAD: i64 = 100; // assuming the key is warm for now
     pub const JUMP: i64 = 8;
     pub const JUMPI: i64 = 10;
     pub const PC: i64 = 2;
@@ -85,8 +104,6 @@ pub mod gas_cost {
     pub const LOG: i64 = 375;
     pub const BLOCKHASH: i64 = 20;
     pub const CALL: i64 = 0;
-    pub const EXTCODESIZE_WARM: i64 = 100;
-    pub const EXTCODECOPY_WARM: i64 = 100;
     pub const RETURNDATASIZE: i64 = 2;
     pub const RETURNDATACOPY: i64 = 3;
     pub const ADDRESS: i64 = 2;
@@ -97,6 +114,20 @@ pub mod gas_cost {
     pub const TSTORE: i64 = 100;
     pub const SELFDESTRUCT: i64 = 5_000;
     pub const SELFDESTRUCT_DYNAMIC_GAS: i64 = 25_000;
+    pub const SELFDESTRUCT_REFUND: i64 = 24_000;
+
+    // Storage costs (EIP-2200)
+    pub const SSTORE_SET: i64 = 20_000;
+    pub const SSTORE_RESET: i64 = 5_000;
+    pub const SSTORE_CLEARS_SCHEDULE: i64 = 15_000;
+    /// EIP-3529: refund for clearing a slot, reduced to the reset cost
+    /// of a cold slot plus the cost of adding it to the access list
+    pub const SSTORE_CLEARS_SCHEDULE_LONDON: i64 = 4_800;
+
+    // Access costs (EIP-2929)
+    pub const WARM_STORAGE_READ_COST: i64 = 100;
+    pub const COLD_SLOAD_COST: i64 = 2_100;
+    pub const COLD_ACCOUNT_ACCESS_COST: i64 = 2_600;
 
     pub const MIN_BLOB_GASPRICE: u64 = 1;
     pub const BLOB_GASPRICE_UPDATE_FRACTION: u64 = 3338477;
@@ -108,24 +139,111 @@ pub mod gas_cost {
     // Transaction costs
     pub const TX_BASE_COST: u64 = 21000;
     pub const TX_DATA_COST_PER_NON_ZERO: u64 = 16;
+    /// Cost of a non-zero byte of data before EIP-2028
+    pub const TX_DATA_COST_PER_NON_ZERO_FRONTIER: u64 = 68;
     pub const TX_DATA_COST_PER_ZERO: u64 = 4;
     pub const TX_CREATE_COST: u64 = 32000;
     pub const TX_ACCESS_LIST_ADDRESS_COST: u64 = 2400;
     pub const TX_ACCESS_LIST_STORAGE_KEY_COST: u64 = 1900;
+    /// EIP-3529: the gas refund is capped to the gas used divided by this quotient
+    pub const MAX_REFUND_QUOTIENT: u64 = 5;
+    pub const MAX_REFUND_QUOTIENT_FRONTIER: u64 = 2;
     pub const MAX_CODE_SIZE: usize = 0x6000;
 
+    /// Divisor of the cap on the refund, lowered to a fifth of the gas used in London.
+    pub fn max_refund_quotient(spec_id: SpecId) -> u64 {
+        if spec_id.is_enabled_in(SpecId::London) {
+            MAX_REFUND_QUOTIENT
+        } else {
+            MAX_REFUND_QUOTIENT_FRONTIER
+        }
+    }
+
+    /// Cost of SLOAD before the cold and warm accesses of EIP-2929.
+    pub fn sload_cost(spec_id: SpecId) -> i64 {
+        if spec_id.is_enabled_in(SpecId::Istanbul) {
+            // EIP-1884: Repricing for trie-size-dependent opcodes
+            800
+        } else if spec_id.is_enabled_in(SpecId::Tangerine) {
+            200
+        } else {
+            50
+        }
+    }
+
+    /// Cost of BALANCE before the cold and warm accesses of EIP-2929.
+    pub fn balance_cost(spec_id: SpecId) -> i64 {
+        if spec_id.is_enabled_in(SpecId::Istanbul) {
+            700
+        } else if spec_id.is_enabled_in(SpecId::Tangerine) {
+            400
+        } else {
+            20
+        }
+    }
+
+    /// Cost of EXTCODESIZE and EXTCODECOPY before the cold and warm accesses of EIP-2929.
+    pub fn extcode_cost(spec_id: SpecId) -> i64 {
+        if spec_id.is_enabled_in(SpecId::Tangerine) {
+            700
+        } else {
+            20
+        }
+    }
+
+    /// Cost of EXTCODEHASH before the cold and warm accesses of EIP-2929.
+    pub fn extcodehash_cost(spec_id: SpecId) -> i64 {
+        if spec_id.is_enabled_in(SpecId::Istanbul) {
+            700
+        } else {
+            400
+        }
+    }
+
+    /// Cost of the CALL family before the cold and warm accesses of EIP-2929.
+    pub fn call_cost(spec_id: SpecId) -> i64 {
+        if spec_id.is_enabled_in(SpecId::Tangerine) {
+            700
+        } else {
+            40
+        }
+    }
+
+    /// Cost of each byte of the exponent of EXP, raised by EIP-160.
+    pub fn exp_byte_cost(spec_id: SpecId) -> i64 {
+        if spec_id.is_enabled_in(SpecId::SpuriousDragon) {
+            50
+        } else {
+            10
+        }
+    }
+
+    /// Static cost of SELFDESTRUCT, which was free before EIP-150.
+    pub fn selfdestruct_cost(spec_id: SpecId) -> i64 {
+        if spec_id.is_enabled_in(SpecId::Tangerine) {
+            SELFDESTRUCT
+        } else {
+            0
+        }
+    }
+
     pub fn init_code_cost(init_code_length: usize) -> u64 {
         INIT_WORD_COST as u64 * (init_code_length as u64 + 31) / 32
     }
 
-    pub fn memory_expansion_cost(last_size: u32, new_size: u32) -> i64 {
-        let new_memory_size_word = (new_size + 31) / 32;
-        let new_memory_cost =
-            (new_memory_size_word * new_memory_size_word) / 512 + (3 * new_memory_size_word);
-        let last_memory_size_word = (last_size + 31) / 32;
-        let last_memory_cost =
-            (last_memory_size_word * last_memory_size_word) / 512 + (3 * last_memory_size_word);
-        (new_memory_cost - last_memory_cost).into()
+    /// Returns the biggest memory size, in bytes, whose expansion cost fits in `gas`.
+    pub fn max_memory_size_for_gas(gas: u64) -> u64 {
+        let cost = |words: u128| words * words / 512 + 3 * words;
+        let gas = gas as u128;
+        // Start from the root of `words² / 512 + 3 * words = gas`, then fix the rounding
+        let mut words = (256.0 * ((9.0 + gas as f64 / 128.0).sqrt() - 3.0)) as u128;
+        while words > 0 && cost(words) > gas {
+            words -= 1;
+        }
+        while cost(words + 1) <= gas {
+            words += 1;
+        }
+        (words * 32).min(u64::MAX as u128) as u64
     }
 
     pub fn memory_copy_cost(size: u32) -> i64 {
@@ -152,14 +270,18 @@ pub mod call_opcode {
     pub const REVERT_RETURN_CODE: u8 = 0;
 
     // Gas related constants
-    pub const WARM_MEMORY_ACCESS_COST: u64 = 100;
     pub const NOT_ZERO_VALUE_COST: u64 = 9000;
     pub const EMPTY_CALLEE_COST: u64 = 25000;
     pub const STIPEND_GAS_ADDITION: u64 = 2300;
     pub const GAS_CAP_DIVISION_FACTOR: u64 = 64;
+
+    // Maximum depth of nested calls and creates
+    pub const CALL_STACK_LIMIT: usize = 1024;
 }
 
 pub mod precompiles {
+    use crate::spec::SpecId;
+
     pub const ECRECOVER_COST: u64 = 3000;
     pub const ECRECOVER_ADDRESS: u64 = 0x01;
     pub const SHA2_256_COST: u64 = 60;
@@ -169,7 +291,39 @@ pub mod precompiles {
     pub const IDENTITY_COST: u64 = 15;
     pub const IDENTITY_ADDRESS: u64 = 0x04;
     pub const MODEXP_ADDRESS: u64 = 0x05;
+    pub const ECADD_ADDRESS: u64 = 0x06;
+    pub const ECMUL_ADDRESS: u64 = 0x07;
+    pub const ECPAIRING_ADDRESS: u64 = 0x08;
     pub const BLAKE2F_ADDRESS: u64 = 0x09;
+    pub const POINT_EVALUATION_ADDRESS: u64 = 0x0a;
+
+    /// Returns the addresses holding a precompile in the given spec.
+    ///
+    /// Every active precompile is listed, including the ones that aren't implemented
+    /// yet, so they are all warm from the start of the transaction (EIP-2929).
+    pub fn precompile_addresses(spec_id: SpecId) -> Vec<u64> {
+        let mut addresses = vec![
+            ECRECOVER_ADDRESS,
+            SHA2_256_ADDRESS,
+            RIPEMD_160_ADDRESS,
+            IDENTITY_ADDRESS,
+        ];
+        if spec_id.is_enabled_in(SpecId::Byzantium) {
+            addresses.extend([
+                MODEXP_ADDRESS,
+                ECADD_ADDRESS,
+                ECMUL_ADDRESS,
+                ECPAIRING_ADDRESS,
+            ]);
+        }
+        if spec_id.is_enabled_in(SpecId::Istanbul) {
+            addresses.push(BLAKE2F_ADDRESS);
+        }
+        if spec_id.is_enabled_in(SpecId::Cancun) {
+            addresses.push(POINT_EVALUATION_ADDRESS);
+        }
+        addresses
+    }
 
     pub fn identity_dynamic_cost(len: u64) -> u64 {
         (len + 31) / 32 * 3
@@ -225,4 +379,15 @@ mod tests {
         assert_eq!(gas_cost::exp_dynamic_cost(16777216), 210);
         assert_eq!(gas_cost::exp_dynamic_cost(4294967296), 260);
     }
+
+    #[test]
+    fn test_max_memory_size_for_gas() {
+        assert_eq!(gas_cost::max_memory_size_for_gas(0), 0);
+        assert_eq!(gas_cost::max_memory_size_for_gas(2), 0);
+        assert_eq!(gas_cost::max_memory_size_for_gas(3), 32);
+        // 32 words cost 32 * 32 / 512 + 3 * 32 = 98
+        assert_eq!(gas_cost::max_memory_size_for_gas(97), 992);
+        assert_eq!(gas_cost::max_memory_size_for_gas(98), 1024);
+        assert!(gas_cost::max_memory_size_for_gas(u64::MAX) > MAX_MEMORY_SIZE);
+    }
 }
@@ -35,8 +35,9 @@ use crate::{
     errors::CodegenError,
     module::MLIRModule,
     program::Program,
+    spec::SpecId,
     syscall::ExitStatusCode,
-    utils::return_empty_result,
+    utils::{check_syscall_status, return_empty_result},
 };
 
 #[derive(Debug, Eq, PartialEq)]
@@ -68,7 +69,13 @@ impl Context {
         Self { melior_context }
     }
 
-    pub fn compile(&self, program: &Program, config: Session) -> Result<MLIRModule, CodegenError> {
+    /// Compiles the program with the rules of the given spec.
+    pub fn compile(
+        &self,
+        program: &Program,
+        spec_id: SpecId,
+        config: Session,
+    ) -> Result<MLIRModule, CodegenError> {
         static INITIALIZED: OnceLock<()> = OnceLock::new();
         INITIALIZED.get_or_init(|| unsafe {
             LLVM_InitializeAllTargets();
@@ -107,7 +114,7 @@ impl Context {
 
         let mut melior_module = MeliorModule::from_operation(op).expect("module failed to create");
 
-        compile_program(context, &melior_module, program)?;
+        compile_program(context, &melior_module, program, spec_id)?;
         assert!(melior_module.as_operation().verify());
 
         if let Some(path) = &config.raw_mlir_path {
@@ -204,6 +211,7 @@ fn compile_program(
     context: &MeliorContext,
     module: &MeliorModule,
     program: &Program,
+    spec_id: SpecId,
 ) -> Result<(), CodegenError> {
     let location = Location::unknown(context);
     let ptr_type = pointer(context, 0);
@@ -235,9 +243,17 @@ fn compile_program(
     // PERF: avoid generating unneeded setup blocks
     let setup_block = main_region.append_block(Block::new(&[]));
 
-    let mut op_ctx = OperationCtx::new(context, module, &main_region, &setup_block, program)?;
-
-    let mut last_block = setup_block;
+    let mut op_ctx = OperationCtx::new(
+        context,
+        module,
+        &main_region,
+        &setup_block,
+        program,
+        spec_id,
+    )?;
+
+    // The setup code reads the frame through syscalls too
+    let mut last_block = check_syscall_status(&op_ctx, &main_region, &setup_block)?;
 
     // Generate code for the program
     for op in &op_ctx.program.operations {
@@ -136,37 +136,6 @@ impl Db {
             .collect()
     }
 
-    pub fn commit(&mut self, changes: HashMap<Address, Account>) {
-        for (address, mut account) in changes {
-            let not_modified =
-                !account.is_touched() && !account.is_created() && !account.is_selfdestructed();
-            let created_and_destroyed = account.is_created() && account.is_selfdestructed();
-
-            if created_and_destroyed || not_modified {
-                continue;
-            }
-
-            if account.is_created() {
-                self.store_contract(&account.info);
-            }
-
-            let mut db_account = self
-                .accounts
-                .entry(address)
-                .or_insert_with(DbAccount::empty);
-            db_account.nonce = account.info.nonce;
-            db_account.balance = account.info.balance;
-            db_account.status = AccountStatus::Cold;
-            db_account.bytecode_hash = account.info.code_hash;
-            db_account.storage.extend(
-                account
-                    .storage
-                    .into_iter()
-                    .map(|(key, value)| (key, value.present_value)),
-            );
-        }
-    }
-
     fn store_contract(&mut self, account: &AccountInfo) {
         if !account.has_code() {
             return;
@@ -222,6 +191,10 @@ impl From<DbAccount> for AccountInfo {
     }
 }
 
+/// The state an [`Evm`](crate::Evm) reads from.
+///
+/// Executing transactions requires the database and its error type to be [`Send`], since
+/// deeply nested calls run on their own threads, which read the state through the journal.
 pub trait Database {
     /// The database error type.
     type Error;
@@ -248,10 +221,54 @@ pub trait Database {
     }
 }
 
+/// Applies the state changes resulting from a transaction to the database.
+pub trait DatabaseCommit {
+    /// Commit changes to the database.
+    fn commit(&mut self, changes: HashMap<Address, Account>);
+}
+
 #[derive(Error, Debug, Clone, Hash, PartialEq, Eq)]
 #[error("error on database access")]
 pub struct DatabaseError;
 
+impl DatabaseCommit for Db {
+    fn commit(&mut self, changes: HashMap<Address, Account>) {
+        for (address, mut account) in changes {
+            let not_modified =
+                !account.is_touched() && !account.is_created() && !account.is_selfdestructed();
+            if not_modified {
+                continue;
+            }
+
+            // Selfdestructed accounts are removed along with their storage, whatever the spec.
+            // EIP-6780 only changes which accounts get marked as selfdestructed.
+            if account.is_selfdestructed() {
+                self.accounts.remove(&address);
+                continue;
+            }
+
+            if account.is_created() {
+                self.store_contract(&account.info);
+            }
+
+            let mut db_account = self
+                .accounts
+                .entry(address)
+                .or_insert_with(DbAccount::empty);
+            db_account.nonce = account.info.nonce;
+            db_account.balance = account.info.balance;
+            db_account.status = AccountStatus::Cold;
+            db_account.bytecode_hash = account.info.code_hash;
+            db_account.storage.extend(
+                account
+                    .storage
+                    .into_iter()
+                    .map(|(key, value)| (key, value.present_value)),
+            );
+        }
+    }
+}
+
 impl Database for Db {
     type Error = Infallible;
 
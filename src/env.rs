@@ -1,14 +1,17 @@
 use crate::{
     constants::{
+        call_opcode::CALL_STACK_LIMIT,
         gas_cost::{
             init_code_cost, MAX_CODE_SIZE, TX_ACCESS_LIST_ADDRESS_COST,
             TX_ACCESS_LIST_STORAGE_KEY_COST, TX_BASE_COST, TX_CREATE_COST,
-            TX_DATA_COST_PER_NON_ZERO, TX_DATA_COST_PER_ZERO,
+            TX_DATA_COST_PER_NON_ZERO, TX_DATA_COST_PER_NON_ZERO_FRONTIER, TX_DATA_COST_PER_ZERO,
         },
-        MAX_BLOB_NUMBER_PER_BLOCK, VERSIONED_HASH_VERSION_KZG,
+        GAS_PER_BLOB, MAX_BLOB_NUMBER_PER_BLOCK, MAX_MEMORY_SIZE, VERSIONED_HASH_VERSION_KZG,
     },
+    db::AccountInfo,
     primitives::{Address, Bytes, B256, U256},
-    result::InvalidTransaction,
+    result::{InvalidHeader, InvalidTransaction},
+    spec::SpecId,
     utils::calc_blob_gasprice,
 };
 
@@ -38,12 +41,53 @@ impl Env {
     /// Reference: https://github.com/ethereum/execution-specs/blob/c854868f4abf2ab0c3e8790d4c40607e0d251147/src/ethereum/cancun/fork.py#L332
     pub fn validate_transaction(&mut self) -> Result<(), InvalidTransaction> {
         let is_create = matches!(self.tx.transact_to, TransactTo::Create);
+        let spec_id = self.cfg.spec_id;
 
-        if is_create && self.tx.data.len() > 2 * MAX_CODE_SIZE {
+        if let Some(chain_id) = self.tx.chain_id {
+            if chain_id != self.cfg.chain_id {
+                return Err(InvalidTransaction::InvalidChainId);
+            }
+        }
+        if !self.cfg.disable_block_gas_limit && U256::from(self.tx.gas_limit) > self.block.gas_limit
+        {
+            return Err(InvalidTransaction::CallerGasLimitMoreThanBlock);
+        }
+        if let Some(priority_fee) = self.tx.gas_priority_fee {
+            if priority_fee > self.tx.gas_price {
+                return Err(InvalidTransaction::PriorityFeeGreaterThanMaxFee);
+            }
+        }
+        // EIP-1559: the base fee was introduced in London
+        if spec_id.is_enabled_in(SpecId::London)
+            && !self.cfg.disable_base_fee
+            && self.tx.gas_price < self.block.basefee
+        {
+            return Err(InvalidTransaction::GasPriceLessThanBasefee);
+        }
+        // EIP-3860: Limit and meter initcode
+        if spec_id.is_enabled_in(SpecId::Shanghai)
+            && is_create
+            && self.tx.data.len() > self.cfg.max_initcode_size()
+        {
             return Err(InvalidTransaction::CreateInitCodeSizeLimit);
         }
+        if !spec_id.is_enabled_in(SpecId::Berlin) && !self.tx.access_list.is_empty() {
+            return Err(InvalidTransaction::AccessListNotSupported);
+        }
+        if !spec_id.is_enabled_in(SpecId::Cancun) {
+            if self.tx.max_fee_per_blob_gas.is_some() {
+                return Err(InvalidTransaction::MaxFeePerBlobGasNotSupported);
+            }
+            if !self.tx.blob_hashes.is_empty() {
+                return Err(InvalidTransaction::BlobVersionedHashesNotSupported);
+            }
+        }
         if let Some(max) = self.tx.max_fee_per_blob_gas {
-            let price = self.block.blob_gasprice.unwrap();
+            // A block without a blob gas price has no blob fee market to pay with
+            let price = self
+                .block
+                .blob_gasprice
+                .ok_or(InvalidTransaction::MaxFeePerBlobGasNotSupported)?;
             if U256::from(price) > max {
                 return Err(InvalidTransaction::BlobGasPriceGreaterThanMax);
             }
@@ -66,22 +110,114 @@ impl Env {
                     max: MAX_BLOB_NUMBER_PER_BLOCK as usize,
                 });
             }
+        } else if !self.tx.blob_hashes.is_empty() {
+            // Blobs can't be paid for without a max fee per blob gas
+            return Err(InvalidTransaction::BlobVersionedHashesNotSupported);
         }
         // TODO: check if more validations are needed
         Ok(())
     }
 
+    /// Checks the transaction against the current state of the caller's account.
+    pub fn validate_transaction_against_state(
+        &self,
+        caller_account: &AccountInfo,
+    ) -> Result<(), InvalidTransaction> {
+        // EIP-3607: Reject transactions from senders with deployed code
+        if !self.cfg.disable_eip3607 && caller_account.has_code() {
+            return Err(InvalidTransaction::RejectCallerWithCode);
+        }
+        if caller_account.nonce == u64::MAX {
+            return Err(InvalidTransaction::NonceOverflowInTransaction);
+        }
+        if let Some(tx) = self.tx.nonce.filter(|_| !self.cfg.disable_nonce_check) {
+            let state = caller_account.nonce;
+            if tx > state {
+                return Err(InvalidTransaction::NonceTooHigh { tx, state });
+            }
+            if tx < state {
+                return Err(InvalidTransaction::NonceTooLow { tx, state });
+            }
+        }
+        let max_fee = self
+            .calc_max_fee()
+            .ok_or(InvalidTransaction::OverflowPaymentInTransaction)?;
+        if !self.cfg.disable_balance_check && caller_account.balance < max_fee {
+            return Err(InvalidTransaction::LackOfFundForMaxFee {
+                fee: Box::new(max_fee),
+                balance: Box::new(caller_account.balance),
+            });
+        }
+        Ok(())
+    }
+
+    /// Maximum amount the sender may pay for the transaction, including the value sent.
+    ///
+    /// Returns `None` if it overflows.
+    pub fn calc_max_fee(&self) -> Option<U256> {
+        U256::from(self.tx.gas_limit)
+            .checked_mul(self.tx.gas_price)
+            .and_then(|gas_cost| gas_cost.checked_add(self.tx.value))
+            .and_then(|fee| fee.checked_add(self.calc_max_data_fee()?))
+    }
+
+    /// Price paid by the sender for each unit of gas.
+    ///
+    /// For EIP-1559 transactions this is the base fee plus the priority fee,
+    /// capped at `gas_price` (the max fee per gas).
+    pub fn effective_gas_price(&self) -> U256 {
+        match self.tx.gas_priority_fee {
+            Some(priority_fee) => self
+                .tx
+                .gas_price
+                .min(self.block.basefee.saturating_add(priority_fee)),
+            None => self.tx.gas_price,
+        }
+    }
+
+    /// Blob fee charged to the sender at the block's blob gas price (EIP-4844).
+    ///
+    /// This fee is burned, and isn't refunded even if the transaction doesn't use all its gas.
+    /// It's zero for transactions without a max fee per blob gas, since their blobs are
+    /// rejected by [`Self::validate_transaction`].
+    pub fn calc_data_fee(&self) -> U256 {
+        if self.tx.max_fee_per_blob_gas.is_none() {
+            return U256::zero();
+        }
+        let blob_gasprice = U256::from(self.block.blob_gasprice.unwrap_or_default());
+        blob_gasprice * U256::from(self.tx.get_total_blob_gas())
+    }
+
+    /// Maximum blob fee the sender may pay, used to check it can afford the transaction.
+    ///
+    /// Returns `None` if it overflows.
+    pub fn calc_max_data_fee(&self) -> Option<U256> {
+        let max_fee_per_blob_gas = self.tx.max_fee_per_blob_gas.unwrap_or_default();
+        max_fee_per_blob_gas.checked_mul(U256::from(self.tx.get_total_blob_gas()))
+    }
+
     ///  Calculates the gas that is charged before execution is started.
     fn calculate_intrinsic_cost(&self) -> u64 {
+        let spec_id = self.cfg.spec_id;
+        // EIP-2028: Transaction data gas cost reduction
+        let non_zero_data_cost = if spec_id.is_enabled_in(SpecId::Istanbul) {
+            TX_DATA_COST_PER_NON_ZERO
+        } else {
+            TX_DATA_COST_PER_NON_ZERO_FRONTIER
+        };
         let data_cost = self.tx.data.iter().fold(0, |acc, byte| {
             acc + if *byte == 0 {
                 TX_DATA_COST_PER_ZERO
             } else {
-                TX_DATA_COST_PER_NON_ZERO
+                non_zero_data_cost
             }
         });
         let create_cost = match self.tx.transact_to {
             TransactTo::Call(_) => 0,
+            // EIP-2: creation transactions cost more since Homestead
+            TransactTo::Create if !spec_id.is_enabled_in(SpecId::Homestead) => 0,
+            // EIP-3860: Limit and meter initcode
+            TransactTo::Create if !spec_id.is_enabled_in(SpecId::Shanghai) => TX_CREATE_COST,
             TransactTo::Create => TX_CREATE_COST + init_code_cost(self.tx.data.len()),
         };
         let access_list_cost = self.tx.access_list.iter().fold(0, |acc, (_, keys)| {
@@ -91,12 +227,29 @@ impl Env {
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct CfgEnv {
     // Chain ID of the EVM, it will be compared to the transaction's Chain ID.
     // Chain ID is introduced EIP-155
     pub chain_id: u64,
+    // Hardfork whose rules are used to run the transaction.
+    // Ignored when `chain_config` is set, since the block then selects the hardfork.
+    //
+    // Default: Cancun
+    pub spec_id: SpecId,
+    // Fork schedule of the chain. If some, it takes precedence over `spec_id`:
+    // the hardfork is the one active at the number and timestamp of the block,
+    // and `spec_id` is overwritten with it when the transaction runs.
+    //
+    // Default: None
+    pub chain_config: Option<ChainConfig>,
+    // Maximum size in bytes the memory of a call frame can grow to.
+    // Expanding the memory past it halts with an out of gas error, once the expansion is paid for.
+    // Memory is addressed with 32 bits, so limits over `MAX_MEMORY_SIZE` behave as `MAX_MEMORY_SIZE`.
+    //
+    // Default: MAX_MEMORY_SIZE
+    pub memory_limit: u64,
     // Bytecode that is created with CREATE/CREATE2 is by default analysed and jumptable is created.
     // This is very beneficial for testing and speeds up execution of that bytecode if called multiple times.
     //
@@ -104,10 +257,254 @@ pub struct CfgEnv {
     //pub perf_analyse_created_bytecodes: AnalysisKind,
     // If some it will effects EIP-170: Contract code size limit. Useful to increase this because of tests.
     // By default it is 0x6000 (~25kb).
-    //pub limit_contract_code_size: Option<usize>,
+    pub limit_contract_code_size: Option<usize>,
+    // If some it will effects EIP-3860: Limit and meter initcode.
+    // By default it is twice the contract code size limit.
+    pub limit_initcode_size: Option<usize>,
+    // If some it overrides the maximum depth of nested calls and creates.
+    // By default it is 1024.
+    pub limit_call_depth: Option<usize>,
+    // Skips the check that the caller can pay for the transaction.
+    // The caller is given the funds it lacks instead.
+    pub disable_balance_check: bool,
+    // Skips the check of the transaction nonce against the caller's nonce.
+    pub disable_nonce_check: bool,
+    // Skips the check that the gas price covers the block's base fee.
+    pub disable_base_fee: bool,
+    // Skips the check that the transaction gas limit fits in the block gas limit.
+    pub disable_block_gas_limit: bool,
+    // Skips EIP-3607: Reject transactions from senders with deployed code.
+    pub disable_eip3607: bool,
 }
 
-#[derive(Clone, Debug, Default)]
+impl Default for CfgEnv {
+    fn default() -> Self {
+        Self {
+            chain_id: 0,
+            spec_id: SpecId::default(),
+            chain_config: None,
+            memory_limit: MAX_MEMORY_SIZE,
+            limit_contract_code_size: None,
+            limit_initcode_size: None,
+            limit_call_depth: None,
+            disable_balance_check: false,
+            disable_nonce_check: false,
+            disable_base_fee: false,
+            disable_block_gas_limit: false,
+            disable_eip3607: false,
+        }
+    }
+}
+
+impl CfgEnv {
+    /// Maximum size of the code deployed by a contract creation (EIP-170).
+    pub fn max_code_size(&self) -> usize {
+        self.limit_contract_code_size.unwrap_or(MAX_CODE_SIZE)
+    }
+
+    /// Maximum size of the init code of a contract creation (EIP-3860).
+    pub fn max_initcode_size(&self) -> usize {
+        self.limit_initcode_size.unwrap_or(2 * self.max_code_size())
+    }
+
+    /// Maximum depth of nested calls and creates.
+    pub fn max_call_depth(&self) -> usize {
+        self.limit_call_depth.unwrap_or(CALL_STACK_LIMIT)
+    }
+
+    /// Runs the transactions with the rules of the given chain, also taking its chain id.
+    ///
+    /// From then on, [`Self::spec_id`] is ignored in favor of the chain's fork schedule.
+    pub fn set_chain_config(&mut self, chain_config: ChainConfig) {
+        self.chain_id = chain_config.chain_id;
+        self.chain_config = Some(chain_config);
+    }
+
+    /// Returns the spec whose rules apply to the given block.
+    pub fn spec_id_at(&self, block: &BlockEnv) -> SpecId {
+        match &self.chain_config {
+            Some(chain_config) => chain_config.spec_id_at(block),
+            None => self.spec_id,
+        }
+    }
+}
+
+/// Activation points of the hardforks of a chain.
+///
+/// Forks up to the merge activate at a block number, and later ones at a timestamp.
+/// Forks that aren't scheduled are `None`. The fields follow the `config` section of
+/// geth's genesis files, so with the `serde` feature custom chains can be loaded with
+/// `ChainConfig::from_json`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct ChainConfig {
+    pub chain_id: u64,
+    pub homestead_block: Option<u64>,
+    /// Tangerine Whistle
+    pub eip150_block: Option<u64>,
+    /// Spurious Dragon
+    pub eip158_block: Option<u64>,
+    pub byzantium_block: Option<u64>,
+    pub constantinople_block: Option<u64>,
+    pub petersburg_block: Option<u64>,
+    pub istanbul_block: Option<u64>,
+    pub berlin_block: Option<u64>,
+    pub london_block: Option<u64>,
+    /// First block after the merge
+    pub merge_netsplit_block: Option<u64>,
+    pub shanghai_time: Option<u64>,
+    pub cancun_time: Option<u64>,
+    pub prague_time: Option<u64>,
+}
+
+impl ChainConfig {
+    /// Ethereum mainnet
+    pub fn mainnet() -> Self {
+        Self {
+            chain_id: 1,
+            homestead_block: Some(1_150_000),
+            eip150_block: Some(2_463_000),
+            eip158_block: Some(2_675_000),
+            byzantium_block: Some(4_370_000),
+            constantinople_block: Some(7_280_000),
+            petersburg_block: Some(7_280_000),
+            istanbul_block: Some(9_069_000),
+            berlin_block: Some(12_244_000),
+            london_block: Some(12_965_000),
+            merge_netsplit_block: Some(15_537_394),
+            shanghai_time: Some(1_681_338_455),
+            cancun_time: Some(1_710_338_135),
+            prague_time: Some(1_746_612_311),
+        }
+    }
+
+    /// Sepolia testnet
+    pub fn sepolia() -> Self {
+        Self {
+            chain_id: 11_155_111,
+            merge_netsplit_block: Some(1_735_371),
+            shanghai_time: Some(1_677_557_088),
+            cancun_time: Some(1_706_655_072),
+            prague_time: Some(1_741_159_776),
+            ..Self::all_at_genesis_until(SpecId::London)
+        }
+    }
+
+    /// Holesky testnet, which started after the merge
+    pub fn holesky() -> Self {
+        Self {
+            chain_id: 17_000,
+            shanghai_time: Some(1_696_000_704),
+            cancun_time: Some(1_707_305_664),
+            prague_time: Some(1_740_434_112),
+            ..Self::all_at_genesis_until(SpecId::Merge)
+        }
+    }
+
+    /// Parses the config of a custom chain, given either as a geth genesis file
+    /// or as just its `config` section.
+    ///
+    /// The merge happens at the block reaching `terminalTotalDifficulty`, which can only
+    /// be known up front when it's zero, meaning the chain starts merged. Otherwise the
+    /// config must set `mergeNetsplitBlock`, and it's rejected if it doesn't.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        use serde::de::Error;
+
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        if let Some(config) = value.get_mut("config") {
+            value = config.take();
+        }
+        let terminal_total_difficulty = value.get("terminalTotalDifficulty").cloned();
+        let mut chain_config: Self = serde_json::from_value(value)?;
+
+        if let (None, Some(terminal_total_difficulty)) =
+            (chain_config.merge_netsplit_block, terminal_total_difficulty)
+        {
+            let is_zero = terminal_total_difficulty.as_f64() == Some(0.0)
+                || terminal_total_difficulty
+                    .as_str()
+                    .is_some_and(|difficulty| {
+                        difficulty
+                            .trim_start_matches("0x")
+                            .trim_start_matches('0')
+                            .is_empty()
+                    });
+            if !is_zero {
+                return Err(serde_json::Error::custom(
+                    "terminalTotalDifficulty needs mergeNetsplitBlock to know when the merge happens",
+                ));
+            }
+            chain_config.merge_netsplit_block = Some(0);
+        }
+        Ok(chain_config)
+    }
+
+    /// Returns the spec active in the block with the given number and timestamp.
+    pub fn spec_id(&self, block_number: u64, timestamp: u64) -> SpecId {
+        let by_timestamp = [
+            (self.prague_time, SpecId::Prague),
+            (self.cancun_time, SpecId::Cancun),
+            (self.shanghai_time, SpecId::Shanghai),
+        ];
+        let by_block_number = [
+            (self.merge_netsplit_block, SpecId::Merge),
+            (self.london_block, SpecId::London),
+            (self.berlin_block, SpecId::Berlin),
+            (self.istanbul_block, SpecId::Istanbul),
+            (self.petersburg_block, SpecId::Petersburg),
+            (self.constantinople_block, SpecId::Constantinople),
+            (self.byzantium_block, SpecId::Byzantium),
+            (self.eip158_block, SpecId::SpuriousDragon),
+            (self.eip150_block, SpecId::Tangerine),
+            (self.homestead_block, SpecId::Homestead),
+        ];
+        let is_active = |activation: Option<u64>, current: u64| {
+            activation.is_some_and(|activation| current >= activation)
+        };
+
+        by_timestamp
+            .into_iter()
+            .find(|(activation, _)| is_active(*activation, timestamp))
+            .or_else(|| {
+                by_block_number
+                    .into_iter()
+                    .find(|(activation, _)| is_active(*activation, block_number))
+            })
+            .map_or(SpecId::Frontier, |(_, spec_id)| spec_id)
+    }
+
+    /// Returns the spec active in the given block.
+    pub fn spec_id_at(&self, block: &BlockEnv) -> SpecId {
+        // Numbers that don't fit in 64 bits are past any activation point
+        let saturate = |value: U256| value.try_into().unwrap_or(u64::MAX);
+        self.spec_id(saturate(block.number), saturate(block.timestamp))
+    }
+
+    /// Config with all the block number forks up to `last` activated at genesis.
+    fn all_at_genesis_until(last: SpecId) -> Self {
+        let at_genesis = |spec_id: SpecId| last.is_enabled_in(spec_id).then_some(0);
+        Self {
+            chain_id: 0,
+            homestead_block: at_genesis(SpecId::Homestead),
+            eip150_block: at_genesis(SpecId::Tangerine),
+            eip158_block: at_genesis(SpecId::SpuriousDragon),
+            byzantium_block: at_genesis(SpecId::Byzantium),
+            constantinople_block: at_genesis(SpecId::Constantinople),
+            petersburg_block: at_genesis(SpecId::Petersburg),
+            istanbul_block: at_genesis(SpecId::Istanbul),
+            berlin_block: at_genesis(SpecId::Berlin),
+            london_block: at_genesis(SpecId::London),
+            merge_netsplit_block: at_genesis(SpecId::Merge),
+            shanghai_time: None,
+            cancun_time: None,
+            prague_time: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct BlockEnv {
     /// The number of ancestor blocks of this block (block height).
     pub number: U256,
@@ -117,17 +514,17 @@ pub struct BlockEnv {
     pub coinbase: Address,
     /// The timestamp of the block in seconds since the UNIX epoch.
     pub timestamp: U256,
-    // The gas limit of the block.
-    //pub gas_limit: U256,
+    /// The gas limit of the block.
+    pub gas_limit: U256,
     //
     // The base fee per gas, added in the London upgrade with [EIP-1559].
     //
     // [EIP-1559]: https://eips.ethereum.org/EIPS/eip-1559
     pub basefee: U256,
-    // The difficulty of the block.
-    //
-    // Unused after the Paris (AKA the merge) upgrade, and replaced by `prevrandao`.
-    //pub difficulty: U256,
+    /// The difficulty of the block.
+    ///
+    /// Unused after the Paris (AKA the merge) upgrade, and replaced by `prevrandao`.
+    pub difficulty: U256,
     // The output of the randomness beacon provided by the beacon chain.
     //
     // Replaces `difficulty` after the Paris (AKA the merge) upgrade with [EIP-4399].
@@ -137,7 +534,7 @@ pub struct BlockEnv {
     // [EIP-4399]: https://eips.ethereum.org/EIPS/eip-4399
     pub prevrandao: Option<B256>,
     // Excess blob gas and blob gasprice.
-    // See also [`crate::calc_excess_blob_gas`]
+    // See also [`crate::utils::calc_excess_blob_gas`]
     // and [`calc_blob_gasprice`].
     //
     // Incorporated as part of the Cancun upgrade via [EIP-4844].
@@ -145,9 +542,57 @@ pub struct BlockEnv {
     // [EIP-4844]: https://eips.ethereum.org/EIPS/eip-4844
     pub excess_blob_gas: Option<u64>,
     pub blob_gasprice: Option<u128>,
+    /// Total blob gas used by the transactions in the block.
+    ///
+    /// Incorporated as part of the Cancun upgrade via [EIP-4844].
+    ///
+    /// [EIP-4844]: https://eips.ethereum.org/EIPS/eip-4844
+    pub blob_gas_used: Option<u64>,
+    /// Root of the parent beacon block.
+    ///
+    /// Incorporated as part of the Cancun upgrade via [EIP-4788].
+    ///
+    /// [EIP-4788]: https://eips.ethereum.org/EIPS/eip-4788
+    pub parent_beacon_block_root: Option<B256>,
+}
+
+impl Default for BlockEnv {
+    fn default() -> Self {
+        Self {
+            number: U256::zero(),
+            coinbase: Address::zero(),
+            timestamp: U256::zero(),
+            gas_limit: U256::MAX,
+            basefee: U256::zero(),
+            difficulty: U256::zero(),
+            prevrandao: Some(B256::zero()),
+            excess_blob_gas: Some(0),
+            blob_gasprice: Some(calc_blob_gasprice(0)),
+            blob_gas_used: None,
+            parent_beacon_block_root: None,
+        }
+    }
 }
 
 impl BlockEnv {
+    /// Checks that the fields required by the given spec are set.
+    pub fn validate(&self, spec_id: SpecId) -> Result<(), InvalidHeader> {
+        // Required since the Paris (AKA the merge) upgrade
+        if spec_id.is_enabled_in(SpecId::Merge) && self.prevrandao.is_none() {
+            return Err(InvalidHeader::PrevrandaoNotSet);
+        }
+        // Required since the Cancun upgrade
+        if spec_id.is_enabled_in(SpecId::Cancun) {
+            if self.excess_blob_gas.is_none() {
+                return Err(InvalidHeader::ExcessBlobGasNotSet);
+            }
+            if self.blob_gasprice.is_none() {
+                return Err(InvalidHeader::BlobGasPriceNotSet);
+            }
+        }
+        Ok(())
+    }
+
     pub fn set_blob_base_fee(&mut self, excess_blob_gas: u64) {
         self.excess_blob_gas = Some(excess_blob_gas);
         self.blob_gasprice = Some(calc_blob_gasprice(excess_blob_gas));
@@ -169,17 +614,17 @@ pub struct TxEnv {
     pub value: U256,
     // The data of the transaction.
     pub data: Bytes,
-    // The nonce of the transaction.
-    //
-    // Caution: If set to `None`, then nonce validation against the account's nonce is skipped: [InvalidTransaction::NonceTooHigh] and [InvalidTransaction::NonceTooLow]
-    // pub nonce: Option<u64>,
+    /// The nonce of the transaction.
+    ///
+    /// Caution: If set to `None`, then nonce validation against the account's nonce is skipped: [InvalidTransaction::NonceTooHigh] and [InvalidTransaction::NonceTooLow]
+    pub nonce: Option<u64>,
 
-    // The chain ID of the transaction. If set to `None`, no checks are performed.
-    //
-    // Incorporated as part of the Spurious Dragon upgrade via [EIP-155].
-    //
-    // [EIP-155]: https://eips.ethereum.org/EIPS/eip-155
-    // pub chain_id: Option<u64>,
+    /// The chain ID of the transaction. If set to `None`, no checks are performed.
+    ///
+    /// Incorporated as part of the Spurious Dragon upgrade via [EIP-155].
+    ///
+    /// [EIP-155]: https://eips.ethereum.org/EIPS/eip-155
+    pub chain_id: Option<u64>,
 
     // A list of addresses and storage keys that the transaction plans to access.
     //
@@ -188,12 +633,15 @@ pub struct TxEnv {
     // [EIP-2930]: https://eips.ethereum.org/EIPS/eip-2930
     pub access_list: Vec<(Address, Vec<U256>)>,
 
-    // The priority fee per gas.
-    //
-    // Incorporated as part of the London upgrade via [EIP-1559].
-    //
-    // [EIP-1559]: https://eips.ethereum.org/EIPS/eip-1559
-    // pub gas_priority_fee: Option<U256>,
+    /// The priority fee per gas.
+    ///
+    /// When set, the transaction uses the EIP-1559 fee model and [`Self::gas_price`]
+    /// is the max fee per gas.
+    ///
+    /// Incorporated as part of the London upgrade via [EIP-1559].
+    ///
+    /// [EIP-1559]: https://eips.ethereum.org/EIPS/eip-1559
+    pub gas_priority_fee: Option<U256>,
 
     // The list of blob versioned hashes. Per EIP there should be at least
     // one blob present if [`Self::max_fee_per_blob_gas`] is `Some`.
@@ -217,12 +665,12 @@ impl Default for TxEnv {
             // TODO: we are using signed comparison for the gas counter
             gas_limit: i64::MAX as _,
             gas_price: U256::zero(),
-            // gas_priority_fee: None,
+            gas_priority_fee: None,
             transact_to: TransactTo::Call(Address::zero()),
             value: U256::zero(),
             data: Bytes::new(),
-            // chain_id: None,
-            // nonce: None,
+            chain_id: None,
+            nonce: None,
             access_list: Vec::new(),
             blob_hashes: Vec::new(),
             max_fee_per_blob_gas: None,
@@ -247,4 +695,9 @@ impl TxEnv {
             TransactTo::Create => Address::zero(),
         }
     }
+
+    /// Total blob gas used by the transaction's blobs (EIP-4844).
+    pub fn get_total_blob_gas(&self) -> u64 {
+        GAS_PER_BLOB * self.blob_hashes.len() as u64
+    }
 }
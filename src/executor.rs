@@ -1,11 +1,36 @@
+use std::{
+    collections::HashMap,
+    fmt, io,
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Mutex, PoisonError},
+};
+
 use melior::ExecutionEngine;
+use sha3::{Digest, Keccak256};
 
 use crate::{
     constants::MAIN_ENTRYPOINT,
+    context::Context,
+    db::Database,
+    errors::CodegenError,
     module::MLIRModule,
+    primitives::B256,
+    program::Program,
+    spec::SpecId,
     syscall::{MainFunc, SyscallContext},
 };
 
+/// Number of nested frames that share a single native stack segment
+const FRAMES_PER_STACK_SEGMENT: usize = 16;
+/// Size of the native stack backing each segment of nested frames
+const STACK_SEGMENT_SIZE: usize = 32 * 1024 * 1024;
+/// Maximum number of idle segment threads kept around for reuse
+const MAX_POOLED_SEGMENTS: usize = 64;
+/// Number of idle executors kept by an [`ExecutorCache`] unless told otherwise
+pub const DEFAULT_EXECUTOR_CACHE_CAPACITY: usize = 256;
+
+type ExecutorKey = (B256, SpecId);
+
 #[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum OptLevel {
     None = 0,
@@ -19,22 +44,318 @@ pub struct Executor {
     engine: ExecutionEngine,
 }
 
+// The engine is only used by one frame at a time, and frames on other stack segments
+// run while the thread that sent them there is blocked.
+unsafe impl Send for Executor {}
+
 impl Executor {
-    pub fn new(module: &MLIRModule, syscall_ctx: &SyscallContext, opt_level: OptLevel) -> Self {
+    pub fn new<DB>(
+        module: &MLIRModule,
+        syscall_ctx: &SyscallContext<DB>,
+        opt_level: OptLevel,
+    ) -> Self
+    where
+        DB: Database + Send,
+        DB::Error: Send,
+    {
         let engine = ExecutionEngine::new(module.module(), opt_level as usize, &[], false);
         syscall_ctx.register_symbols(&engine);
         Self { engine }
     }
 
-    pub fn execute(&self, context: &mut SyscallContext, initial_gas: u64) -> u8 {
-        let main_fn: MainFunc = self.get_main_entrypoint();
+    pub fn execute<DB: Database>(&self, context: &mut SyscallContext<DB>, initial_gas: u64) -> u8 {
+        let main_fn: MainFunc<DB> = self.get_main_entrypoint();
 
         main_fn(context, initial_gas)
     }
 
-    fn get_main_entrypoint(&self) -> MainFunc {
+    fn get_main_entrypoint<DB: Database>(&self) -> MainFunc<DB> {
         let function_name = format!("_mlir_ciface_{MAIN_ENTRYPOINT}");
         let fptr = self.engine.lookup(&function_name);
         unsafe { std::mem::transmute(fptr) }
     }
 }
+
+/// Executors of the code run so far, reused by later frames running the same code.
+///
+/// Executors are keyed by the hash of the code and the spec it was compiled with. The generated
+/// code keeps the state of the frame running it in globals, so an executor is taken out of the
+/// cache while it runs: a nested frame running the same code compiles one of its own, and both
+/// are kept once the frames finish.
+///
+/// At most `capacity` idle executors are kept. Once it's reached, the ones of the least
+/// recently used code are dropped first.
+pub struct ExecutorCache {
+    context: Mutex<Context>,
+    executors: Mutex<ExecutorPool<Executor>>,
+    capacity: usize,
+}
+
+impl Default for ExecutorCache {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_EXECUTOR_CACHE_CAPACITY)
+    }
+}
+
+impl ExecutorCache {
+    /// Creates a cache keeping at most `capacity` idle executors.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            context: Default::default(),
+            executors: Default::default(),
+            capacity,
+        }
+    }
+
+    /// Drops all the idle executors.
+    pub fn clear(&self) {
+        *self
+            .executors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Default::default();
+    }
+
+    /// Runs the code of the frame with the spec of its environment, compiling it if there's
+    /// no executor available for it.
+    pub fn execute<DB>(
+        &self,
+        syscall_ctx: &mut SyscallContext<DB>,
+        initial_gas: u64,
+    ) -> Result<u8, CodegenError>
+    where
+        DB: Database + Send,
+        DB::Error: Send,
+    {
+        let bytecode = &syscall_ctx.call_frame.bytecode;
+        let spec_id = syscall_ctx.env.cfg.spec_id;
+        let key = (B256::from_slice(&Keccak256::digest(bytecode)), spec_id);
+
+        let cached = self
+            .executors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take(&key);
+        let executor = match cached {
+            Some(executor) => executor,
+            None => {
+                let program = Program::from_bytecode(bytecode, spec_id);
+                let context = self.context.lock().unwrap_or_else(PoisonError::into_inner);
+                let module = context.compile(&program, spec_id, Default::default())?;
+                Executor::new(&module, syscall_ctx, OptLevel::Aggressive)
+            }
+        };
+
+        let exit_status = executor.execute(syscall_ctx, initial_gas);
+        self.executors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .put(key, executor, self.capacity);
+        Ok(exit_status)
+    }
+}
+
+/// Idle values grouped by key, along with when each key was last used.
+struct ExecutorPool<T> {
+    entries: HashMap<ExecutorKey, (u64, Vec<T>)>,
+    len: usize,
+    clock: u64,
+}
+
+impl<T> Default for ExecutorPool<T> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            len: 0,
+            clock: 0,
+        }
+    }
+}
+
+impl<T> ExecutorPool<T> {
+    fn take(&mut self, key: &ExecutorKey) -> Option<T> {
+        let (_, values) = self.entries.get_mut(key)?;
+        let value = values.pop()?;
+        if values.is_empty() {
+            self.entries.remove(key);
+        }
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Adds a value, dropping the ones of the least recently used keys to stay within
+    /// `capacity`.
+    fn put(&mut self, key: ExecutorKey, value: T, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        while self.len >= capacity {
+            self.evict_least_recently_used();
+        }
+        self.clock += 1;
+        let (last_used, values) = self.entries.entry(key).or_insert_with(|| (0, Vec::new()));
+        *last_used = self.clock;
+        values.push(value);
+        self.len += 1;
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let least_recently_used = self
+            .entries
+            .iter()
+            .min_by_key(|(_, (last_used, _))| *last_used)
+            .map(|(key, _)| *key);
+        if let Some(key) = least_recently_used {
+            self.take(&key);
+        }
+    }
+}
+
+impl fmt::Debug for ExecutorCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExecutorCache").finish_non_exhaustive()
+    }
+}
+
+static SEGMENT_POOL: Mutex<Vec<StackSegment>> = Mutex::new(Vec::new());
+
+type SegmentJob = Box<dyn FnOnce() + Send>;
+
+/// A thread with its own guarded stack, running the frames of a segment sent to it.
+///
+/// Idle segments are pooled, so nested frames don't spawn a thread on every call.
+struct StackSegment {
+    jobs: mpsc::Sender<SegmentJob>,
+}
+
+impl StackSegment {
+    fn spawn() -> io::Result<Self> {
+        let (jobs, receiver) = mpsc::channel::<SegmentJob>();
+        std::thread::Builder::new()
+            .name("evm_mlir-stack-segment".into())
+            .stack_size(STACK_SEGMENT_SIZE)
+            .spawn(move || receiver.into_iter().for_each(|job| job()))?;
+        Ok(Self { jobs })
+    }
+
+    /// Takes an idle segment from the pool, or spawns a new one if there's none.
+    fn acquire() -> io::Result<Self> {
+        let pooled = SEGMENT_POOL
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+        match pooled {
+            Some(segment) => Ok(segment),
+            None => Self::spawn(),
+        }
+    }
+
+    /// Gives the segment back to the pool. Segments that don't fit in it exit.
+    fn release(self) {
+        let mut pool = SEGMENT_POOL.lock().unwrap_or_else(PoisonError::into_inner);
+        if pool.len() < MAX_POOLED_SEGMENTS {
+            pool.push(self);
+        }
+    }
+
+    /// Runs `f` on the segment's thread, blocking until it's done.
+    ///
+    /// A panic in `f` is caught and returned, so the segment can still be reused.
+    fn run<R: Send>(&self, f: impl FnOnce() -> R + Send) -> io::Result<std::thread::Result<R>> {
+        let (result_sender, result_receiver) = mpsc::sync_channel(1);
+        let job: Box<dyn FnOnce() + Send + '_> = Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(f));
+            let _ = result_sender.send(result);
+        });
+        // SAFETY: the job is either dropped or run to completion before this function
+        // returns, since it holds the sender of the result we block on below. So the
+        // borrows it captures outlive it.
+        let job: SegmentJob = unsafe { std::mem::transmute(job) };
+        let segment_exited = || io::Error::other("stack segment thread exited");
+        self.jobs.send(job).map_err(|_| segment_exited())?;
+        result_receiver.recv().map_err(|_| segment_exited())
+    }
+}
+
+/// Runs `f` for a frame at the given call depth.
+///
+/// Nested frames are grouped in segments of [`FRAMES_PER_STACK_SEGMENT`]. The first frame of
+/// each segment runs on a pooled thread with its own guarded stack, so a chain of nested
+/// calls only ever grows the caller's native stack by a single segment.
+///
+/// Fails without running `f` when there's no thread for a new segment and one can't be
+/// spawned.
+pub(crate) fn with_stack_segment<R: Send>(
+    depth: usize,
+    f: impl FnOnce() -> R + Send,
+) -> io::Result<R> {
+    if depth % FRAMES_PER_STACK_SEGMENT != 0 {
+        return Ok(f());
+    }
+
+    let segment = StackSegment::acquire()?;
+    let result = segment.run(f)?;
+    segment.release();
+    Ok(result.unwrap_or_else(|payload| panic::resume_unwind(payload)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> ExecutorKey {
+        (B256::repeat_byte(byte), SpecId::default())
+    }
+
+    #[test]
+    fn pool_evicts_the_least_recently_used_key() {
+        let mut pool = ExecutorPool::default();
+        pool.put(key(1), 1, 2);
+        pool.put(key(2), 2, 2);
+        // Using the first key again makes the second one the least recently used
+        let value = pool.take(&key(1)).unwrap();
+        pool.put(key(1), value, 2);
+
+        pool.put(key(3), 3, 2);
+
+        assert_eq!(pool.len, 2);
+        assert_eq!(pool.take(&key(2)), None);
+        assert_eq!(pool.take(&key(1)), Some(1));
+        assert_eq!(pool.take(&key(3)), Some(3));
+    }
+
+    #[test]
+    fn stack_segments_run_every_frame_on_the_same_thread() {
+        let segment = StackSegment::spawn().unwrap();
+        let mut frames = 0;
+        let mut run_frame = || {
+            segment
+                .run(|| {
+                    frames += 1;
+                    std::thread::current().id()
+                })
+                .unwrap()
+                .unwrap()
+        };
+
+        let first = run_frame();
+        let second = run_frame();
+
+        assert_ne!(first, std::thread::current().id());
+        assert_eq!(first, second);
+        assert_eq!(frames, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "frame panicked")]
+    fn panics_in_stack_segments_reach_the_caller() {
+        let _ = with_stack_segment(FRAMES_PER_STACK_SEGMENT, || panic!("frame panicked"));
+    }
+
+    #[test]
+    fn pool_without_capacity_keeps_nothing() {
+        let mut pool = ExecutorPool::default();
+        pool.put(key(1), 1, 0);
+
+        assert_eq!(pool.take(&key(1)), None);
+    }
+}
@@ -1,13 +1,23 @@
 use crate::{
     constants::EMPTY_CODE_HASH_STR,
-    db::{AccountInfo, Bytecode, Database, Db},
+    db::{AccountInfo, Bytecode, Database},
     primitives::{Address, B256, U256},
+    result::EVMError,
     state::{Account, AccountStatus, EvmStorageSlot},
+    syscall::Log,
 };
 
 use sha3::{Digest, Keccak256};
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 use std::str::FromStr;
+use thiserror::Error;
+
+/// Error of a journal operation that can't be applied to the state.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum JournalError {
+    #[error("account {0:?} doesn't exist")]
+    AccountNotFound(Address),
+}
 
 #[derive(Clone, Default, Debug, PartialEq)]
 pub struct JournalStorageSlot {
@@ -80,22 +90,109 @@ impl From<&JournalAccount> for AccountInfo {
 type AccountState = HashMap<Address, JournalAccount>;
 type ContractState = HashMap<B256, Bytecode>;
 
-#[derive(Default, Debug)]
-pub struct Journal<'a> {
+/// A change made to the journal, holding what is needed to undo it.
+///
+/// Account changes also keep the previous status, since every change can
+/// mark the account as touched.
+#[derive(Clone, Debug, PartialEq)]
+enum JournalEntry {
+    /// An account was created, replacing the previous one (if any)
+    AccountCreated {
+        address: Address,
+        previous: Option<JournalAccount>,
+    },
+    BalanceChanged {
+        address: Address,
+        balance: U256,
+        status: AccountStatus,
+    },
+    NonceChanged {
+        address: Address,
+        nonce: u64,
+        status: AccountStatus,
+    },
+    CodeChanged {
+        address: Address,
+        bytecode_hash: B256,
+        status: AccountStatus,
+    },
+    StorageChanged {
+        address: Address,
+        key: U256,
+        present_value: U256,
+        status: AccountStatus,
+    },
+    StatusChanged {
+        address: Address,
+        status: AccountStatus,
+    },
+    /// The address was added to the accessed addresses (EIP-2929)
+    AccountWarmed { address: Address },
+    /// The storage key was added to the accessed storage keys (EIP-2929)
+    StorageKeyWarmed { address: Address, key: U256 },
+    /// A log was emitted
+    LogAdded,
+    /// A transient storage slot was written (EIP-1153)
+    TransientStorageChanged {
+        address: Address,
+        key: U256,
+        value: U256,
+    },
+    /// The gas refund counter was changed by the given amount
+    RefundChanged { amount: i64 },
+}
+
+/// A point in the journal that changes made afterwards can be reverted to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JournalCheckpoint(usize);
+
+#[derive(Debug)]
+pub struct Journal<'a, DB: Database> {
     accounts: AccountState,
     contracts: ContractState,
     block_hashes: HashMap<U256, B256>,
-    db: Option<&'a mut Db>,
+    /// Addresses accessed during the transaction (EIP-2929)
+    accessed_addresses: HashSet<Address>,
+    /// Storage keys accessed during the transaction (EIP-2929)
+    accessed_storage_keys: HashSet<(Address, U256)>,
+    /// Transient storage, which only lives during the transaction (EIP-1153)
+    transient_storage: HashMap<(Address, U256), U256>,
+    /// Logs emitted during the transaction, in order
+    logs: Vec<Log>,
+    /// Gas refund counter, which may go negative in the middle of the transaction
+    refund: i64,
+    /// Undo log with the changes made since the first checkpoint
+    entries: Vec<JournalEntry>,
+    /// First fatal error hit during the transaction, which aborts it
+    error: Option<EVMError<DB::Error>>,
+    db: Option<&'a mut DB>,
+}
+
+impl<DB: Database> Default for Journal<'_, DB> {
+    fn default() -> Self {
+        Self {
+            accounts: Default::default(),
+            contracts: Default::default(),
+            block_hashes: Default::default(),
+            accessed_addresses: Default::default(),
+            accessed_storage_keys: Default::default(),
+            transient_storage: Default::default(),
+            logs: Default::default(),
+            refund: 0,
+            entries: Default::default(),
+            error: None,
+            db: None,
+        }
+    }
 }
 
-// TODO: Handle unwraps and panics
 // TODO: Improve overall performance
 //  -> Performance is not the focus currently
 //  -> Many copies, clones and Db fetches that may be reduced
 //  -> For the moment we seek for something that works.
 //  -> We can optimize in the future.
-impl<'a> Journal<'a> {
-    pub fn new(db: &'a mut Db) -> Self {
+impl<'a, DB: Database> Journal<'a, DB> {
+    pub fn new(db: &'a mut DB) -> Self {
         Self {
             db: Some(db),
             ..Default::default()
@@ -105,9 +202,18 @@ impl<'a> Journal<'a> {
     /* ACCOUNT HANDLING */
 
     pub fn new_account(&mut self, address: Address, balance: U256) {
-        // TODO: Check if account already exists and return error or panic
         let account = JournalAccount::new_created(balance);
-        self.accounts.insert(address, account);
+        self._insert_account(address, account);
+    }
+
+    /// Inserts an account that didn't exist, without marking it as created, since it only
+    /// receives value and isn't deployed in the transaction.
+    pub fn new_touched_account(&mut self, address: Address, balance: U256) {
+        let account = JournalAccount {
+            status: AccountStatus::Touched,
+            ..JournalAccount::new_created(balance)
+        };
+        self._insert_account(address, account);
     }
 
     pub fn new_contract(&mut self, address: Address, bytecode: Bytecode, balance: U256) {
@@ -122,30 +228,78 @@ impl<'a> Journal<'a> {
             ..Default::default()
         };
 
-        self.accounts.insert(address, account);
+        self._insert_account(address, account);
         self.contracts.insert(hash, bytecode);
     }
 
     pub fn set_balance(&mut self, address: &Address, balance: U256) {
         if let Some(acc) = self._get_account_mut(address) {
+            let entry = JournalEntry::BalanceChanged {
+                address: *address,
+                balance: acc.balance,
+                status: acc.status,
+            };
             acc.balance = balance;
             acc.status |= AccountStatus::Touched;
+            self.entries.push(entry);
         }
     }
 
     pub fn set_nonce(&mut self, address: &Address, nonce: u64) {
         if let Some(acc) = self._get_account_mut(address) {
+            let entry = JournalEntry::NonceChanged {
+                address: *address,
+                nonce: acc.nonce,
+                status: acc.status,
+            };
             acc.nonce = nonce;
             acc.status |= AccountStatus::Touched;
+            self.entries.push(entry);
+        }
+    }
+
+    pub fn set_code(&mut self, address: &Address, bytecode: Bytecode) {
+        let mut hasher = Keccak256::new();
+        hasher.update(&bytecode);
+        let hash = B256::from_slice(&hasher.finalize());
+        if let Some(acc) = self._get_account_mut(address) {
+            let entry = JournalEntry::CodeChanged {
+                address: *address,
+                bytecode_hash: acc.bytecode_hash,
+                status: acc.status,
+            };
+            acc.bytecode_hash = hash;
+            acc.status |= AccountStatus::Touched;
+            self.entries.push(entry);
+            self.contracts.insert(hash, bytecode);
         }
     }
 
     pub fn set_status(&mut self, address: &Address, status: AccountStatus) {
         if let Some(acc) = self._get_account_mut(address) {
+            let entry = JournalEntry::StatusChanged {
+                address: *address,
+                status: acc.status,
+            };
             acc.status |= status;
+            self.entries.push(entry);
         }
     }
 
+    /// Returns whether the account was created in the current transaction
+    pub fn account_is_created(&self, address: &Address) -> bool {
+        self.accounts
+            .get(address)
+            .is_some_and(|acc| acc.status.contains(AccountStatus::Created))
+    }
+
+    /// Returns whether the account was selfdestructed in the current transaction
+    pub fn account_is_selfdestructed(&self, address: &Address) -> bool {
+        self.accounts
+            .get(address)
+            .is_some_and(|acc| acc.status.contains(AccountStatus::SelfDestructed))
+    }
+
     pub fn get_account(&mut self, address: &Address) -> Option<AccountInfo> {
         self._get_account(address).map(AccountInfo::from)
     }
@@ -161,28 +315,58 @@ impl<'a> Journal<'a> {
         }
 
         let hash = acc.bytecode_hash;
-        self.contracts.get(&hash).cloned().unwrap_or(
-            self.db
-                .as_mut()
-                .and_then(|db| db.code_by_hash(hash).ok())
-                .unwrap_or(default),
-        )
+        if let Some(code) = self.contracts.get(&hash) {
+            return code.clone();
+        }
+        let result = self.db.as_mut().map(|db| db.code_by_hash(hash));
+        self._unwrap_db_result(result)
     }
 
     /* WARM COLD HANDLING */
 
     pub fn account_is_warm(&self, address: &Address) -> bool {
-        self.accounts
-            .get(address)
-            .map(|acc| acc.status.contains(AccountStatus::Cold))
-            .unwrap_or(false)
+        self.accessed_addresses.contains(address)
     }
 
+    pub fn key_is_warm(&self, address: &Address, key: &U256) -> bool {
+        self.accessed_storage_keys.contains(&(*address, *key))
+    }
+
+    /// Adds the address to the accessed addresses, returning whether the access was cold
+    pub fn access_account(&mut self, address: &Address) -> bool {
+        let is_cold = self.accessed_addresses.insert(*address);
+        if is_cold {
+            self.entries
+                .push(JournalEntry::AccountWarmed { address: *address });
+        }
+        is_cold
+    }
+
+    /// Adds the storage key to the accessed storage keys, returning whether the access was cold
+    pub fn access_storage_key(&mut self, address: &Address, key: &U256) -> bool {
+        let is_cold = self.accessed_storage_keys.insert((*address, *key));
+        if is_cold {
+            self.entries.push(JournalEntry::StorageKeyWarmed {
+                address: *address,
+                key: *key,
+            });
+        }
+        is_cold
+    }
+
+    /// Loads the account and marks it as warm
     pub fn prefetch_account(&mut self, address: &Address) {
+        self.access_account(address);
         let _ = self._get_account(address);
     }
 
+    /// Loads the account along with the given storage keys, marking all of them as warm
     pub fn prefetch_account_keys(&mut self, address: &Address, keys: &[U256]) {
+        self.prefetch_account(address);
+        for key in keys {
+            self.access_storage_key(address, key);
+        }
+
         if self._get_account(address).is_none() {
             return;
         };
@@ -192,16 +376,12 @@ impl<'a> Journal<'a> {
             .map(|key| (*key, self._fetch_storage_from_db(address, key)))
             .collect();
 
-        let acc = self._get_account_mut(address).unwrap();
-        acc.storage.extend(slots);
-    }
-
-    // We ignore the `EvmStorageSlot::is_cold` attribute
-    pub fn key_is_warm(&self, address: &Address, key: &U256) -> bool {
-        self.accounts
-            .get(address)
-            .and_then(|acc| acc.storage.get(key))
-            .is_some()
+        let Some(acc) = self._get_account_mut(address) else {
+            return;
+        };
+        for (key, slot) in slots {
+            acc.storage.entry(key).or_insert(slot);
+        }
     }
 
     /* STORAGE HANDLING */
@@ -213,24 +393,74 @@ impl<'a> Journal<'a> {
             .storage
             .get(key)
             .cloned()
-            .unwrap_or(self._fetch_storage_from_db(address, key));
-        let acc = self._get_account_mut(address).unwrap();
-        acc.storage.insert(*key, slot.clone()); // Now this key is warm
+            .unwrap_or_else(|| self._fetch_storage_from_db(address, key));
+        let acc = self._get_account_mut(address)?;
+        acc.storage.insert(*key, slot.clone());
         Some(slot)
     }
 
-    pub fn write_storage(&mut self, address: &Address, key: U256, value: U256) {
-        let acc = self._get_account(address).unwrap(); //TODO handle error here
+    pub fn write_storage(
+        &mut self,
+        address: &Address,
+        key: U256,
+        value: U256,
+    ) -> Result<(), JournalError> {
+        let acc = self
+            ._get_account(address)
+            .ok_or(JournalError::AccountNotFound(*address))?;
+        let status = acc.status;
         let mut slot = acc
             .storage
             .get(&key)
             .cloned()
-            .unwrap_or(self._fetch_storage_from_db(address, &key));
+            .unwrap_or_else(|| self._fetch_storage_from_db(address, &key));
 
+        let entry = JournalEntry::StorageChanged {
+            address: *address,
+            key,
+            present_value: slot.present_value,
+            status,
+        };
         slot.present_value = value;
-        let acc = self._get_account_mut(address).unwrap();
-        acc.storage.insert(key, slot.clone());
+        let acc = self
+            ._get_account_mut(address)
+            .ok_or(JournalError::AccountNotFound(*address))?;
+        acc.storage.insert(key, slot);
         acc.status |= AccountStatus::Touched;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /* TRANSIENT STORAGE HANDLING */
+
+    pub fn read_transient_storage(&self, address: &Address, key: &U256) -> U256 {
+        self.transient_storage
+            .get(&(*address, *key))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn write_transient_storage(&mut self, address: &Address, key: U256, value: U256) {
+        let previous = self
+            .transient_storage
+            .insert((*address, key), value)
+            .unwrap_or_default();
+        self.entries.push(JournalEntry::TransientStorageChanged {
+            address: *address,
+            key,
+            value: previous,
+        });
+    }
+
+    /* LOGS */
+
+    pub fn add_log(&mut self, log: Log) {
+        self.logs.push(log);
+        self.entries.push(JournalEntry::LogAdded);
+    }
+
+    pub fn logs(&self) -> &[Log] {
+        &self.logs
     }
 
     /* BLOCK HASH */
@@ -239,17 +469,45 @@ impl<'a> Journal<'a> {
         match self.block_hashes.get(number).cloned() {
             Some(hash) => hash,
             None => {
-                let block_hash = self
-                    .db
-                    .as_mut()
-                    .and_then(|db| db.block_hash(*number).ok())
-                    .unwrap_or_default();
+                let result = self.db.as_mut().map(|db| db.block_hash(*number));
+                let block_hash = self._unwrap_db_result(result);
                 self.block_hashes.insert(*number, block_hash);
                 block_hash
             }
         }
     }
 
+    /* GAS REFUND */
+
+    /// Adds `amount` (which may be negative) to the gas refund counter
+    pub fn record_refund(&mut self, amount: i64) {
+        if amount == 0 {
+            return;
+        }
+        self.refund += amount;
+        self.entries.push(JournalEntry::RefundChanged { amount });
+    }
+
+    /// Returns the gas refund counter
+    pub fn refund(&self) -> i64 {
+        self.refund
+    }
+
+    /* ERROR HANDLING */
+
+    /// Records an error that aborts the transaction. Only the first one is kept.
+    pub fn set_error(&mut self, error: EVMError<DB::Error>) {
+        self.error.get_or_insert(error);
+    }
+
+    /// Takes the error that aborted the transaction, if any.
+    ///
+    /// Database reads that fail return default values, so the execution results
+    /// must be discarded when this returns an error.
+    pub fn take_error(&mut self) -> Result<(), EVMError<DB::Error>> {
+        self.error.take().map_or(Ok(()), Err)
+    }
+
     /* OTHER METHODS */
 
     pub fn into_state(&self) -> HashMap<Address, Account> {
@@ -293,35 +551,121 @@ impl<'a> Journal<'a> {
             .collect()
     }
 
-    pub fn eject_base(&mut self) -> Self {
-        Self {
-            accounts: self.accounts.clone(),
-            contracts: self.contracts.clone(),
-            block_hashes: self.block_hashes.clone(),
-            db: self.db.take(),
+    /* CHECKPOINTS */
+
+    /// Marks the current point in the journal, so that the changes made afterwards
+    /// can be reverted with [`Journal::checkpoint_revert`].
+    pub fn checkpoint(&self) -> JournalCheckpoint {
+        JournalCheckpoint(self.entries.len())
+    }
+
+    /// Keeps the changes made since the checkpoint.
+    ///
+    /// They are still recorded, so they are undone if an enclosing checkpoint is reverted.
+    pub fn checkpoint_commit(&mut self, _checkpoint: JournalCheckpoint) {}
+
+    /// Undoes every change made since the checkpoint, in reverse order.
+    ///
+    /// This includes the addresses and storage keys warmed since then, as EIP-2929 requires.
+    pub fn checkpoint_revert(&mut self, checkpoint: JournalCheckpoint) {
+        let entries = self.entries.split_off(checkpoint.0);
+        for entry in entries.into_iter().rev() {
+            self._undo(entry);
         }
     }
 
-    pub fn extend_from_successful(&mut self, other: Journal<'a>) {
-        self.accounts = other.accounts;
-        self.contracts = other.contracts;
-        self.block_hashes = other.block_hashes;
-        self.db = other.db;
+    /* PRIVATE AUXILIARY METHODS */
+
+    fn _undo(&mut self, entry: JournalEntry) {
+        match entry {
+            JournalEntry::AccountCreated { address, previous } => match previous {
+                Some(account) => {
+                    self.accounts.insert(address, account);
+                }
+                None => {
+                    self.accounts.remove(&address);
+                }
+            },
+            JournalEntry::BalanceChanged {
+                address,
+                balance,
+                status,
+            } => {
+                let acc = self._journaled_account_mut(&address);
+                acc.balance = balance;
+                acc.status = status;
+            }
+            JournalEntry::NonceChanged {
+                address,
+                nonce,
+                status,
+            } => {
+                let acc = self._journaled_account_mut(&address);
+                acc.nonce = nonce;
+                acc.status = status;
+            }
+            JournalEntry::CodeChanged {
+                address,
+                bytecode_hash,
+                status,
+            } => {
+                let acc = self._journaled_account_mut(&address);
+                acc.bytecode_hash = bytecode_hash;
+                acc.status = status;
+            }
+            JournalEntry::StorageChanged {
+                address,
+                key,
+                present_value,
+                status,
+            } => {
+                let acc = self._journaled_account_mut(&address);
+                acc.storage
+                    .get_mut(&key)
+                    .expect("storage changes are only journaled for loaded slots")
+                    .present_value = present_value;
+                acc.status = status;
+            }
+            JournalEntry::StatusChanged { address, status } => {
+                self._journaled_account_mut(&address).status = status;
+            }
+            JournalEntry::AccountWarmed { address } => {
+                self.accessed_addresses.remove(&address);
+            }
+            JournalEntry::StorageKeyWarmed { address, key } => {
+                self.accessed_storage_keys.remove(&(address, key));
+            }
+            JournalEntry::LogAdded => {
+                self.logs.pop();
+            }
+            JournalEntry::TransientStorageChanged {
+                address,
+                key,
+                value,
+            } => {
+                self.transient_storage.insert((address, key), value);
+            }
+            JournalEntry::RefundChanged { amount } => {
+                self.refund -= amount;
+            }
+        }
     }
 
-    pub fn extend_from_reverted(&mut self, other: Journal<'a>) {
-        // TODO: Maybe warm/cold state on both addresses and storage keys should be preserved
-        // but if that's the case, then:
-        // - What happens if the reverted callee context created a new account/storage key?
-        //   -> Should the warm state be preserved? What value should we introduce into the
-        //      self.accounts HashMap? A default one?
-        //
-        // For the moment, we will just discard changes and take the database back
-        self.db = other.db
+    fn _insert_account(&mut self, address: Address, account: JournalAccount) {
+        let previous = self.accounts.insert(address, account);
+        self.entries
+            .push(JournalEntry::AccountCreated { address, previous });
     }
 
     /* PRIVATE AUXILIARY METHODS */
 
+    /// Returns the account a journal entry refers to, which was loaded when the change was made
+    fn _journaled_account_mut(&mut self, address: &Address) -> &mut JournalAccount {
+        self.accounts
+            .get_mut(address)
+            .expect("changes are only journaled for loaded accounts")
+    }
+
     fn _get_account(&mut self, address: &Address) -> Option<&JournalAccount> {
         self._get_account_mut(address).map(|acc| &*acc)
     }
@@ -334,7 +678,13 @@ impl<'a> Journal<'a> {
         let maybe_acc = match self.accounts.entry(*address) {
             Entry::Occupied(e) => Some(e.into_mut()),
             Entry::Vacant(e) => {
-                let acc = db.basic(*address).ok().flatten()?;
+                let acc = match db.basic(*address) {
+                    Ok(acc) => acc?,
+                    Err(err) => {
+                        self.error.get_or_insert(EVMError::Database(err));
+                        return None;
+                    }
+                };
                 let mut acc = JournalAccount::from(acc);
                 acc.status = AccountStatus::Loaded;
                 Some(e.insert(acc))
@@ -345,11 +695,155 @@ impl<'a> Journal<'a> {
     }
 
     fn _fetch_storage_from_db(&mut self, address: &Address, key: &U256) -> JournalStorageSlot {
-        let value = self
-            .db
-            .as_mut()
-            .and_then(|db| db.storage(*address, *key).ok())
-            .unwrap_or_default();
-        JournalStorageSlot::from(value)
+        let result = self.db.as_mut().map(|db| db.storage(*address, *key));
+        JournalStorageSlot::from(self._unwrap_db_result(result))
+    }
+
+    /// Returns the value read from the database, recording the error if the read failed.
+    fn _unwrap_db_result<T: Default>(&mut self, result: Option<Result<T, DB::Error>>) -> T {
+        match result {
+            Some(Ok(value)) => value,
+            Some(Err(err)) => {
+                self.error.get_or_insert(EVMError::Database(err));
+                T::default()
+            }
+            None => T::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Db;
+
+    #[test]
+    fn writing_storage_of_a_missing_account_fails() {
+        let address = Address::from_low_u64_be(1);
+        let mut db = Db::new();
+        let mut journal = Journal::new(&mut db);
+
+        let result = journal.write_storage(&address, U256::from(7), U256::from(3));
+
+        assert_eq!(result, Err(JournalError::AccountNotFound(address)));
+        assert_eq!(journal.read_storage(&address, &U256::from(7)), None);
+    }
+
+    #[test]
+    fn checkpoint_revert_undoes_changes_made_after_it() {
+        let address = Address::from_low_u64_be(1);
+        let key = U256::from(7);
+        let mut db = Db::new();
+        db.set_account(address, 1, U256::from(10), HashMap::new());
+        let mut journal = Journal::new(&mut db);
+
+        journal.set_balance(&address, U256::from(20));
+        let outer = journal.checkpoint();
+        journal.set_nonce(&address, 2);
+        journal.access_storage_key(&address, &key);
+        journal.write_storage(&address, key, U256::from(3)).unwrap();
+
+        let inner = journal.checkpoint();
+        journal.set_balance(&address, U256::from(30));
+        journal.checkpoint_revert(inner);
+
+        let account = journal.get_account(&address).unwrap();
+        assert_eq!(account.balance, U256::from(20));
+        assert_eq!(account.nonce, 2);
+        assert!(journal.key_is_warm(&address, &key));
+
+        journal.checkpoint_revert(outer);
+
+        let account = journal.get_account(&address).unwrap();
+        assert_eq!(account.balance, U256::from(20));
+        assert_eq!(account.nonce, 1);
+        assert!(!journal.key_is_warm(&address, &key));
+        let slot = journal.read_storage(&address, &key).unwrap();
+        assert_eq!(slot.present_value, U256::zero());
+    }
+
+    #[test]
+    fn reverting_a_committed_checkpoint_undoes_its_changes() {
+        let address = Address::from_low_u64_be(1);
+        let mut db = Db::new();
+        let mut journal = Journal::new(&mut db);
+
+        let outer = journal.checkpoint();
+        let inner = journal.checkpoint();
+        journal.new_account(address, U256::from(5));
+        journal.access_account(&address);
+        journal.checkpoint_commit(inner);
+        assert!(journal.get_account(&address).is_some());
+
+        journal.checkpoint_revert(outer);
+        assert!(journal.get_account(&address).is_none());
+        assert!(!journal.account_is_warm(&address));
+    }
+
+    #[test]
+    fn accounts_that_only_receive_value_are_not_created() {
+        let receiver = Address::from_low_u64_be(1);
+        let contract = Address::from_low_u64_be(2);
+        let mut db = Db::new();
+        let mut journal = Journal::new(&mut db);
+
+        journal.new_touched_account(receiver, U256::from(5));
+        journal.new_account(contract, U256::zero());
+
+        assert!(!journal.account_is_created(&receiver));
+        assert_eq!(
+            journal.get_account(&receiver).unwrap().balance,
+            U256::from(5)
+        );
+        assert!(journal.account_is_created(&contract));
+    }
+
+    /// Database counting its storage reads, which fail after the first one
+    #[derive(Default)]
+    struct CountingDb {
+        db: Db,
+        storage_reads: usize,
+    }
+
+    impl Database for CountingDb {
+        type Error = &'static str;
+
+        fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+            Ok(self.db.basic(address).unwrap())
+        }
+
+        fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+            Ok(self.db.code_by_hash(code_hash).unwrap())
+        }
+
+        fn storage(&mut self, address: Address, index: U256) -> Result<U256, Self::Error> {
+            self.storage_reads += 1;
+            if self.storage_reads > 1 {
+                return Err("storage read twice");
+            }
+            Ok(self.db.storage(address, index).unwrap())
+        }
+
+        fn block_hash(&mut self, number: U256) -> Result<B256, Self::Error> {
+            Ok(self.db.block_hash(number).unwrap())
+        }
+    }
+
+    #[test]
+    fn cached_storage_slots_dont_hit_the_database() {
+        let address = Address::from_low_u64_be(1);
+        let key = U256::from(7);
+        let mut db = CountingDb::default();
+        db.db
+            .set_account(address, 1, U256::from(10), HashMap::new());
+        let mut journal = Journal::new(&mut db);
+
+        journal.read_storage(&address, &key).unwrap();
+        journal.read_storage(&address, &key).unwrap();
+        journal.write_storage(&address, key, U256::from(3)).unwrap();
+
+        assert!(journal.take_error().is_ok());
+        drop(journal);
+        assert_eq!(db.storage_reads, 1);
     }
 }
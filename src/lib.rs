@@ -1,12 +1,18 @@
 use builder::EvmBuilder;
-use db::{Database, Db};
-use executor::{Executor, OptLevel};
+use constants::{gas_cost, precompiles::precompile_addresses};
+use db::{Database, DatabaseCommit};
+use env::{CfgEnv, TransactTo};
+use executor::ExecutorCache;
 use journal::Journal;
-use program::Program;
-use result::{EVMError, ExecutionResult, ResultAndState};
+use primitives::{Address, Bytes, U256};
+use result::{
+    EVMError, ExecutionResult, HaltReason, InvalidTransaction, OutOfGasError, Output,
+    ResultAndState,
+};
+use spec::SpecId;
+use std::sync::Arc;
 use syscall::{CallFrame, SyscallContext};
-
-use crate::context::Context;
+use utils::compute_contract_address;
 
 pub mod builder;
 pub mod codegen;
@@ -23,14 +29,23 @@ pub mod syscall;
 pub mod utils;
 pub use env::Env;
 pub mod journal;
+pub mod memory;
 pub mod precompiles;
 pub mod result;
+pub mod spec;
 pub mod state;
 
+/// Executes transactions against a [`Database`].
+///
+/// Running transactions requires the database and its errors to be [`Send`]: every few
+/// levels of nesting, calls run on a new thread with its own stack, so the native stack
+/// doesn't overflow, and those threads access the database through the journal.
 #[derive(Debug)]
 pub struct Evm<DB: Database> {
     pub env: Env,
     pub db: DB,
+    /// Code compiled by previous frames and transactions
+    executor_cache: Arc<ExecutorCache>,
 }
 
 impl<DB: Database + Default> Evm<DB> {
@@ -41,46 +56,342 @@ impl<DB: Database + Default> Evm<DB> {
 
     /// Creates a new EVM instance with the given environment and database.
     pub fn new(env: Env, db: DB) -> Self {
-        Self { env, db }
+        Self {
+            env,
+            db,
+            executor_cache: Default::default(),
+        }
+    }
+}
+
+impl<DB: Database> Evm<DB> {
+    /// Drops the code compiled by previous transactions.
+    pub fn clear_executor_cache(&self) {
+        self.executor_cache.clear();
     }
 }
 
-impl Evm<Db> {
+impl<DB> Evm<DB>
+where
+    DB: Database + Send,
+    DB::Error: Send,
+{
     /// Executes [the configured transaction](Env::tx).
-    pub fn transact(&mut self) -> Result<ResultAndState, EVMError> {
-        let context = Context::new();
-        let code_address = self.env.tx.get_address();
-
-        //TODO: Improve error handling
-        let bytecode = self
-            .db
-            .code_by_address(code_address)
-            .expect("Failed to get code from address");
-        let program = Program::from_bytecode(&bytecode);
-
-        self.env.consume_intrinsic_cost()?;
-        self.env.validate_transaction()?;
-        // validate transaction
-
-        let module = context
-            .compile(&program, Default::default())
-            .expect("failed to compile program");
-
-        let call_frame = CallFrame::new(self.env.tx.caller);
-        let journal = Journal::new(&mut self.db);
-        let mut context = SyscallContext::new(self.env.clone(), journal, call_frame);
-        let executor = Executor::new(&module, &context, OptLevel::Aggressive);
-
-        // TODO: improve this once we stabilize the API a bit
-        context.inner_context.program = program.to_bytecode();
-        executor.execute(&mut context, self.env.tx.gas_limit);
-
-        context.get_result()
+    pub fn transact(&mut self) -> Result<ResultAndState, EVMError<DB::Error>> {
+        let mut env = self.env.clone();
+
+        let gas_limit = env.tx.gas_limit;
+        // The spec is taken from the chain's fork schedule when there's one
+        env.cfg.spec_id = env.cfg.spec_id_at(&env.block);
+        let spec_id = env.cfg.spec_id;
+        if spec_id.is_enabled_in(SpecId::Prague) {
+            return Err(EVMError::Custom(format!("{spec_id} is not supported yet")));
+        }
+        env.block.validate(spec_id)?;
+        env.validate_transaction()?;
+
+        let mut journal = Journal::new(&mut self.db);
+        let caller_account = journal.get_account(&env.tx.caller).unwrap_or_default();
+        journal.take_error()?;
+        env.validate_transaction_against_state(&caller_account)?;
+        // Without the balance check, the caller is given the funds it lacks
+        if env.cfg.disable_balance_check {
+            let max_fee = env.calc_max_fee().unwrap_or(U256::MAX);
+            if caller_account.balance < max_fee {
+                increase_balance(
+                    &mut journal,
+                    &env.tx.caller,
+                    max_fee - caller_account.balance,
+                );
+            }
+        }
+
+        env.consume_intrinsic_cost()?;
+        let intrinsic_gas = gas_limit - env.tx.gas_limit;
+
+        // Creation transactions run `tx.data` as init code at the new contract's address
+        let is_create = matches!(env.tx.transact_to, TransactTo::Create);
+        let (code_address, calldata, bytecode) = match env.tx.transact_to {
+            TransactTo::Call(address) => {
+                let bytecode = journal.code_by_address(&address);
+                (address, env.tx.data.clone(), bytecode)
+            }
+            TransactTo::Create => {
+                let address = compute_contract_address(env.tx.caller, caller_account.nonce);
+                (address, Bytes::new(), env.tx.data.clone())
+            }
+        };
+
+        let call_frame = CallFrame {
+            code_address,
+            address: code_address,
+            caller: env.tx.caller,
+            value: env.tx.value,
+            calldata,
+            bytecode,
+            gas_limit: env.tx.gas_limit,
+            ..Default::default()
+        };
+
+        // Pre-warm the accessed addresses and storage keys (EIP-2929 and EIP-2930)
+        journal.prefetch_account(&env.tx.caller);
+        journal.prefetch_account(&code_address);
+        // EIP-3651: Warm COINBASE
+        if spec_id.is_enabled_in(SpecId::Shanghai) {
+            journal.prefetch_account(&env.block.coinbase);
+        }
+        for precompile in precompile_addresses(spec_id) {
+            journal.prefetch_account(&Address::from_low_u64_be(precompile));
+        }
+        for (address, keys) in &env.tx.access_list {
+            journal.prefetch_account_keys(address, keys);
+        }
+
+        deduct_caller(&env, &mut journal, gas_limit)?;
+
+        // EIP-684: creation fails if the target address already has code or a nonce
+        let collision = is_create
+            && journal
+                .get_account(&code_address)
+                .is_some_and(|account| account.has_code() || account.nonce != 0);
+
+        let result = if collision {
+            ExecutionResult::Halt {
+                reason: HaltReason::CreateCollision,
+                gas_used: env.tx.gas_limit,
+            }
+        } else {
+            // The value transfer and the execution changes are discarded if the execution fails
+            let checkpoint = journal.checkpoint();
+            if is_create {
+                let balance = journal
+                    .get_account(&code_address)
+                    .unwrap_or_default()
+                    .balance;
+                journal.new_account(code_address, balance);
+                // EIP-161: contracts start with nonce one
+                if spec_id.is_enabled_in(SpecId::SpuriousDragon) {
+                    journal.set_nonce(&code_address, 1);
+                }
+            }
+            transfer_value(&env, &mut journal, &code_address);
+
+            let mut context = SyscallContext::new(env.clone(), journal, call_frame);
+            context.executor_cache = Arc::clone(&self.executor_cache);
+            self.executor_cache
+                .execute(&mut context, env.tx.gas_limit)?;
+
+            let mut result = context.execution_result();
+            journal = context.journal;
+            if is_create {
+                result = deposit_code(
+                    &mut journal,
+                    &code_address,
+                    result,
+                    env.tx.gas_limit,
+                    &env.cfg,
+                );
+            }
+            if result.is_success() {
+                journal.checkpoint_commit(checkpoint);
+            } else {
+                journal.checkpoint_revert(checkpoint);
+            }
+            result
+        };
+
+        // The refund is capped to a fraction of the gas spent, lowered by EIP-3529
+        let gas_spent = intrinsic_gas + result.gas_used();
+        let gas_refunded = (journal.refund().max(0) as u64)
+            .min(gas_spent / gas_cost::max_refund_quotient(spec_id));
+        let gas_used = gas_spent - gas_refunded;
+        reimburse_caller(&env, &mut journal, gas_limit - gas_used);
+        reward_beneficiary(&env, &mut journal, gas_used);
+        let result = with_final_gas(result, gas_used, gas_refunded);
+
+        journal.take_error()?;
+        let state = journal.into_state();
+        Ok(ResultAndState { result, state })
     }
 
-    pub fn transact_commit(&mut self) -> Result<ExecutionResult, EVMError> {
+    /// Executes [the configured transaction](Env::tx) and commits the resulting state.
+    pub fn transact_commit(&mut self) -> Result<ExecutionResult, EVMError<DB::Error>>
+    where
+        DB: DatabaseCommit,
+    {
         let ResultAndState { state, result } = self.transact()?;
         self.db.commit(state);
         Ok(result)
     }
 }
+
+/// Increments the caller's nonce and charges it the gas limit and the blob fee up front.
+///
+/// The caller's funds are checked beforehand by [`Env::validate_transaction_against_state`].
+/// The blob fee is burned, so it's neither refunded nor paid to the coinbase.
+fn deduct_caller<DB: Database>(
+    env: &Env,
+    journal: &mut Journal<DB>,
+    gas_limit: u64,
+) -> Result<(), InvalidTransaction> {
+    let caller = env.tx.caller;
+    let caller_account = journal.get_account(&caller).unwrap_or_default();
+    let gas_cost = U256::from(gas_limit)
+        .checked_mul(env.effective_gas_price())
+        .and_then(|gas_cost| gas_cost.checked_add(env.calc_data_fee()))
+        .ok_or(InvalidTransaction::OverflowPaymentInTransaction)?;
+    let balance = match caller_account.balance.checked_sub(gas_cost) {
+        Some(balance) => balance,
+        // Callers without funds only get here when the balance check is disabled
+        None if env.cfg.disable_balance_check => U256::zero(),
+        None => {
+            return Err(InvalidTransaction::LackOfFundForMaxFee {
+                fee: Box::new(gas_cost),
+                balance: Box::new(caller_account.balance),
+            })
+        }
+    };
+
+    if journal.get_account(&caller).is_none() {
+        journal.new_touched_account(caller, U256::zero());
+    }
+    journal.set_nonce(&caller, caller_account.nonce + 1);
+    journal.set_balance(&caller, balance);
+    Ok(())
+}
+
+/// Stores the runtime code returned by the init code of a creation transaction.
+///
+/// The creation halts, consuming all the gas, if the code is invalid or the
+/// remaining gas can't pay for its deposit.
+pub(crate) fn deposit_code<DB: Database>(
+    journal: &mut Journal<DB>,
+    address: &Address,
+    result: ExecutionResult,
+    gas_limit: u64,
+    cfg: &CfgEnv,
+) -> ExecutionResult {
+    let ExecutionResult::Success {
+        reason,
+        gas_used,
+        gas_refunded,
+        logs,
+        output,
+    } = result
+    else {
+        return result;
+    };
+
+    let code = output.into_data();
+    let deposit_cost = code.len() as u64 * gas_cost::BYTE_DEPOSIT_COST as u64;
+    let spec_id = cfg.spec_id;
+    let halt_reason =
+        if spec_id.is_enabled_in(SpecId::SpuriousDragon) && code.len() > cfg.max_code_size() {
+            // EIP-170: Contract code size limit
+            Some(HaltReason::CreateContractSizeLimit)
+        } else if spec_id.is_enabled_in(SpecId::London) && code.first() == Some(&0xEF) {
+            // EIP-3541: Reject new contract code starting with the 0xEF byte
+            Some(HaltReason::CreateContractStartingWithEF)
+        } else if gas_used + deposit_cost > gas_limit {
+            Some(HaltReason::OutOfGas(OutOfGasError::Basic))
+        } else {
+            None
+        };
+    // Before EIP-2, a creation that can't pay for the deposit succeeds without code
+    if !spec_id.is_enabled_in(SpecId::Homestead)
+        && halt_reason == Some(HaltReason::OutOfGas(OutOfGasError::Basic))
+    {
+        return ExecutionResult::Success {
+            reason,
+            gas_used,
+            gas_refunded,
+            logs,
+            output: Output::Create(Bytes::new(), Some(*address)),
+        };
+    }
+    if let Some(reason) = halt_reason {
+        return ExecutionResult::Halt {
+            reason,
+            gas_used: gas_limit,
+        };
+    }
+
+    journal.set_code(address, code.clone());
+    ExecutionResult::Success {
+        reason,
+        gas_used: gas_used + deposit_cost,
+        gas_refunded,
+        logs,
+        output: Output::Create(code, Some(*address)),
+    }
+}
+
+/// Replaces the execution gas of `result` with the gas charged for the whole transaction.
+fn with_final_gas(result: ExecutionResult, gas_used: u64, gas_refunded: u64) -> ExecutionResult {
+    match result {
+        ExecutionResult::Success {
+            reason,
+            logs,
+            output,
+            ..
+        } => ExecutionResult::Success {
+            reason,
+            gas_used,
+            gas_refunded,
+            logs,
+            output,
+        },
+        ExecutionResult::Revert { output, .. } => ExecutionResult::Revert { gas_used, output },
+        ExecutionResult::Halt { reason, .. } => ExecutionResult::Halt { reason, gas_used },
+    }
+}
+
+/// Transfers the transaction value from the caller to the callee.
+fn transfer_value<DB: Database>(env: &Env, journal: &mut Journal<DB>, callee: &Address) {
+    let value = env.tx.value;
+    if value.is_zero() {
+        return;
+    }
+
+    let caller_balance = journal
+        .get_account(&env.tx.caller)
+        .unwrap_or_default()
+        .balance;
+    journal.set_balance(&env.tx.caller, caller_balance - value);
+    increase_balance(journal, callee, value);
+}
+
+/// Returns the unused gas to the caller.
+fn reimburse_caller<DB: Database>(env: &Env, journal: &mut Journal<DB>, unused_gas: u64) {
+    let reimbursement = env.effective_gas_price() * U256::from(unused_gas);
+    increase_balance(journal, &env.tx.caller, reimbursement);
+}
+
+/// Pays the priority fee to the coinbase. Since London the base fee is burned, before
+/// that the coinbase gets the whole gas price.
+fn reward_beneficiary<DB: Database>(env: &Env, journal: &mut Journal<DB>, gas_used: u64) {
+    let gas_price = env.effective_gas_price();
+    let priority_fee = if env.cfg.spec_id.is_enabled_in(SpecId::London) {
+        gas_price.saturating_sub(env.block.basefee)
+    } else {
+        gas_price
+    };
+    let reward = priority_fee * U256::from(gas_used);
+    increase_balance(journal, &env.block.coinbase, reward);
+}
+
+/// Adds `amount` to the balance of `address`, creating the account if it doesn't exist.
+pub(crate) fn increase_balance<DB: Database>(
+    journal: &mut Journal<DB>,
+    address: &Address,
+    amount: U256,
+) {
+    if amount.is_zero() {
+        return;
+    }
+
+    match journal.get_account(address) {
+        Some(account) => journal.set_balance(address, account.balance + amount),
+        None => journal.new_touched_account(*address, amount),
+    }
+}
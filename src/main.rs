@@ -7,7 +7,8 @@ use evm_mlir::{
     executor::{Executor, OptLevel},
     journal::Journal,
     program::Program,
-    syscall::SyscallContext,
+    spec::SpecId,
+    syscall::{CallFrame, SyscallContext},
 };
 
 fn main() {
@@ -21,7 +22,8 @@ fn main() {
         _ => panic!("Invalid optimization level"),
     };
     let bytecode = std::fs::read(path).expect("Could not read file");
-    let program = Program::from_bytecode(&bytecode);
+    let spec_id = SpecId::default();
+    let program = Program::from_bytecode(&bytecode, spec_id);
 
     let session = Session {
         raw_mlir_path: Some(PathBuf::from("output")),
@@ -30,17 +32,22 @@ fn main() {
 
     let context = Context::new();
     let module = context
-        .compile(&program, session)
+        .compile(&program, spec_id, session)
         .expect("failed to compile program");
 
+    let initial_gas = 1000;
+
     let env = Env::default();
     let mut db = Db::default();
     let journal = Journal::new(&mut db);
-    let mut context = SyscallContext::new(env, journal, Default::default());
+    let call_frame = CallFrame {
+        bytecode: bytecode.into(),
+        gas_limit: initial_gas,
+        ..Default::default()
+    };
+    let mut context = SyscallContext::new(env, journal, call_frame);
     let executor = Executor::new(&module, &context, opt_level);
 
-    let initial_gas = 1000;
-
     let result = executor.execute(&mut context, initial_gas);
     println!("Execution result: {result}");
 }
@@ -0,0 +1,310 @@
+//! # Memory of the EVM call frames
+//!
+//! Each call frame gets its memory from a region of virtual memory reserved up front
+//! with `mmap`, big enough to hold the memory limit of the frame. Pages are only committed
+//! by the OS when first touched, so the reservation itself is cheap. Since the region
+//! never moves, growing the memory doesn't reallocate nor copy.
+//!
+//! The region is followed by a guard page, so any access past its end faults instead of
+//! silently reading or corrupting other memory.
+//!
+//! The region is reserved when the frame starts, and the generated code loads its base
+//! pointer only once. The reservation is capped by what the gas of the frame can pay for,
+//! so frames with little gas reserve little.
+//!
+//! Only going over the memory limit is an EVM outcome, halting the frame with an out of
+//! gas error. Failing to reserve the region depends on the host (its address space, its
+//! limits, the pooled regions), so it aborts the transaction with an [`EVMError`] instead
+//! of affecting its result.
+//!
+//! [`EVMError`]: crate::result::EVMError
+//!
+//! Regions are pooled, so they can be reused by later frames and transactions.
+use std::{
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    sync::Mutex,
+};
+
+use thiserror::Error;
+
+use crate::constants::MAX_MEMORY_SIZE;
+
+/// Maximum number of released regions kept around for reuse
+const MAX_POOLED_REGIONS: usize = 64;
+
+static REGION_POOL: Mutex<Vec<MemoryRegion>> = Mutex::new(Vec::new());
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum MemoryError {
+    #[error("memory size {0} is over the memory limit")]
+    SizeTooBig(usize),
+    #[error("couldn't reserve the memory region")]
+    ReservationFailed,
+}
+
+/// A reservation of virtual memory, with a guard page at its end.
+#[derive(Debug)]
+struct MemoryRegion {
+    base: NonNull<u8>,
+    capacity: usize,
+}
+
+// The region is only ever accessed by the frame owning it
+unsafe impl Send for MemoryRegion {}
+
+impl MemoryRegion {
+    /// Reserves a region able to hold `size` bytes.
+    fn reserve(size: usize) -> Result<Self, MemoryError> {
+        let page_size = page_size();
+        let (capacity, mapping_size) = size
+            .checked_next_multiple_of(page_size)
+            .and_then(|capacity| Some((capacity, capacity.checked_add(page_size)?)))
+            .ok_or(MemoryError::ReservationFailed)?;
+        // SAFETY: we ask for a new private anonymous mapping, not overlapping any other
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                mapping_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(MemoryError::ReservationFailed);
+        }
+        let region = Self {
+            base: NonNull::new(base as *mut u8).ok_or(MemoryError::ReservationFailed)?,
+            capacity,
+        };
+        // SAFETY: the guard page is the last page of the mapping we just created
+        let guard_page = unsafe { region.base.as_ptr().add(capacity) };
+        if unsafe { libc::mprotect(guard_page as *mut _, page_size, libc::PROT_NONE) } != 0 {
+            return Err(MemoryError::ReservationFailed);
+        }
+        Ok(region)
+    }
+
+    /// Takes a region able to hold `size` bytes from the pool, or reserves a new one if
+    /// there's none.
+    fn acquire(size: usize) -> Result<Self, MemoryError> {
+        let pooled = REGION_POOL.lock().ok().and_then(|mut pool| {
+            let index = pool.iter().position(|region| region.capacity >= size)?;
+            Some(pool.swap_remove(index))
+        });
+        match pooled {
+            Some(region) => Ok(region),
+            None => Self::reserve(size),
+        }
+    }
+
+    /// Gives the region back to the pool, after zeroing the first `used` bytes.
+    fn release(self, used: usize) {
+        let used = used.next_multiple_of(page_size());
+        if used > 0 {
+            // Mapping fresh pages over the used ones zeroes them, and lets the OS
+            // reclaim the physical memory backing them.
+            // SAFETY: the range is within the usable part of the region
+            let remapped = unsafe {
+                libc::mmap(
+                    self.base.as_ptr() as *mut _,
+                    used,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE | libc::MAP_FIXED,
+                    -1,
+                    0,
+                )
+            };
+            if remapped == libc::MAP_FAILED {
+                // The region can't be guaranteed to be zeroed, so it's not reused
+                return;
+            }
+        }
+        if let Ok(mut pool) = REGION_POOL.lock() {
+            if pool.len() < MAX_POOLED_REGIONS {
+                pool.push(self);
+            }
+        }
+    }
+}
+
+impl Drop for MemoryRegion {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was created in `reserve` with this same size
+        unsafe {
+            libc::munmap(self.base.as_ptr() as *mut _, self.capacity + page_size());
+        }
+    }
+}
+
+/// The memory of a call frame.
+///
+/// Its contents can be accessed as a byte slice. The region backing it is reserved by
+/// [`Memory::reserve`], or on its first extension, and never moves afterwards.
+#[derive(Debug)]
+pub struct Memory {
+    region: Option<MemoryRegion>,
+    len: usize,
+    limit: usize,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::with_limit(MAX_MEMORY_SIZE as usize)
+    }
+}
+
+impl Memory {
+    /// Creates an empty memory that can grow up to `limit` bytes.
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            region: None,
+            len: 0,
+            limit,
+        }
+    }
+
+    /// Reserves the region backing the memory, if it isn't reserved yet, and returns its
+    /// base pointer. The pointer stays valid for as long as the memory lives.
+    pub fn reserve(&mut self) -> Result<NonNull<u8>, MemoryError> {
+        let region = match self.region.take() {
+            Some(region) => region,
+            None => MemoryRegion::acquire(self.limit)?,
+        };
+        Ok(self.region.insert(region).base)
+    }
+
+    /// Grows the memory to `new_len` bytes, filling the new bytes with zeros.
+    ///
+    /// Smaller lengths leave the memory untouched. The base pointer never changes.
+    pub fn extend(&mut self, new_len: usize) -> Result<(), MemoryError> {
+        if new_len <= self.len {
+            return Ok(());
+        }
+        if new_len > self.limit {
+            return Err(MemoryError::SizeTooBig(new_len));
+        }
+        // Untouched pages of a region are already zeroed
+        self.reserve()?;
+        self.len = new_len;
+        Ok(())
+    }
+}
+
+impl Deref for Memory {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.region {
+            // SAFETY: the first `len` bytes of the region are mapped and initialized
+            Some(region) => unsafe { std::slice::from_raw_parts(region.base.as_ptr(), self.len) },
+            None => &[],
+        }
+    }
+}
+
+impl DerefMut for Memory {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match &mut self.region {
+            // SAFETY: the first `len` bytes of the region are mapped and initialized
+            Some(region) => unsafe {
+                std::slice::from_raw_parts_mut(region.base.as_ptr(), self.len)
+            },
+            None => &mut [],
+        }
+    }
+}
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        if let Some(region) = self.region.take() {
+            region.release(self.len);
+        }
+    }
+}
+
+fn page_size() -> usize {
+    // SAFETY: sysconf has no preconditions
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db::DatabaseError, result::EVMError};
+
+    #[test]
+    fn extending_memory_keeps_the_base_pointer() {
+        let mut memory = Memory::default();
+        memory.extend(32).unwrap();
+        let base = memory.as_ptr();
+
+        memory.extend(1 << 20).unwrap();
+
+        assert_eq!(memory.as_ptr(), base);
+        assert_eq!(memory.len(), 1 << 20);
+        assert!(memory.iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn reused_regions_are_zeroed() {
+        let mut memory = Memory::default();
+        memory.extend(64).unwrap();
+        memory[..64].fill(0xff);
+        drop(memory);
+
+        let mut memory = Memory::default();
+        memory.extend(64).unwrap();
+
+        assert!(memory.iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn extending_past_the_maximum_size_fails() {
+        let mut memory = Memory::default();
+        let new_len = MAX_MEMORY_SIZE as usize + 1;
+
+        assert_eq!(
+            memory.extend(new_len),
+            Err(MemoryError::SizeTooBig(new_len))
+        );
+        assert!(memory.is_empty());
+    }
+
+    #[test]
+    fn memory_is_reserved_up_to_its_limit() {
+        let mut memory = Memory::with_limit(64);
+        memory.extend(64).unwrap();
+
+        assert!(matches!(memory.region, Some(ref region) if region.capacity < (1 << 20)));
+        assert_eq!(memory.extend(65), Err(MemoryError::SizeTooBig(65)));
+        assert_eq!(memory.len(), 64);
+    }
+
+    #[test]
+    fn reserving_keeps_the_base_pointer() {
+        let mut memory = Memory::with_limit(1 << 20);
+        let base = memory.reserve().unwrap();
+
+        memory.extend(1 << 20).unwrap();
+
+        assert_eq!(memory.reserve().unwrap(), base);
+        assert_eq!(memory.as_ptr(), base.as_ptr() as *const u8);
+    }
+
+    #[test]
+    fn memory_fails_when_it_cant_be_reserved() {
+        let mut memory = Memory::with_limit(usize::MAX);
+
+        let error = memory.extend(32).unwrap_err();
+
+        assert_eq!(error, MemoryError::ReservationFailed);
+        assert!(matches!(
+            EVMError::<DatabaseError>::from(error),
+            EVMError::Custom(_)
+        ));
+        assert!(memory.is_empty());
+    }
+}
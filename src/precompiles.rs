@@ -3,24 +3,55 @@ use crate::constants::precompiles::{
     ECRECOVER_COST, IDENTITY_COST, RIPEMD_160_COST, SHA2_256_COST,
 };
 use crate::primitives::U256;
+use crate::spec::SpecId;
 use bytes::Bytes;
 use num_bigint::BigUint;
 use secp256k1::{ecdsa, Message, Secp256k1};
 use sha3::{Digest, Keccak256};
 use std::array::TryFromSliceError;
+use thiserror::Error;
+
+/// Reason a precompile failed. A failing precompile consumes all the gas sent to it.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrecompileError {
+    #[error("out of gas")]
+    OutOfGas,
+    #[error("invalid input")]
+    InvalidInput,
+    /// The precompile is active but isn't implemented, so the transaction can't be executed.
+    #[error("precompile not implemented")]
+    NotImplemented,
+}
+
+/// Stands in for the active precompiles that aren't implemented yet.
+pub fn not_implemented(
+    _calldata: &Bytes,
+    _gas_limit: u64,
+    _consumed_gas: &mut u64,
+) -> Result<Bytes, PrecompileError> {
+    Err(PrecompileError::NotImplemented)
+}
 
 pub fn ecrecover(
     calldata: &Bytes,
     gas_limit: u64,
     consumed_gas: &mut u64,
-) -> Result<Bytes, secp256k1::Error> {
-    if gas_limit < ECRECOVER_COST || calldata.len() < 128 {
-        return Ok(Bytes::new());
+) -> Result<Bytes, PrecompileError> {
+    if gas_limit < ECRECOVER_COST {
+        return Err(PrecompileError::OutOfGas);
     }
     *consumed_gas += ECRECOVER_COST;
-    let hash = &calldata[0..32];
-    let v = calldata[63] as i32 - 27;
-    let sig = &calldata[64..128];
+    // Missing input bytes are zeros, and invalid signatures don't recover any address
+    let mut input = [0_u8; 128];
+    let len = calldata.len().min(input.len());
+    input[..len].copy_from_slice(&calldata[..len]);
+    Ok(recover_address(&input).unwrap_or_default())
+}
+
+fn recover_address(input: &[u8; 128]) -> Result<Bytes, secp256k1::Error> {
+    let hash = &input[0..32];
+    let v = input[63] as i32 - 27;
+    let sig = &input[64..128];
 
     let msg = Message::from_digest_slice(hash)?;
     let id = ecdsa::RecoveryId::from_i32(v)?;
@@ -36,82 +67,89 @@ pub fn ecrecover(
     Ok(Bytes::copy_from_slice(&address_hash))
 }
 
-pub fn identity(calldata: &Bytes, gas_limit: u64, consumed_gas: &mut u64) -> Bytes {
+pub fn identity(
+    calldata: &Bytes,
+    gas_limit: u64,
+    consumed_gas: &mut u64,
+) -> Result<Bytes, PrecompileError> {
     let gas_cost = IDENTITY_COST + identity_dynamic_cost(calldata.len() as u64);
     if gas_limit < gas_cost {
-        return Bytes::new();
+        return Err(PrecompileError::OutOfGas);
     }
     *consumed_gas += gas_cost;
-    calldata.clone()
+    Ok(calldata.clone())
 }
 
-pub fn sha2_256(calldata: &Bytes, gas_limit: u64, consumed_gas: &mut u64) -> Bytes {
+pub fn sha2_256(
+    calldata: &Bytes,
+    gas_limit: u64,
+    consumed_gas: &mut u64,
+) -> Result<Bytes, PrecompileError> {
     let gas_cost = SHA2_256_COST + sha2_256_dynamic_cost(calldata.len() as u64);
     if gas_limit < gas_cost {
-        return Bytes::new();
+        return Err(PrecompileError::OutOfGas);
     }
     *consumed_gas += gas_cost;
     let hash = sha2::Sha256::digest(calldata);
-    Bytes::copy_from_slice(&hash)
+    Ok(Bytes::copy_from_slice(&hash))
 }
 
-pub fn ripemd_160(calldata: &Bytes, gas_limit: u64, consumed_gas: &mut u64) -> Bytes {
+pub fn ripemd_160(
+    calldata: &Bytes,
+    gas_limit: u64,
+    consumed_gas: &mut u64,
+) -> Result<Bytes, PrecompileError> {
     let gas_cost = RIPEMD_160_COST + ripemd_160_dynamic_cost(calldata.len() as u64);
     if gas_limit < gas_cost {
-        return Bytes::new();
+        return Err(PrecompileError::OutOfGas);
     }
     *consumed_gas += gas_cost;
     let mut hasher = ripemd::Ripemd160::new();
     hasher.update(calldata);
     let mut output = [0u8; 32];
     hasher.finalize_into((&mut output[12..]).into());
-    Bytes::copy_from_slice(&output)
+    Ok(Bytes::copy_from_slice(&output))
 }
 
-pub fn modexp(calldata: &Bytes, gas_limit: u64, consumed_gas: &mut u64) -> Bytes {
-    if calldata.len() < 96 {
-        return Bytes::new();
-    }
-
-    // Cast sizes as usize and check for overflow.
-    // Bigger sizes are not accepted, as memory can't index bigger values.
-    let Ok(b_size) = usize::try_from(U256::from_big_endian(&calldata[0..32])) else {
-        return Bytes::new();
-    };
-    let Ok(e_size) = usize::try_from(U256::from_big_endian(&calldata[32..64])) else {
-        return Bytes::new();
-    };
-    let Ok(m_size) = usize::try_from(U256::from_big_endian(&calldata[64..96])) else {
-        return Bytes::new();
-    };
-
-    // Check if calldata contains all values
-    let params_len = 96 + b_size + e_size + m_size;
-    if calldata.len() < params_len {
-        return Bytes::new();
-    }
-    let b = BigUint::from_bytes_be(&calldata[96..96 + b_size]);
-    let e = BigUint::from_bytes_be(&calldata[96 + b_size..96 + b_size + e_size]);
-    let m = BigUint::from_bytes_be(&calldata[96 + b_size + e_size..params_len]);
-
-    // Compute gas cost
-    let max_length = b_size.max(m_size);
-    let words = (max_length + 7) / 8;
-    let multiplication_complexity = (words * words) as u64;
-    let iteration_count = if e_size <= 32 && e != BigUint::ZERO {
-        e.bits() - 1
-    } else if e_size > 32 {
-        8 * (e_size as u64 - 32) + e.bits().max(1) - 1
-    } else {
-        0
+pub fn modexp(
+    calldata: &Bytes,
+    gas_limit: u64,
+    consumed_gas: &mut u64,
+    spec_id: SpecId,
+) -> Result<Bytes, PrecompileError> {
+    // Missing input is read as zeros. Sizes too big for a u64 can't be paid for anyway
+    let read_size = |offset| {
+        let size = U256::from_big_endian(&padded_slice(calldata, offset, 32));
+        if size > U256::from(u64::MAX) {
+            u64::MAX
+        } else {
+            size.as_u64()
+        }
     };
-    let calculate_iteration_count = iteration_count.max(1);
-    let gas_cost = (multiplication_complexity * calculate_iteration_count / 3).max(200);
+    let (b_size, e_size, m_size) = (read_size(0), read_size(32), read_size(64));
+
+    // Only the first 32 bytes of the exponent are needed to price the call
+    let e_offset = 96_u64.saturating_add(b_size);
+    let e_head = BigUint::from_bytes_be(&padded_slice(
+        calldata,
+        e_offset.try_into().unwrap_or(usize::MAX),
+        e_size.min(32) as usize,
+    ));
+    let gas_cost = modexp_gas_cost(spec_id, b_size, e_size, m_size, &e_head);
     if gas_limit < gas_cost {
-        return Bytes::new();
+        return Err(PrecompileError::OutOfGas);
     }
     *consumed_gas += gas_cost;
 
+    if b_size == 0 && m_size == 0 {
+        return Ok(Bytes::new());
+    }
+    // The sizes are bounded by the gas paid, so they fit in memory
+    let (b_size, e_size, m_size) = (b_size as usize, e_size as usize, m_size as usize);
+    let b = BigUint::from_bytes_be(&padded_slice(calldata, 96, b_size));
+    let e = BigUint::from_bytes_be(&padded_slice(calldata, 96 + b_size, e_size));
+    let m = BigUint::from_bytes_be(&padded_slice(calldata, 96 + b_size + e_size, m_size));
+
     let result = if m == BigUint::ZERO {
         BigUint::ZERO
     } else if e == BigUint::ZERO {
@@ -120,8 +158,56 @@ pub fn modexp(calldata: &Bytes, gas_limit: u64, consumed_gas: &mut u64) -> Bytes
         b.modpow(&e, &m)
     };
 
-    let output = &result.to_bytes_be()[..m_size];
-    Bytes::copy_from_slice(output)
+    // The result is left padded to the size of the modulus
+    let result = result.to_bytes_be();
+    let mut output = vec![0_u8; m_size];
+    let padding = m_size.saturating_sub(result.len());
+    output[padding..].copy_from_slice(&result[result.len().saturating_sub(m_size)..]);
+    Ok(Bytes::from(output))
+}
+
+/// Gas cost of a modexp call, following EIP-2565 since Berlin and EIP-198 before.
+fn modexp_gas_cost(
+    spec_id: SpecId,
+    b_size: u64,
+    e_size: u64,
+    m_size: u64,
+    e_head: &BigUint,
+) -> u64 {
+    let head_bits = e_head.bits().saturating_sub(1);
+    let iteration_count = if e_size <= 32 {
+        head_bits
+    } else {
+        (e_size - 32).saturating_mul(8).saturating_add(head_bits)
+    };
+    let iteration_count = iteration_count.max(1) as u128;
+    let max_length = b_size.max(m_size) as u128;
+
+    let gas_cost = if spec_id.is_enabled_in(SpecId::Berlin) {
+        let words = max_length.div_ceil(8);
+        let multiplication_complexity = words.saturating_mul(words);
+        (multiplication_complexity.saturating_mul(iteration_count) / 3).max(200)
+    } else {
+        let squared = max_length.saturating_mul(max_length);
+        let multiplication_complexity = if max_length <= 64 {
+            squared
+        } else if max_length <= 1024 {
+            squared / 4 + 96 * max_length - 3072
+        } else {
+            (squared / 16).saturating_add(480 * max_length) - 199_680
+        };
+        multiplication_complexity.saturating_mul(iteration_count) / 20
+    };
+    u64::try_from(gas_cost).unwrap_or(u64::MAX)
+}
+
+/// Returns `len` bytes of `data` starting at `offset`, padded with zeros past its end.
+fn padded_slice(data: &[u8], offset: usize, len: usize) -> Vec<u8> {
+    let mut padded = vec![0_u8; len];
+    let available = data.get(offset..).unwrap_or_default();
+    let copy_size = available.len().min(len);
+    padded[..copy_size].copy_from_slice(&available[..copy_size]);
+    padded
 }
 
 // Extracted from https://datatracker.ietf.org/doc/html/rfc7693#section-2.7
@@ -205,8 +291,6 @@ fn blake2f_compress(rounds: usize, h: &mut [u64; 8], m: &[u64; 16], t: &[u64; 2]
 
 const CALLDATA_LEN: usize = 213;
 
-use thiserror::Error;
-
 #[derive(Error, Debug)]
 #[error("Blake2Error")]
 pub struct Blake2fError;
@@ -302,7 +386,9 @@ mod tests {
             &Bytes::copy_from_slice(&calldata),
             expected_gas,
             &mut consumed_gas,
-        );
+            SpecId::Berlin,
+        )
+        .unwrap();
 
         assert_eq!(consumed_gas, expected_gas);
     }
@@ -329,11 +415,127 @@ mod tests {
             &Bytes::copy_from_slice(&calldata),
             expected_gas,
             &mut consumed_gas,
-        );
+            SpecId::Berlin,
+        )
+        .unwrap();
 
         assert_eq!(consumed_gas, expected_gas);
     }
 
+    #[test]
+    fn modexp_gas_cost_before_berlin() {
+        let mut calldata = [0_u8; 99];
+        // b_size = 1, e_size = 1, m_size = 1, with b = 8, e = 9, m = 10
+        calldata[31] = 1;
+        calldata[63] = 1;
+        calldata[95] = 1;
+        calldata[96..].copy_from_slice(&[8, 9, 10]);
+
+        // EIP-198 has no minimum cost: 1 * 3 / 20 = 0
+        let mut consumed_gas = 0;
+        let output = modexp(
+            &Bytes::copy_from_slice(&calldata),
+            0,
+            &mut consumed_gas,
+            SpecId::Byzantium,
+        )
+        .unwrap();
+
+        assert_eq!(consumed_gas, 0);
+        // 8 ^ 9 mod 10 = 8
+        assert_eq!(output.as_ref(), &[8]);
+    }
+
+    #[test]
+    fn modexp_gas_cost_before_berlin2() {
+        let mut calldata = [0_u8; 354];
+        // b_size = 256, e_size = 1, m_size = 1, with e = 6
+        calldata[30] = 1;
+        calldata[63] = 1;
+        calldata[95] = 1;
+        calldata[351..].copy_from_slice(&[8, 6, 10]);
+
+        // (256^2 / 4 + 96 * 256 - 3072) * 2 / 20
+        let expected_gas = 3788;
+        let mut consumed_gas = 0;
+        let calldata = Bytes::copy_from_slice(&calldata);
+        modexp(&calldata, expected_gas, &mut consumed_gas, SpecId::Istanbul).unwrap();
+        assert_eq!(consumed_gas, expected_gas);
+
+        let result = modexp(&calldata, expected_gas - 1, &mut 0, SpecId::Istanbul);
+        assert_eq!(result, Err(PrecompileError::OutOfGas));
+    }
+
+    #[test]
+    fn modexp_with_empty_input_returns_nothing() {
+        let mut consumed_gas = 0;
+        let output = modexp(&Bytes::new(), 200, &mut consumed_gas, SpecId::Berlin).unwrap();
+
+        assert!(output.is_empty());
+        assert_eq!(consumed_gas, 200);
+    }
+
+    #[test]
+    fn modexp_pads_short_input_with_zeros() {
+        let mut calldata = [0_u8; 96];
+        // b_size = 1, e_size = 1, m_size = 1, with the operands missing
+        calldata[31] = 1;
+        calldata[63] = 1;
+        calldata[95] = 1;
+
+        let mut consumed_gas = 0;
+        let output = modexp(
+            &Bytes::copy_from_slice(&calldata),
+            200,
+            &mut consumed_gas,
+            SpecId::Berlin,
+        )
+        .unwrap();
+
+        // 0 ^ 0 mod 0 = 0
+        assert_eq!(output.as_ref(), &[0]);
+    }
+
+    #[test]
+    fn modexp_pads_truncated_operands_with_zeros() {
+        let mut calldata = [0_u8; 98];
+        // b_size = 1, e_size = 1, m_size = 2, with the modulus cut after its first byte
+        calldata[31] = 1;
+        calldata[63] = 1;
+        calldata[95] = 2;
+        calldata[96] = 3;
+        calldata[97] = 2;
+        let calldata = [&calldata[..], &[0x01]].concat();
+
+        let mut consumed_gas = 0;
+        let output = modexp(
+            &Bytes::from(calldata),
+            200,
+            &mut consumed_gas,
+            SpecId::Berlin,
+        )
+        .unwrap();
+
+        // 3 ^ 2 mod 0x0100 = 9, left padded to the modulus size
+        assert_eq!(output.as_ref(), &[0, 9]);
+    }
+
+    #[test]
+    fn modexp_with_truncated_sizes_reads_them_as_zero_padded() {
+        // b_size is cut short, so it's read as 0x0100...
+        let calldata = [1_u8];
+
+        let mut consumed_gas = 0;
+        let result = modexp(
+            &Bytes::copy_from_slice(&calldata),
+            1_000_000,
+            &mut consumed_gas,
+            SpecId::Berlin,
+        );
+
+        assert_eq!(result, Err(PrecompileError::OutOfGas));
+    }
+
     #[test]
     fn test_blake2_evm_codes_happy_path() {
         let rounds = hex::decode("0000000c").unwrap();
@@ -2,6 +2,8 @@ use num_bigint::BigUint;
 use std::{cmp::min, fmt};
 use thiserror::Error;
 
+use crate::spec::SpecId;
+
 #[derive(Debug)]
 pub enum Opcode {
     STOP = 0x00,
@@ -337,6 +339,31 @@ impl TryFrom<u8> for Opcode {
     }
 }
 
+impl Opcode {
+    /// Returns the spec that added the opcode to the instruction set.
+    pub fn introduced_in(&self) -> SpecId {
+        match self {
+            Opcode::DELEGATECALL => SpecId::Homestead,
+            Opcode::RETURNDATASIZE
+            | Opcode::RETURNDATACOPY
+            | Opcode::STATICCALL
+            | Opcode::REVERT => SpecId::Byzantium,
+            Opcode::SHL | Opcode::SHR | Opcode::SAR | Opcode::EXTCODEHASH | Opcode::CREATE2 => {
+                SpecId::Constantinople
+            }
+            Opcode::CHAINID | Opcode::SELFBALANCE => SpecId::Istanbul,
+            Opcode::BASEFEE => SpecId::London,
+            Opcode::PUSH0 => SpecId::Shanghai,
+            Opcode::TLOAD
+            | Opcode::TSTORE
+            | Opcode::MCOPY
+            | Opcode::BLOBHASH
+            | Opcode::BLOBBASEFEE => SpecId::Cancun,
+            _ => SpecId::Frontier,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Operation {
     Stop,
@@ -530,13 +557,15 @@ pub struct Program {
 }
 
 impl Program {
-    pub fn from_bytecode_checked(bytecode: &[u8]) -> Result<Self, ParseError> {
+    /// Parses the bytecode with the instruction set of the given spec, failing on the
+    /// opcodes it doesn't have.
+    pub fn from_bytecode_checked(bytecode: &[u8], spec_id: SpecId) -> Result<Self, ParseError> {
         let mut operations = vec![];
         let mut pc = 0;
         let mut failed_opcodes = vec![];
 
         while pc < bytecode.len() {
-            match Self::parse_operation(bytecode, pc) {
+            match Self::parse_operation(bytecode, pc, spec_id) {
                 Ok((op, new_pc)) => {
                     operations.push(op);
                     pc = new_pc;
@@ -560,12 +589,15 @@ impl Program {
         }
     }
 
-    pub fn from_bytecode(bytecode: &[u8]) -> Self {
+    /// Parses the bytecode with the instruction set of the given spec.
+    ///
+    /// Opcodes added by later specs are parsed as [`Operation::Invalid`].
+    pub fn from_bytecode(bytecode: &[u8], spec_id: SpecId) -> Self {
         let mut operations = vec![];
         let mut pc = 0;
 
         while pc < bytecode.len() {
-            match Self::parse_operation(bytecode, pc) {
+            match Self::parse_operation(bytecode, pc, spec_id) {
                 Ok((op, new_pc)) => {
                     operations.push(op);
                     pc = new_pc;
@@ -595,8 +627,12 @@ impl Program {
     fn parse_operation(
         bytecode: &[u8],
         mut pc: usize,
+        spec_id: SpecId,
     ) -> Result<(Operation, usize), OpcodeParseError> {
         let opcode = Opcode::try_from(bytecode[pc])?;
+        if !spec_id.is_enabled_in(opcode.introduced_in()) {
+            return Err(OpcodeParseError(bytecode[pc]));
+        }
 
         let op = match opcode {
             Opcode::STOP => Operation::Stop,
@@ -1,5 +1,7 @@
 use crate::{
-    db::DatabaseError,
+    errors::CodegenError,
+    journal::JournalError,
+    memory::MemoryError,
     primitives::{Address, Bytes, U256},
     state::Account,
     syscall::Log,
@@ -145,13 +147,15 @@ impl Output {
 
 /// Main EVM error.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum EVMError {
+pub enum EVMError<DBError> {
     /// Transaction validation error.
     Transaction(InvalidTransaction),
     /// Header validation error.
     Header(InvalidHeader),
     /// Database error.
-    Database(DatabaseError),
+    Database(DBError),
+    /// Bytecode compilation error.
+    Compilation(String),
     /// Custom error.
     ///
     /// Useful for handler registers where custom logic would want to return their own custom error.
@@ -160,29 +164,48 @@ pub enum EVMError {
     Precompile(String),
 }
 
-impl fmt::Display for EVMError {
+impl<DBError: fmt::Display> fmt::Display for EVMError<DBError> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Transaction(e) => write!(f, "transaction validation error: {e}"),
             Self::Header(e) => write!(f, "header validation error: {e}"),
             Self::Database(e) => write!(f, "database error: {e}"),
+            Self::Compilation(e) => write!(f, "compilation error: {e}"),
             Self::Precompile(e) | Self::Custom(e) => f.write_str(e),
         }
     }
 }
 
-impl From<InvalidTransaction> for EVMError {
+impl<DBError> From<InvalidTransaction> for EVMError<DBError> {
     fn from(value: InvalidTransaction) -> Self {
         Self::Transaction(value)
     }
 }
 
-impl From<InvalidHeader> for EVMError {
+impl<DBError> From<InvalidHeader> for EVMError<DBError> {
     fn from(value: InvalidHeader) -> Self {
         Self::Header(value)
     }
 }
 
+impl<DBError> From<CodegenError> for EVMError<DBError> {
+    fn from(value: CodegenError) -> Self {
+        Self::Compilation(value.to_string())
+    }
+}
+
+impl<DBError> From<JournalError> for EVMError<DBError> {
+    fn from(value: JournalError) -> Self {
+        Self::Custom(value.to_string())
+    }
+}
+
+impl<DBError> From<MemoryError> for EVMError<DBError> {
+    fn from(value: MemoryError) -> Self {
+        Self::Custom(value.to_string())
+    }
+}
+
 /// Transaction validation error.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -239,7 +262,7 @@ pub enum InvalidTransaction {
     /// Blob transaction can't be a create transaction.
     /// `to` must be present
     BlobCreateTransaction,
-    /// Transaction has more then [`crate::MAX_BLOB_NUMBER_PER_BLOCK`] blobs
+    /// Transaction has more then [`crate::constants::MAX_BLOB_NUMBER_PER_BLOCK`] blobs
     TooManyBlobs {
         max: usize,
         have: usize,
@@ -316,6 +339,8 @@ pub enum InvalidHeader {
     PrevrandaoNotSet,
     /// `excess_blob_gas` is not set for Cancun and above.
     ExcessBlobGasNotSet,
+    /// `blob_gasprice` is not set for Cancun and above.
+    BlobGasPriceNotSet,
 }
 
 impl fmt::Display for InvalidHeader {
@@ -323,6 +348,7 @@ impl fmt::Display for InvalidHeader {
         match self {
             Self::PrevrandaoNotSet => write!(f, "`prevrandao` not set"),
             Self::ExcessBlobGasNotSet => write!(f, "`excess_blob_gas` not set"),
+            Self::BlobGasPriceNotSet => write!(f, "`blob_gasprice` not set"),
         }
     }
 }
@@ -364,6 +390,9 @@ pub enum HaltReason {
     CallNotAllowedInsideStatic,
     OutOfFunds,
     CallTooDeep,
+
+    /// A syscall failed unexpectedly
+    FatalExternalError,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -0,0 +1,103 @@
+//! # Hardfork specifications
+//!
+//! The rules of the EVM changed with each hardfork: opcodes were added, gas costs were
+//! repriced and refunds were reduced. [`SpecId`] selects the hardfork whose rules are used.
+use std::{fmt, str::FromStr};
+
+use thiserror::Error;
+
+/// Ethereum mainnet hardforks, in activation order.
+///
+/// Each spec includes the changes of all the previous ones, so they can be compared to
+/// check whether a change is enabled.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum SpecId {
+    Frontier,
+    Homestead,
+    /// EIP-150: Gas cost changes for IO-heavy operations
+    Tangerine,
+    /// EIP-158/161: State clearing, and EIP-170: Contract code size limit
+    SpuriousDragon,
+    Byzantium,
+    Constantinople,
+    Petersburg,
+    Istanbul,
+    /// EIP-2929: Gas cost increases for state access opcodes
+    Berlin,
+    /// EIP-1559: Fee market change, and EIP-3529: Reduction in refunds
+    London,
+    /// Paris, AKA the merge
+    Merge,
+    Shanghai,
+    #[default]
+    Cancun,
+    /// Only scheduled: none of its rules are implemented, so transactions running
+    /// with it are rejected instead of silently following Cancun's rules
+    Prague,
+}
+
+impl SpecId {
+    /// Returns whether the changes introduced in `other` are active in this spec.
+    pub fn is_enabled_in(self, other: SpecId) -> bool {
+        self >= other
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("unknown spec: {0}")]
+pub struct SpecParseError(pub String);
+
+impl FromStr for SpecId {
+    type Err = SpecParseError;
+
+    /// Parses the spec from its name, as used in the Ethereum tests and client configs
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let spec = match name {
+            "Frontier" => SpecId::Frontier,
+            "Homestead" => SpecId::Homestead,
+            "Tangerine" | "EIP150" => SpecId::Tangerine,
+            "SpuriousDragon" | "EIP158" => SpecId::SpuriousDragon,
+            "Byzantium" => SpecId::Byzantium,
+            "Constantinople" => SpecId::Constantinople,
+            "Petersburg" | "ConstantinopleFix" => SpecId::Petersburg,
+            "Istanbul" => SpecId::Istanbul,
+            "Berlin" => SpecId::Berlin,
+            "London" => SpecId::London,
+            "Merge" | "Paris" => SpecId::Merge,
+            "Shanghai" => SpecId::Shanghai,
+            "Cancun" => SpecId::Cancun,
+            "Prague" => SpecId::Prague,
+            _ => return Err(SpecParseError(name.to_string())),
+        };
+        Ok(spec)
+    }
+}
+
+impl fmt::Display for SpecId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specs_are_parsed_from_their_names() {
+        assert_eq!("Shanghai".parse(), Ok(SpecId::Shanghai));
+        assert_eq!("EIP150".parse(), Ok(SpecId::Tangerine));
+        assert_eq!("Paris".parse(), Ok(SpecId::Merge));
+        assert_eq!(
+            "Olympic".parse::<SpecId>(),
+            Err(SpecParseError("Olympic".to_string()))
+        );
+    }
+
+    #[test]
+    fn later_specs_enable_earlier_changes() {
+        assert!(SpecId::Cancun.is_enabled_in(SpecId::Cancun));
+        assert!(SpecId::Prague.is_enabled_in(SpecId::Frontier));
+        assert!(!SpecId::Istanbul.is_enabled_in(SpecId::Berlin));
+    }
+}
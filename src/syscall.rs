@@ -3,40 +3,47 @@
 //! The syscalls implemented here are to be exposed to the generated code
 //! via [`register_syscalls`]. Each syscall implements functionality that's
 //! not possible to implement in the generated code, such as interacting with
-//! the storage, or just difficult, like allocating memory in the heap
-//! ([`SyscallContext::extend_memory`]).
+//! the storage, or just difficult, like reserving the memory of the frame
+//! ([`SyscallContext::get_memory_ptr`]).
 //!
 //! ### Adding a new syscall
 //!
 //! New syscalls should be implemented by adding a new method to the [`SyscallContext`]
-//! struct (see [`SyscallContext::write_result`] for an example). After that, the syscall
-//! should be registered in the [`register_syscalls`] function, which will make it available
-//! to the generated code. Afterwards, the syscall should be declared in
+//! struct (see [`SyscallContext::write_result`] for an example). Its body should be wrapped
+//! in `guard_syscall!`, since panics must never unwind into the generated code. After that,
+//! the syscall should be registered in the [`register_syscalls`] function, which will make it
+//! available to the generated code. Afterwards, the syscall should be declared in
 //! [`mlir::declare_syscalls`], which will make the syscall available inside the MLIR code.
 //! Finally, the function can be called from the MLIR code like a normal function (see
 //! [`mlir::write_result_syscall`] for an example).
-use std::ffi::c_void;
+use std::{cell::Cell, ffi::c_void, ops::Range, sync::Arc};
 
 use crate::{
-    constants::{call_opcode, gas_cost, precompiles, CallType},
-    context::Context,
-    db::AccountInfo,
-    env::{Env, TransactTo},
-    executor::{Executor, OptLevel},
+    constants::{call_opcode, gas_cost, precompiles, CallType, MAX_MEMORY_SIZE},
+    db::Database,
+    deposit_code,
+    env::Env,
+    executor::{with_stack_segment, ExecutorCache},
+    increase_balance,
     journal::Journal,
-    precompiles::{blake2f, ecrecover, identity, modexp, ripemd_160, sha2_256},
+    memory::{Memory, MemoryError},
+    precompiles::{
+        blake2f, ecrecover, identity, modexp, not_implemented, ripemd_160, sha2_256,
+        PrecompileError,
+    },
     primitives::{Address, Bytes, B256, U256 as EU256},
-    program::Program,
-    result::{EVMError, ExecutionResult, HaltReason, Output, ResultAndState, SuccessReason},
+    result::{
+        EVMError, ExecutionResult, HaltReason, OutOfGasError, Output, ResultAndState, SuccessReason,
+    },
+    spec::SpecId,
     state::AccountStatus,
     utils::{compute_contract_address, compute_contract_address2},
 };
 use melior::ExecutionEngine;
 use sha3::{Digest, Keccak256};
-use std::collections::HashMap;
 
 /// Function type for the main entrypoint of the generated code
-pub type MainFunc = extern "C" fn(&mut SyscallContext, initial_gas: u64) -> u8;
+pub type MainFunc<DB> = extern "C" fn(&mut SyscallContext<DB>, initial_gas: u64) -> u8;
 
 #[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
 #[repr(C, align(16))]
@@ -84,6 +91,11 @@ pub enum ExitStatusCode {
     Stop,
     Revert,
     Error,
+    SelfDestruct,
+    StaticCallViolation,
+    OutOfOffset,
+    MemoryLimit,
+    OutOfGas,
     Default,
 }
 impl ExitStatusCode {
@@ -97,6 +109,11 @@ impl ExitStatusCode {
             x if x == Self::Stop.to_u8() => Self::Stop,
             x if x == Self::Revert.to_u8() => Self::Revert,
             x if x == Self::Error.to_u8() => Self::Error,
+            x if x == Self::SelfDestruct.to_u8() => Self::SelfDestruct,
+            x if x == Self::StaticCallViolation.to_u8() => Self::StaticCallViolation,
+            x if x == Self::OutOfOffset.to_u8() => Self::OutOfOffset,
+            x if x == Self::MemoryLimit.to_u8() => Self::MemoryLimit,
+            x if x == Self::OutOfGas.to_u8() => Self::OutOfGas,
             _ => Self::Default,
         }
     }
@@ -106,30 +123,44 @@ impl ExitStatusCode {
 pub struct InnerContext {
     /// The memory segment of the EVM.
     /// For extending it, see [`Self::extend_memory`]
-    memory: Vec<u8>,
+    memory: Memory,
     /// The result of the execution
     return_data: Option<(usize, usize)>,
-    // The program bytecode
-    pub program: Vec<u8>,
+    /// The output of the last call made by this frame
+    last_call_return_data: Vec<u8>,
     gas_remaining: Option<u64>,
-    gas_refund: u64,
     exit_status: Option<ExitStatusCode>,
-    logs: Vec<LogData>,
+    /// Set when a syscall fails, halting the frame regardless of its exit status
+    halt_reason: Cell<Option<HaltReason>>,
 }
 
-/// Information about current call frame
-#[derive(Debug, Default)]
+/// The message executed by the current call frame
+#[derive(Clone, Debug, Default)]
 pub struct CallFrame {
+    /// The address whose code is being executed
+    pub code_address: Address,
+    /// The address whose storage, balance and logs are used (`ADDRESS`)
+    pub address: Address,
+    /// The sender of the message (`CALLER`)
     pub caller: Address,
-    ctx_is_static: bool,
-    last_call_return_data: Vec<u8>,
+    /// The value sent along with the message (`CALLVALUE`)
+    pub value: EU256,
+    /// The input data of the message (`CALLDATA*`)
+    pub calldata: Bytes,
+    /// The code being executed (`CODECOPY`)
+    pub bytecode: Bytes,
+    /// The gas available to the frame
+    pub gas_limit: u64,
+    /// Number of frames above this one (zero for the transaction's frame)
+    pub depth: usize,
+    /// Whether the frame is forbidden from modifying the state (`STATICCALL`)
+    pub ctx_is_static: bool,
 }
 
 impl CallFrame {
     pub fn new(caller: Address) -> Self {
         Self {
             caller,
-            ctx_is_static: false,
             ..Default::default()
         }
     }
@@ -137,12 +168,13 @@ impl CallFrame {
 
 /// The context passed to syscalls
 #[derive(Debug)]
-pub struct SyscallContext<'c> {
+pub struct SyscallContext<'c, DB: Database> {
     pub env: Env,
-    pub journal: Journal<'c>,
+    pub journal: Journal<'c, DB>,
     pub call_frame: CallFrame,
     pub inner_context: InnerContext,
-    pub transient_storage: HashMap<(Address, EU256), EU256>, // TODO: Move this to Journal
+    /// Compiled code shared with the nested frames
+    pub(crate) executor_cache: Arc<ExecutorCache>,
 }
 
 #[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
@@ -158,45 +190,68 @@ pub struct Log {
 }
 
 /// Accessors for disponibilizing the execution results
-impl<'c> SyscallContext<'c> {
-    pub fn new(env: Env, journal: Journal<'c>, call_frame: CallFrame) -> Self {
+impl<'c, DB: Database> SyscallContext<'c, DB> {
+    pub fn new(env: Env, journal: Journal<'c, DB>, call_frame: CallFrame) -> Self {
+        // Memory is only reserved up to the limit of the frame, or to what its gas can pay for
+        let memory_limit =
+            env.cfg
+                .memory_limit
+                .min(MAX_MEMORY_SIZE)
+                .min(gas_cost::max_memory_size_for_gas(call_frame.gas_limit)) as usize;
         Self {
             env,
             journal,
             call_frame,
-            inner_context: Default::default(),
-            transient_storage: Default::default(),
+            inner_context: InnerContext {
+                memory: Memory::with_limit(memory_limit),
+                ..Default::default()
+            },
+            executor_cache: Default::default(),
         }
     }
 
     pub fn return_values(&self) -> &[u8] {
         let (offset, size) = self.inner_context.return_data.unwrap_or((0, 0));
-        &self.inner_context.memory[offset..offset + size]
+        self.inner_context
+            .memory
+            .get(offset..offset + size)
+            .unwrap_or_default()
     }
 
+    /// Logs emitted so far in the transaction, including the ones from nested frames.
     pub fn logs(&self) -> Vec<Log> {
-        self.inner_context
-            .logs
-            .iter()
-            .map(|logdata| Log {
-                address: self.env.tx.caller,
-                data: logdata.clone(),
-            })
-            .collect()
+        self.journal.logs().to_vec()
+    }
+
+    pub fn get_result(&mut self) -> Result<ResultAndState, EVMError<DB::Error>> {
+        self.journal.take_error()?;
+        let result = self.execution_result();
+        // TODO: Check if this is ok
+        let state = self.journal.into_state();
+
+        Ok(ResultAndState { result, state })
     }
 
-    pub fn get_result(&self) -> Result<ResultAndState, EVMError> {
+    /// Builds the result of the execution, without collecting the resulting state.
+    pub(crate) fn execution_result(&self) -> ExecutionResult {
         let gas_remaining = self.inner_context.gas_remaining.unwrap_or(0);
-        let gas_refunded = self.inner_context.gas_refund;
-        let gas_initial = self.env.tx.gas_limit;
+        // Refunds are applied once the transaction ends, so this is the counter so far
+        let gas_refunded = self.journal.refund().max(0) as u64;
+        let gas_initial = self.call_frame.gas_limit;
         let gas_used = gas_initial.saturating_sub(gas_remaining);
+        if let Some(reason) = self.inner_context.halt_reason.get() {
+            return ExecutionResult::Halt {
+                reason,
+                gas_used: gas_initial,
+            };
+        }
         let exit_status = self
             .inner_context
             .exit_status
             .clone()
             .unwrap_or(ExitStatusCode::Default);
         let return_values = self.return_values().to_vec();
-        let result = match exit_status {
+        match exit_status {
             ExitStatusCode::Return => ExecutionResult::Success {
                 reason: SuccessReason::Return,
                 gas_used,
@@ -211,20 +266,210 @@ impl<'c> SyscallContext<'c> {
                 output: Output::Call(return_values.into()), // TODO: add case Output::Create
                 logs: self.logs(),
             },
+            ExitStatusCode::SelfDestruct => ExecutionResult::Success {
+                reason: SuccessReason::SelfDestruct,
+                gas_used,
+                gas_refunded,
+                output: Output::Call(Bytes::new()),
+                logs: self.logs(),
+            },
             ExitStatusCode::Revert => ExecutionResult::Revert {
                 output: return_values.into(),
                 gas_used,
             },
+            ExitStatusCode::StaticCallViolation => ExecutionResult::Halt {
+                reason: HaltReason::StateChangeDuringStaticCall,
+                gas_used,
+            },
+            ExitStatusCode::OutOfOffset => ExecutionResult::Halt {
+                reason: HaltReason::OutOfOffset,
+                gas_used,
+            },
+            ExitStatusCode::MemoryLimit => ExecutionResult::Halt {
+                reason: HaltReason::OutOfGas(OutOfGasError::MemoryLimit),
+                gas_used,
+            },
+            ExitStatusCode::OutOfGas => ExecutionResult::Halt {
+                reason: HaltReason::OutOfGas(OutOfGasError::Basic),
+                gas_used,
+            },
             ExitStatusCode::Error | ExitStatusCode::Default => ExecutionResult::Halt {
                 reason: HaltReason::OpcodeNotFound, // TODO: check which Halt error
                 gas_used,
             },
+        }
+    }
+}
+
+/// Helpers for failing syscalls
+impl<'c, DB: Database> SyscallContext<'c, DB> {
+    /// Halts the frame as soon as the failing syscall returns, see [`Self::has_halted`].
+    /// Only the first reason is kept.
+    fn halt(&self, reason: HaltReason) {
+        if self.inner_context.halt_reason.get().is_none() {
+            self.inner_context.halt_reason.set(Some(reason));
+        }
+    }
+
+    /// Returns the memory range of `size` bytes starting at `offset`, or halts the frame
+    /// with [`HaltReason::OutOfOffset`] if it doesn't fit in the current memory.
+    fn memory_range(&self, offset: u32, size: u32) -> Option<Range<usize>> {
+        let (offset, size) = (offset as usize, size as usize);
+        if size == 0 {
+            return Some(0..0);
+        }
+        if offset + size > self.inner_context.memory.len() {
+            self.halt(HaltReason::OutOfOffset);
+            return None;
+        }
+        Some(offset..offset + size)
+    }
+}
+
+/// Runs the body of a syscall, catching any panic so it never unwinds into the generated code.
+///
+/// A panic halts the frame with [`HaltReason::FatalExternalError`], and the syscall returns
+/// the given default value (or [`Default::default`]) instead. The generated code never uses
+/// it, since it checks [`SyscallContext::has_halted`] right after every syscall.
+macro_rules! guard_syscall {
+    ($ctx:ident, $default:expr, $body:block) => {{
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| $body));
+        match result {
+            Ok(value) => value,
+            Err(_) => {
+                $ctx.halt(HaltReason::FatalExternalError);
+                $default
+            }
+        }
+    }};
+    ($ctx:ident, $body:block) => {
+        guard_syscall!($ctx, Default::default(), $body)
+    };
+}
+
+type PrecompileFn = fn(&Bytes, u64, &mut u64) -> Result<Bytes, PrecompileError>;
+
+/// Returns the precompiled contract deployed at `address` in the given spec, if any.
+fn precompile(address: &Address, spec_id: SpecId) -> Option<PrecompileFn> {
+    let is_active = precompiles::precompile_addresses(spec_id)
+        .into_iter()
+        .any(|precompile| Address::from_low_u64_be(precompile) == *address);
+    if !is_active {
+        return None;
+    }
+    let precompile: PrecompileFn = match *address {
+        x if x == Address::from_low_u64_be(precompiles::ECRECOVER_ADDRESS) => ecrecover,
+        x if x == Address::from_low_u64_be(precompiles::IDENTITY_ADDRESS) => identity,
+        x if x == Address::from_low_u64_be(precompiles::SHA2_256_ADDRESS) => sha2_256,
+        x if x == Address::from_low_u64_be(precompiles::RIPEMD_160_ADDRESS) => ripemd_160,
+        // Modexp was repriced by EIP-2565
+        x if x == Address::from_low_u64_be(precompiles::MODEXP_ADDRESS)
+            && spec_id.is_enabled_in(SpecId::Berlin) =>
+        {
+            |calldata, gas_limit, consumed_gas| {
+                modexp(calldata, gas_limit, consumed_gas, SpecId::Berlin)
+            }
+        }
+        x if x == Address::from_low_u64_be(precompiles::MODEXP_ADDRESS) => {
+            |calldata, gas_limit, consumed_gas| {
+                modexp(calldata, gas_limit, consumed_gas, SpecId::Byzantium)
+            }
+        }
+        x if x == Address::from_low_u64_be(precompiles::BLAKE2F_ADDRESS) => {
+            |calldata, gas_limit, consumed_gas| {
+                blake2f(calldata, gas_limit, consumed_gas)
+                    .map_err(|_| PrecompileError::InvalidInput)
+            }
+        }
+        // The elliptic curve and point evaluation precompiles aren't implemented yet
+        _ => not_implemented,
+    };
+    Some(precompile)
+}
+
+/// Gas cost of SSTORE, following the net gas metering of EIP-2200 since Istanbul.
+///
+/// The rules of Constantinople (EIP-1283) are not applied, since they were
+/// removed by Petersburg before reaching mainnet.
+fn sstore_cost(spec_id: SpecId, original: EU256, current: EU256, new: EU256, is_cold: bool) -> i64 {
+    if !spec_id.is_enabled_in(SpecId::Istanbul) {
+        return if current.is_zero() && !new.is_zero() {
+            gas_cost::SSTORE_SET
+        } else {
+            gas_cost::SSTORE_RESET
+        };
+    }
+
+    let (sload_cost, sstore_reset) = sstore_base_costs(spec_id);
+    let cost = if new == current || original != current {
+        sload_cost
+    } else if original.is_zero() {
+        gas_cost::SSTORE_SET
+    } else {
+        sstore_reset
+    };
+
+    if spec_id.is_enabled_in(SpecId::Berlin) && is_cold {
+        cost + gas_cost::COLD_SLOAD_COST
+    } else {
+        cost
+    }
+}
+
+/// Gas refunded by SSTORE, which can be negative when a previous refund is undone.
+fn sstore_refund(spec_id: SpecId, original: EU256, current: EU256, new: EU256) -> i64 {
+    let clears_schedule = if spec_id.is_enabled_in(SpecId::London) {
+        gas_cost::SSTORE_CLEARS_SCHEDULE_LONDON
+    } else {
+        gas_cost::SSTORE_CLEARS_SCHEDULE
+    };
+
+    if !spec_id.is_enabled_in(SpecId::Istanbul) {
+        return if !current.is_zero() && new.is_zero() {
+            clears_schedule
+        } else {
+            0
         };
+    }
+    if current == new {
+        return 0;
+    }
+    if original == current && new.is_zero() {
+        return clears_schedule;
+    }
 
-        // TODO: Check if this is ok
-        let state = self.journal.into_state();
+    let mut refund = 0;
+    if !original.is_zero() {
+        if current.is_zero() {
+            // The slot was cleared before, so that refund is undone
+            refund -= clears_schedule;
+        } else if new.is_zero() {
+            refund += clears_schedule;
+        }
+    }
+    if original == new {
+        // The slot is reset to its original value, refunding the difference
+        // between the cost paid for the first write and the one of a read
+        let (sload_cost, sstore_reset) = sstore_base_costs(spec_id);
+        refund += if original.is_zero() {
+            gas_cost::SSTORE_SET - sload_cost
+        } else {
+            sstore_reset - sload_cost
+        };
+    }
+    refund
+}
 
-        Ok(ResultAndState { result, state })
+/// Returns the cost of reading a slot and of resetting a non-zero slot, as used by SSTORE.
+fn sstore_base_costs(spec_id: SpecId) -> (i64, i64) {
+    if spec_id.is_enabled_in(SpecId::Berlin) {
+        // EIP-2929: the cold access is charged apart from the reset cost
+        (
+            gas_cost::WARM_STORAGE_READ_COST,
+            gas_cost::SSTORE_RESET - gas_cost::COLD_SLOAD_COST,
+        )
+    } else {
+        (gas_cost::sload_cost(spec_id), gas_cost::SSTORE_RESET)
     }
 }
 
@@ -232,7 +477,11 @@ impl<'c> SyscallContext<'c> {
 ///
 /// Note that each function is marked as `extern "C"`, which is necessary for the
 /// function to be callable from the generated code.
-impl<'c> SyscallContext<'c> {
+impl<'c, DB> SyscallContext<'c, DB>
+where
+    DB: Database + Send,
+    DB::Error: Send,
+{
     pub extern "C" fn write_result(
         &mut self,
         offset: u32,
@@ -240,13 +489,17 @@ impl<'c> SyscallContext<'c> {
         remaining_gas: u64,
         execution_result: u8,
     ) {
-        self.inner_context.return_data = Some((offset as usize, bytes_len as usize));
-        self.inner_context.gas_remaining = Some(remaining_gas);
-        self.inner_context.exit_status = Some(ExitStatusCode::from_u8(execution_result));
+        guard_syscall!(self, {
+            self.inner_context.return_data = Some((offset as usize, bytes_len as usize));
+            self.inner_context.gas_remaining = Some(remaining_gas);
+            self.inner_context.exit_status = Some(ExitStatusCode::from_u8(execution_result));
+        })
     }
 
     pub extern "C" fn get_return_data_size(&mut self) -> u32 {
-        self.call_frame.last_call_return_data.len() as _
+        guard_syscall!(self, {
+            self.inner_context.last_call_return_data.len() as _
+        })
     }
 
     pub extern "C" fn copy_return_data_into_memory(
@@ -255,18 +508,23 @@ impl<'c> SyscallContext<'c> {
         offset: u32,
         size: u32,
     ) {
-        Self::copy_exact(
-            &mut self.inner_context.memory,
-            &self.call_frame.last_call_return_data,
-            dest_offset,
-            offset,
-            size,
-        );
+        guard_syscall!(self, {
+            let Some(dest) = self.memory_range(dest_offset, size) else {
+                return;
+            };
+            // EIP-211: reading past the end of the return data halts the frame
+            let source = (offset as usize)..(offset as usize + size as usize);
+            let Some(return_data) = self.inner_context.last_call_return_data.get(source) else {
+                self.halt(HaltReason::OutOfOffset);
+                return;
+            };
+            self.inner_context.memory[dest].copy_from_slice(return_data);
+        })
     }
 
     pub extern "C" fn call(
         &mut self,
-        mut gas_to_send: u64,
+        gas_to_send: u64,
         call_to_address: &U256,
         value_to_transfer: &U256,
         args_offset: u32,
@@ -277,298 +535,375 @@ impl<'c> SyscallContext<'c> {
         consumed_gas: &mut u64,
         call_type: u8,
     ) -> u8 {
-        //TODO: Add call depth check
-        //TODO: Check that the args offsets and sizes are correct -> This from the MLIR side
-        //TODO: This should instead add the account fetch (warm or cold) cost
-        //For the moment we consider warm access
-        let callee_address = Address::from(call_to_address);
-        //Copy the calldata from memory
-        let off = args_offset as usize;
-        let size = args_size as usize;
-        let calldata = Bytes::copy_from_slice(&self.inner_context.memory[off..off + size]);
-
-        let (return_code, return_data) = match callee_address {
-            x if x == Address::from_low_u64_be(precompiles::ECRECOVER_ADDRESS) => (
-                call_opcode::SUCCESS_RETURN_CODE,
-                ecrecover(&calldata, gas_to_send, consumed_gas).unwrap_or_default(),
-            ),
-            x if x == Address::from_low_u64_be(precompiles::IDENTITY_ADDRESS) => (
-                call_opcode::SUCCESS_RETURN_CODE,
-                identity(&calldata, gas_to_send, consumed_gas),
-            ),
-            x if x == Address::from_low_u64_be(precompiles::SHA2_256_ADDRESS) => (
-                call_opcode::SUCCESS_RETURN_CODE,
-                sha2_256(&calldata, gas_to_send, consumed_gas),
-            ),
-            x if x == Address::from_low_u64_be(precompiles::RIPEMD_160_ADDRESS) => (
-                call_opcode::SUCCESS_RETURN_CODE,
-                ripemd_160(&calldata, gas_to_send, consumed_gas),
-            ),
-            x if x == Address::from_low_u64_be(precompiles::MODEXP_ADDRESS) => (
-                call_opcode::SUCCESS_RETURN_CODE,
-                modexp(&calldata, gas_to_send, consumed_gas),
-            ),
-            x if x == Address::from_low_u64_be(precompiles::BLAKE2F_ADDRESS) => (
-                call_opcode::SUCCESS_RETURN_CODE,
-                blake2f(&calldata, gas_to_send, consumed_gas).unwrap_or_default(),
-            ),
-            _ => {
-                // Execute subcontext
-                //TODO: Add call depth check
-                //TODO: Check that the args offsets and sizes are correct -> This from the MLIR side
-                let callee_address = Address::from(call_to_address);
-                let value = value_to_transfer.to_primitive_u256();
-                let call_type = CallType::try_from(call_type)
-                    .expect("Error while parsing CallType on call syscall");
-
-                //TODO: This should instead add the account fetch (warm or cold) cost
-                //For the moment we consider warm access
-                let callee_account = match self.journal.get_account(&callee_address) {
-                    Some(acc) => {
-                        *consumed_gas = call_opcode::WARM_MEMORY_ACCESS_COST;
-                        acc
-                    }
-                    None => {
-                        *consumed_gas = 0;
-                        return call_opcode::REVERT_RETURN_CODE;
-                    }
-                };
-
-                let caller_address = self.env.tx.get_address();
-                let caller_account = self
-                    .journal
-                    .get_account(&caller_address)
-                    .unwrap_or_default();
-
-                let mut stipend = 0;
-                if !value.is_zero() {
-                    if caller_account.balance < value {
-                        //There isn't enough balance to send
-                        return call_opcode::REVERT_RETURN_CODE;
-                    }
-                    *consumed_gas += call_opcode::NOT_ZERO_VALUE_COST;
-                    if callee_account.is_empty() {
-                        *consumed_gas += call_opcode::EMPTY_CALLEE_COST;
-                    }
-                    if available_gas < *consumed_gas {
-                        return call_opcode::REVERT_RETURN_CODE; //It acctually doesn't matter what we return here
-                    }
-                    stipend = call_opcode::STIPEND_GAS_ADDITION;
-
-                    //TODO: Maybe we should increment the nonce too
-                    let caller_balance = caller_account.balance;
-                    self.journal
-                        .set_balance(&caller_address, caller_balance - value);
-
-                    let callee_balance = callee_account.balance;
-                    self.journal
-                        .set_balance(&callee_address, callee_balance + value);
-                }
-
-                let remaining_gas = available_gas - *consumed_gas;
-                gas_to_send = std::cmp::min(
-                    remaining_gas / call_opcode::GAS_CAP_DIVISION_FACTOR,
-                    gas_to_send,
-                );
-                *consumed_gas += gas_to_send;
-                gas_to_send += stipend;
-
-                let mut env = self.env.clone();
-
-                //TODO: Check if calling `get_address()` here is ok
-                let this_address = self.env.tx.get_address();
-                let (new_frame_caller, new_value, transact_to) = match call_type {
-                    CallType::Call | CallType::StaticCall => (this_address, value, callee_address),
-                    CallType::CallCode => (this_address, value, this_address),
-                    CallType::DelegateCall => {
-                        (self.call_frame.caller, self.env.tx.value, this_address)
-                    }
-                };
-
-                env.tx.value = new_value;
-                env.tx.transact_to = TransactTo::Call(transact_to);
-                env.tx.gas_limit = gas_to_send;
-
-                //Copy the calldata from memory
-                let off = args_offset as usize;
-                let size = args_size as usize;
-                env.tx.data = Bytes::from(self.inner_context.memory[off..off + size].to_vec());
+        guard_syscall!(self, {
+            let callee_address = Address::from(call_to_address);
+            let value = value_to_transfer.to_primitive_u256();
+            let call_type = CallType::try_from(call_type)
+                .expect("Error while parsing CallType on call syscall");
+            let spec_id = self.env.cfg.spec_id;
+            *consumed_gas = if spec_id.is_enabled_in(SpecId::Berlin) {
+                self.account_access_cost(&callee_address)
+            } else {
+                gas_cost::call_cost(spec_id) as u64
+            };
+            self.inner_context.last_call_return_data.clear();
 
-                //NOTE: We could optimize this by not making the call if the bytecode is zero.
-                //We would have to refund the stipend here
-                //TODO: Check if returning REVERT because of database fail is ok
-                let bytecode = self.journal.code_by_address(&callee_address);
+            //Copy the calldata from memory
+            let Some(args) = self.memory_range(args_offset, args_size) else {
+                return call_opcode::REVERT_RETURN_CODE;
+            };
+            let calldata = Bytes::copy_from_slice(&self.inner_context.memory[args]);
+            let Some(ret) = self.memory_range(ret_offset, ret_size) else {
+                return call_opcode::REVERT_RETURN_CODE;
+            };
 
-                let program = Program::from_bytecode(&bytecode);
+            // Only CALL moves the value to another account. CALLCODE sends it to the caller itself
+            let transfers_value = call_type == CallType::Call && !value.is_zero();
+            if !value.is_zero() {
+                *consumed_gas += call_opcode::NOT_ZERO_VALUE_COST;
+            }
+            // EIP-161: sending value to a dead account creates it. Before, calling a
+            // non-existent account created it even without value
+            let callee = self.journal.get_account(&callee_address);
+            let creates_callee = if spec_id.is_enabled_in(SpecId::SpuriousDragon) {
+                transfers_value && callee.map_or(true, |account| account.is_empty())
+            } else {
+                call_type == CallType::Call && callee.is_none()
+            };
+            if creates_callee {
+                *consumed_gas += call_opcode::EMPTY_CALLEE_COST;
+            }
+            let Some(remaining_gas) = available_gas.checked_sub(*consumed_gas) else {
+                // The generated code halts when it can't pay for the call
+                return call_opcode::REVERT_RETURN_CODE;
+            };
 
-                let context = Context::new();
-                let module = context
-                    .compile(&program, Default::default())
-                    .expect("failed to compile program");
+            // EIP-150: all but one 64th of the remaining gas can be forwarded.
+            // Before, asking for more gas than what's left runs out of gas
+            let forwarded_gas = if spec_id.is_enabled_in(SpecId::Tangerine) {
+                gas_to_send
+                    .min(remaining_gas - remaining_gas / call_opcode::GAS_CAP_DIVISION_FACTOR)
+            } else if gas_to_send > remaining_gas {
+                *consumed_gas = consumed_gas.saturating_add(gas_to_send);
+                return call_opcode::REVERT_RETURN_CODE;
+            } else {
+                gas_to_send
+            };
+            *consumed_gas += forwarded_gas;
+            // The callee gets a stipend on top of the forwarded gas when value is sent
+            let gas_limit = if value.is_zero() {
+                forwarded_gas
+            } else {
+                forwarded_gas + call_opcode::STIPEND_GAS_ADDITION
+            };
 
-                let is_static = self.call_frame.ctx_is_static || call_type == CallType::StaticCall;
+            // A call that can't start gives back all the gas passed to the callee
+            let this_address = self.call_frame.address;
+            let balance = self
+                .journal
+                .get_account(&this_address)
+                .unwrap_or_default()
+                .balance;
+            if self.call_frame.depth >= self.env.cfg.max_call_depth() || balance < value {
+                *consumed_gas = consumed_gas.saturating_sub(gas_limit);
+                return call_opcode::REVERT_RETURN_CODE;
+            }
 
-                let call_frame = CallFrame {
-                    caller: new_frame_caller,
-                    ctx_is_static: is_static,
-                    ..Default::default()
-                };
+            // The value transfer is undone along with the callee's changes if it fails
+            let checkpoint = self.journal.checkpoint();
+            if transfers_value {
+                self.journal.set_balance(&this_address, balance - value);
+                increase_balance(&mut self.journal, &callee_address, value);
+            }
 
-                let journal = self.journal.eject_base();
+            let result = match precompile(&callee_address, spec_id) {
+                Some(run_precompile) => {
+                    let mut gas_used = 0;
+                    match run_precompile(&calldata, gas_limit, &mut gas_used) {
+                        Ok(output) => ExecutionResult::Success {
+                            reason: SuccessReason::Return,
+                            gas_used,
+                            gas_refunded: 0,
+                            logs: vec![],
+                            output: Output::Call(output),
+                        },
+                        // Running an unimplemented precompile would give a wrong result,
+                        // so the transaction is aborted instead
+                        Err(PrecompileError::NotImplemented) => {
+                            self.journal.set_error(EVMError::Precompile(format!(
+                                "precompile at {callee_address:?} is not implemented"
+                            )));
+                            self.halt(HaltReason::FatalExternalError);
+                            ExecutionResult::Halt {
+                                reason: HaltReason::FatalExternalError,
+                                gas_used: gas_limit,
+                            }
+                        }
+                        // A failing precompile consumes all the gas sent to it
+                        Err(error) => ExecutionResult::Halt {
+                            reason: match error {
+                                PrecompileError::OutOfGas => {
+                                    HaltReason::OutOfGas(OutOfGasError::Precompile)
+                                }
+                                PrecompileError::InvalidInput | PrecompileError::NotImplemented => {
+                                    HaltReason::PrecompileError
+                                }
+                            },
+                            gas_used: gas_limit,
+                        },
+                    }
+                }
+                None => {
+                    // CALLCODE and DELEGATECALL run the callee's code in this frame's context
+                    let (address, caller, value) = match call_type {
+                        CallType::Call | CallType::StaticCall => {
+                            (callee_address, this_address, value)
+                        }
+                        CallType::CallCode => (this_address, this_address, value),
+                        CallType::DelegateCall => {
+                            (this_address, self.call_frame.caller, self.call_frame.value)
+                        }
+                    };
+                    let bytecode = self.journal.code_by_address(&callee_address);
+
+                    let call_frame = CallFrame {
+                        code_address: callee_address,
+                        address,
+                        caller,
+                        value,
+                        calldata,
+                        bytecode,
+                        gas_limit,
+                        depth: self.call_frame.depth + 1,
+                        ctx_is_static: self.call_frame.ctx_is_static
+                            || call_type == CallType::StaticCall,
+                    };
+                    self.execute_subcontext(call_frame)
+                }
+            };
 
-                let mut context = SyscallContext::new(env.clone(), journal, call_frame);
-                let executor = Executor::new(&module, &context, OptLevel::Aggressive);
+            // The gas the callee didn't use is given back, including what's left of the stipend
+            *consumed_gas = (*consumed_gas + result.gas_used()).saturating_sub(gas_limit);
+            let return_code = if result.is_success() {
+                self.journal.checkpoint_commit(checkpoint);
+                call_opcode::SUCCESS_RETURN_CODE
+            } else {
+                self.journal.checkpoint_revert(checkpoint);
+                call_opcode::REVERT_RETURN_CODE
+            };
 
-                executor.execute(&mut context, env.tx.gas_limit);
+            let return_data = result.into_output().unwrap_or_default();
+            let copy_size = ret.len().min(return_data.len());
+            self.inner_context.memory[ret.start..ret.start + copy_size]
+                .copy_from_slice(&return_data[..copy_size]);
+            self.inner_context.last_call_return_data = return_data.to_vec();
 
-                let result = context.get_result().unwrap().result;
+            return_code
+        })
+    }
 
-                let unused_gas = gas_to_send - result.gas_used();
-                *consumed_gas -= unused_gas;
-                *consumed_gas -= result.gas_refunded();
-                let return_code = if result.is_success() {
-                    self.journal.extend_from_successful(context.journal);
-                    call_opcode::SUCCESS_RETURN_CODE
-                } else {
-                    //TODO: If we revert, should we still send the value to the called contract?
-                    self.journal.extend_from_reverted(context.journal);
-                    call_opcode::REVERT_RETURN_CODE
-                };
-                let output = result.into_output().unwrap_or_default();
-                (return_code, output)
+    /// Compiles and executes the code of `call_frame` as a nested frame sharing this frame's
+    /// environment and journal.
+    ///
+    /// The caller is responsible for committing or reverting the frame's changes.
+    fn execute_subcontext(&mut self, call_frame: CallFrame) -> ExecutionResult {
+        let env = self.env.clone();
+        let journal = &mut self.journal;
+        let executor_cache = Arc::clone(&self.executor_cache);
+        let gas_limit = call_frame.gas_limit;
+        let result = with_stack_segment(call_frame.depth, move || {
+            let mut context = SyscallContext::new(env, std::mem::take(journal), call_frame);
+            context.executor_cache = Arc::clone(&executor_cache);
+            let execution = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                executor_cache.execute(&mut context, gas_limit)
+            }));
+            let result = match execution {
+                Ok(Ok(_)) => context.execution_result(),
+                Ok(Err(err)) => {
+                    context.journal.set_error(err.into());
+                    ExecutionResult::Halt {
+                        reason: HaltReason::OpcodeNotFound,
+                        gas_used: gas_limit,
+                    }
+                }
+                // The journal must be given back even if the frame panics, so the
+                // transaction is aborted instead of going on with an empty state
+                Err(_) => {
+                    context
+                        .journal
+                        .set_error(EVMError::Custom("a nested frame panicked".into()));
+                    ExecutionResult::Halt {
+                        reason: HaltReason::FatalExternalError,
+                        gas_used: gas_limit,
+                    }
+                }
+            };
+            *journal = context.journal;
+            result
+        });
+        // Failing to get a stack to run on depends on the host, so it aborts the transaction
+        result.unwrap_or_else(|err| {
+            self.journal.set_error(EVMError::Custom(format!(
+                "couldn't run the frame on a new stack segment: {err}"
+            )));
+            self.halt(HaltReason::FatalExternalError);
+            ExecutionResult::Halt {
+                reason: HaltReason::FatalExternalError,
+                gas_used: gas_limit,
             }
-        };
-
-        //TODO: This copying mechanism may be improved with a safe copy_from_slice which would
-        //reduce the need of calling return_data.to_vec()
-        self.call_frame.last_call_return_data.clear();
-        self.call_frame
-            .last_call_return_data
-            .clone_from(&return_data.to_vec());
-        Self::copy_exact(
-            &mut self.inner_context.memory,
-            &return_data,
-            ret_offset,
-            0,
-            ret_size,
-        );
-
-        return_code
+        })
     }
 
-    fn copy_exact(
-        target: &mut [u8],
-        source: &[u8],
-        target_offset: u32,
-        source_offset: u32,
-        size: u32,
-    ) {
-        // Convert u32 to usize
-        let target_offset = target_offset as usize;
-        let source_offset = source_offset as usize;
-        let size = size as usize;
-
-        // Check if the offsets are within their respective slices
-        if size + target_offset > target.len() {
-            eprintln!("ERROR: Specified target offset and size are bigger than target len");
-            return;
+    /// Halts the frame after a memory operation failed.
+    ///
+    /// Going over the memory limit runs out of gas. Failing to reserve the memory
+    /// depends on the host rather than on the transaction, so it aborts the transaction.
+    fn memory_error(&mut self, error: MemoryError) {
+        match error {
+            MemoryError::SizeTooBig(_) => {
+                self.halt(HaltReason::OutOfGas(OutOfGasError::MemoryLimit));
+            }
+            MemoryError::ReservationFailed => {
+                self.journal.set_error(error.into());
+                self.halt(HaltReason::FatalExternalError);
+            }
         }
+    }
 
-        if size + source_offset > source.len() {
-            eprintln!("ERROR: Specified source offset and size are bigger than source len");
-            return;
+    fn account_access_cost(&mut self, address: &Address) -> u64 {
+        if self.journal.access_account(address) {
+            gas_cost::COLD_ACCOUNT_ACCESS_COST as u64
+        } else {
+            gas_cost::WARM_STORAGE_READ_COST as u64
         }
+    }
 
-        // Calculate the actual number of bytes we can copy
-        let available_target_space = target.len() - target_offset;
-        let available_source_bytes = source.len() - source_offset;
-        let bytes_to_copy = size.min(available_target_space).min(available_source_bytes);
-
-        // Perform the copy
-        target[target_offset..target_offset + bytes_to_copy]
-            .copy_from_slice(&source[source_offset..source_offset + bytes_to_copy]);
+    /// Copies `source[source_offset..]` into `target`, filling the rest of it with zeros.
+    fn copy_padded(target: &mut [u8], source: &[u8], source_offset: usize) {
+        let source = source.get(source_offset..).unwrap_or_default();
+        let copy_size = source.len().min(target.len());
+        target[..copy_size].copy_from_slice(&source[..copy_size]);
+        target[copy_size..].fill(0);
     }
 
     pub extern "C" fn store_in_selfbalance_ptr(&mut self, balance: &mut U256) {
-        let account = match self.env.tx.transact_to {
-            TransactTo::Call(address) => self.journal.get_account(&address).unwrap_or_default(),
-            TransactTo::Create => AccountInfo::default(), //This branch should never happen
-        };
-        balance.hi = (account.balance >> 128).low_u128();
-        balance.lo = account.balance.low_u128();
+        guard_syscall!(self, {
+            let account = self
+                .journal
+                .get_account(&self.call_frame.address)
+                .unwrap_or_default();
+            balance.hi = (account.balance >> 128).low_u128();
+            balance.lo = account.balance.low_u128();
+        })
     }
 
     pub extern "C" fn keccak256_hasher(&mut self, offset: u32, size: u32, hash_ptr: &mut U256) {
-        let offset = offset as usize;
-        let size = size as usize;
-        let data = &self.inner_context.memory[offset..offset + size];
-        let mut hasher = Keccak256::new();
-        hasher.update(data);
-        let result = hasher.finalize();
-        *hash_ptr = U256::from_fixed_be_bytes(result.into());
+        guard_syscall!(self, {
+            let Some(range) = self.memory_range(offset, size) else {
+                return;
+            };
+            let data = &self.inner_context.memory[range];
+            let mut hasher = Keccak256::new();
+            hasher.update(data);
+            let result = hasher.finalize();
+            *hash_ptr = U256::from_fixed_be_bytes(result.into());
+        })
     }
 
     pub extern "C" fn store_in_callvalue_ptr(&self, value: &mut U256) {
-        let aux = &self.env.tx.value;
-        value.lo = aux.low_u128();
-        value.hi = (aux >> 128).low_u128();
+        guard_syscall!(self, {
+            let aux = &self.call_frame.value;
+            value.lo = aux.low_u128();
+            value.hi = (aux >> 128).low_u128();
+        })
     }
 
     pub extern "C" fn store_in_blobbasefee_ptr(&self, value: &mut u128) {
-        *value = self.env.block.blob_gasprice.unwrap_or_default();
+        guard_syscall!(self, {
+            *value = self.env.block.blob_gasprice.unwrap_or_default();
+        })
     }
 
-    pub extern "C" fn get_gaslimit(&self) -> u64 {
-        self.env.tx.gas_limit
+    pub extern "C" fn store_in_gaslimit_ptr(&self, value: &mut U256) {
+        guard_syscall!(self, {
+            let aux = &self.env.block.gas_limit;
+            value.lo = aux.low_u128();
+            value.hi = (aux >> 128).low_u128();
+        })
     }
 
     pub extern "C" fn store_in_caller_ptr(&self, value: &mut U256) {
-        value.copy_from(&self.call_frame.caller);
+        guard_syscall!(self, {
+            value.copy_from(&self.call_frame.caller);
+        })
     }
 
     pub extern "C" fn store_in_gasprice_ptr(&self, value: &mut U256) {
-        let aux = &self.env.tx.gas_price;
-        value.lo = aux.low_u128();
-        value.hi = (aux >> 128).low_u128();
+        guard_syscall!(self, {
+            let aux = &self.env.effective_gas_price();
+            value.lo = aux.low_u128();
+            value.hi = (aux >> 128).low_u128();
+        })
     }
 
     pub extern "C" fn get_chainid(&self) -> u64 {
-        self.env.cfg.chain_id
+        guard_syscall!(self, { self.env.cfg.chain_id })
     }
 
     pub extern "C" fn get_calldata_ptr(&mut self) -> *const u8 {
-        self.env.tx.data.as_ptr()
+        guard_syscall!(self, std::ptr::null(), {
+            self.call_frame.calldata.as_ptr()
+        })
     }
 
     pub extern "C" fn get_calldata_size_syscall(&self) -> u32 {
-        self.env.tx.data.len() as u32
+        guard_syscall!(self, { self.call_frame.calldata.len() as u32 })
     }
 
-    pub extern "C" fn get_origin(&self, address: &mut U256) {
-        let aux = &self.env.tx.caller;
-        address.copy_from(aux);
+    /// Returns the error status of the syscalls made by the frame: 1 if one of them
+    /// failed and halted it, 0 otherwise.
+    ///
+    /// The generated code checks it after every syscall, so a halted frame stops
+    /// running right away.
+    pub extern "C" fn has_halted(&self) -> u8 {
+        self.inner_context.halt_reason.get().is_some() as u8
     }
 
-    pub extern "C" fn extend_memory(&mut self, new_size: u32) -> *mut u8 {
-        let new_size = new_size as usize;
-        if new_size <= self.inner_context.memory.len() {
-            return self.inner_context.memory.as_mut_ptr();
-        }
-        match self
-            .inner_context
-            .memory
-            .try_reserve(new_size - self.inner_context.memory.len())
-        {
-            Ok(()) => {
-                self.inner_context.memory.resize(new_size, 0);
-                self.inner_context.memory.as_mut_ptr()
+    pub extern "C" fn is_static(&self) -> u8 {
+        guard_syscall!(self, { self.call_frame.ctx_is_static as u8 })
+    }
+
+    pub extern "C" fn get_memory_limit(&self) -> u64 {
+        guard_syscall!(self, { self.env.cfg.memory_limit })
+    }
+
+    pub extern "C" fn get_origin(&self, address: &mut U256) {
+        guard_syscall!(self, {
+            let aux = &self.env.tx.caller;
+            address.copy_from(aux);
+        })
+    }
+
+    pub extern "C" fn get_memory_ptr(&mut self) -> *mut u8 {
+        guard_syscall!(self, std::ptr::null_mut(), {
+            match self.inner_context.memory.reserve() {
+                Ok(base) => base.as_ptr(),
+                Err(error) => {
+                    self.memory_error(error);
+                    std::ptr::null_mut()
+                }
             }
-            // TODO: use tracing here
-            Err(err) => {
-                eprintln!("Failed to reserve memory: {err}");
-                std::ptr::null_mut()
+        })
+    }
+
+    pub extern "C" fn extend_memory(&mut self, new_size: u32) {
+        guard_syscall!(self, {
+            if new_size as u64 > self.env.cfg.memory_limit {
+                self.halt(HaltReason::OutOfGas(OutOfGasError::MemoryLimit));
+            } else if let Err(error) = self.inner_context.memory.extend(new_size as usize) {
+                self.memory_error(error);
             }
-        }
+        })
     }
 
     pub extern "C" fn copy_code_to_memory(
@@ -577,114 +912,84 @@ impl<'c> SyscallContext<'c> {
         size: u32,
         dest_offset: u32,
     ) {
-        let code_size = self.inner_context.program.len();
-        // cast everything to `usize`
-        let code_offset = code_offset as usize;
-        let size = size as usize;
-        let dest_offset = dest_offset as usize;
-
-        // adjust the size so it does not go out of bounds
-        let size: usize = if code_offset + size > code_size {
-            code_size.saturating_sub(code_offset)
-        } else {
-            size
-        };
-
-        let Some(code_slice) = &self
-            .inner_context
-            .program
-            .get(code_offset..code_offset + size)
-        else {
-            eprintln!("Error on copy_code_to_memory");
-            return; // TODO: fix bug with code indexes
-        };
-        // copy the program into memory
-        self.inner_context.memory[dest_offset..dest_offset + size].copy_from_slice(code_slice);
+        guard_syscall!(self, {
+            let Some(dest) = self.memory_range(dest_offset, size) else {
+                return;
+            };
+            Self::copy_padded(
+                &mut self.inner_context.memory[dest],
+                &self.call_frame.bytecode,
+                code_offset as usize,
+            );
+        })
     }
 
-    pub extern "C" fn read_storage(&mut self, stg_key: &U256, stg_value: &mut U256) {
-        let address = self.env.tx.get_address();
+    pub extern "C" fn read_storage(&mut self, stg_key: &U256, stg_value: &mut U256) -> u64 {
+        guard_syscall!(self, {
+            let address = self.call_frame.address;
 
-        let key = stg_key.to_primitive_u256();
+            let key = stg_key.to_primitive_u256();
+            let is_cold = self.journal.access_storage_key(&address, &key);
 
-        // Read value from journaled_storage. If there isn't one, then read from db
-        let result = self
-            .journal
-            .read_storage(&address, &key)
-            .unwrap_or_default()
-            .present_value;
+            // Read value from journaled_storage. If there isn't one, then read from db
+            let result = self
+                .journal
+                .read_storage(&address, &key)
+                .unwrap_or_default()
+                .present_value;
+
+            stg_value.hi = (result >> 128).low_u128();
+            stg_value.lo = result.low_u128();
 
-        stg_value.hi = (result >> 128).low_u128();
-        stg_value.lo = result.low_u128();
+            let spec_id = self.env.cfg.spec_id;
+            if !spec_id.is_enabled_in(SpecId::Berlin) {
+                gas_cost::sload_cost(spec_id) as u64
+            } else if is_cold {
+                gas_cost::COLD_SLOAD_COST as u64
+            } else {
+                gas_cost::WARM_STORAGE_READ_COST as u64
+            }
+        })
     }
 
     pub extern "C" fn write_storage(&mut self, stg_key: &U256, stg_value: &mut U256) -> i64 {
-        let key = stg_key.to_primitive_u256();
-        let value = stg_value.to_primitive_u256();
-        // TODO: Check if this case is ok. Can storage be written on Create?
-        let TransactTo::Call(address) = self.env.tx.transact_to else {
-            return 0;
-        };
-
-        let is_cold = !self.journal.key_is_warm(&address, &key);
-        let slot = self.journal.read_storage(&address, &key);
-        self.journal.write_storage(&address, key, value);
-
-        let (original, current) = match slot {
-            Some(slot) => (slot.original_value, slot.present_value),
-            None => (value, value),
-        };
-
-        // Compute the gas cost
-        let mut gas_cost: i64 = if original.is_zero() && current.is_zero() && current != value {
-            20_000
-        } else if original == current && current != value {
-            2_900
-        } else {
-            100
-        };
-
-        // When the value is cold, add extra 2100 gas
-        if is_cold {
-            gas_cost += 2_100;
-        }
+        guard_syscall!(self, {
+            let key = stg_key.to_primitive_u256();
+            let value = stg_value.to_primitive_u256();
+            let address = self.call_frame.address;
+
+            let is_cold = self.journal.access_storage_key(&address, &key);
+            let slot = self.journal.read_storage(&address, &key);
+            if let Err(error) = self.journal.write_storage(&address, key, value) {
+                self.journal.set_error(error.into());
+                self.halt(HaltReason::FatalExternalError);
+                return 0;
+            }
 
-        // Compute the gas refund
-        let reset_non_zero_to_zero = !original.is_zero() && !current.is_zero() && value.is_zero();
-        let undo_reset_to_zero = !original.is_zero() && current.is_zero() && !value.is_zero();
-        let undo_reset_to_zero_into_original = undo_reset_to_zero && (value == original);
-        let reset_back_to_zero = original.is_zero() && !current.is_zero() && value.is_zero();
-        let reset_to_original = (current != value) && (original == value);
-
-        let gas_refund: i64 = if reset_non_zero_to_zero {
-            4_800
-        } else if undo_reset_to_zero_into_original {
-            -2_000
-        } else if undo_reset_to_zero {
-            -4_800
-        } else if reset_back_to_zero {
-            19_900
-        } else if reset_to_original {
-            2_800
-        } else {
-            0
-        };
+            let (original, current) = match slot {
+                Some(slot) => (slot.original_value, slot.present_value),
+                None => (value, value),
+            };
 
-        if gas_refund > 0 {
-            self.inner_context.gas_refund += gas_refund as u64;
-        } else {
-            self.inner_context.gas_refund -= gas_refund.unsigned_abs();
-        };
+            let spec_id = self.env.cfg.spec_id;
+            let gas_cost = sstore_cost(spec_id, original, current, value, is_cold);
+            let gas_refund = sstore_refund(spec_id, original, current, value);
+            self.journal.record_refund(gas_refund);
 
-        gas_cost
+            gas_cost
+        })
     }
 
     pub extern "C" fn append_log(&mut self, offset: u32, size: u32) {
-        self.create_log(offset, size, vec![]);
+        guard_syscall!(self, {
+            self.create_log(offset, size, vec![]);
+        })
     }
 
     pub extern "C" fn append_log_with_one_topic(&mut self, offset: u32, size: u32, topic: &U256) {
-        self.create_log(offset, size, vec![*topic]);
+        guard_syscall!(self, {
+            self.create_log(offset, size, vec![*topic]);
+        })
     }
 
     pub extern "C" fn append_log_with_two_topics(
@@ -694,7 +999,9 @@ impl<'c> SyscallContext<'c> {
         topic1: &U256,
         topic2: &U256,
     ) {
-        self.create_log(offset, size, vec![*topic1, *topic2]);
+        guard_syscall!(self, {
+            self.create_log(offset, size, vec![*topic1, *topic2]);
+        })
     }
 
     pub extern "C" fn append_log_with_three_topics(
@@ -705,7 +1012,9 @@ impl<'c> SyscallContext<'c> {
         topic2: &U256,
         topic3: &U256,
     ) {
-        self.create_log(offset, size, vec![*topic1, *topic2, *topic3]);
+        guard_syscall!(self, {
+            self.create_log(offset, size, vec![*topic1, *topic2, *topic3]);
+        })
     }
 
     pub extern "C" fn append_log_with_four_topics(
@@ -717,111 +1026,145 @@ impl<'c> SyscallContext<'c> {
         topic3: &U256,
         topic4: &U256,
     ) {
-        self.create_log(offset, size, vec![*topic1, *topic2, *topic3, *topic4]);
+        guard_syscall!(self, {
+            self.create_log(offset, size, vec![*topic1, *topic2, *topic3, *topic4]);
+        })
     }
 
     pub extern "C" fn get_block_number(&self, number: &mut U256) {
-        let block_number = self.env.block.number;
+        guard_syscall!(self, {
+            let block_number = self.env.block.number;
 
-        number.hi = (block_number >> 128).low_u128();
-        number.lo = block_number.low_u128();
+            number.hi = (block_number >> 128).low_u128();
+            number.lo = block_number.low_u128();
+        })
     }
 
     pub extern "C" fn get_block_hash(&mut self, number: &mut U256) {
-        let number_as_u256 = number.to_primitive_u256();
-
-        // If number is not in the valid range (last 256 blocks), return zero.
-        let hash = if number_as_u256 < self.env.block.number.saturating_sub(EU256::from(256))
-            || number_as_u256 >= self.env.block.number
-        {
-            // TODO: check if this is necessary. Db should only contain last 256 blocks, so number check would not be needed.
-            B256::zero()
-        } else {
-            self.journal.get_block_hash(&number_as_u256)
-        };
+        guard_syscall!(self, {
+            let number_as_u256 = number.to_primitive_u256();
+
+            // If number is not in the valid range (last 256 blocks), return zero.
+            let hash = if number_as_u256 < self.env.block.number.saturating_sub(EU256::from(256))
+                || number_as_u256 >= self.env.block.number
+            {
+                // TODO: check if this is necessary. Db should only contain last 256 blocks, so number check would not be needed.
+                B256::zero()
+            } else {
+                self.journal.get_block_hash(&number_as_u256)
+            };
 
-        let (hi, lo) = hash.as_bytes().split_at(16);
-        number.lo = u128::from_be_bytes(lo.try_into().unwrap());
-        number.hi = u128::from_be_bytes(hi.try_into().unwrap());
+            let (hi, lo) = hash.as_bytes().split_at(16);
+            number.lo = u128::from_be_bytes(lo.try_into().unwrap());
+            number.hi = u128::from_be_bytes(hi.try_into().unwrap());
+        })
     }
 
     /// Receives a memory offset and size, and a vector of topics.
-    /// Creates a Log with topics and data equal to memory[offset..offset + size]
-    /// and pushes it to the logs vector.
+    /// Creates a Log with topics and data equal to memory[offset..offset + size],
+    /// tagged with the address of the executing contract, and adds it to the journal.
     fn create_log(&mut self, offset: u32, size: u32, topics: Vec<U256>) {
-        let offset = offset as usize;
-        let size = size as usize;
-        let data: Vec<u8> = self.inner_context.memory[offset..offset + size].into();
+        let Some(range) = self.memory_range(offset, size) else {
+            return;
+        };
+        let data: Vec<u8> = self.inner_context.memory[range].into();
 
-        let log = LogData { data, topics };
-        self.inner_context.logs.push(log);
+        let log = Log {
+            address: self.call_frame.address,
+            data: LogData { data, topics },
+        };
+        self.journal.add_log(log);
     }
 
     pub extern "C" fn get_codesize_from_address(&mut self, address: &U256) -> u64 {
-        //TODO: Here we are returning 0 if a Database error occurs. Check this
-        self.journal.code_by_address(&Address::from(address)).len() as _
+        guard_syscall!(self, {
+            // A database error is recorded in the journal, aborting the transaction
+            self.journal.code_by_address(&Address::from(address)).len() as _
+        })
+    }
+
+    /// Marks the address as accessed and returns the gas cost of the access (EIP-2929)
+    pub extern "C" fn access_account(&mut self, address: &U256) -> u64 {
+        guard_syscall!(self, { self.account_access_cost(&Address::from(address)) })
     }
 
     pub extern "C" fn get_address_ptr(&mut self) -> *const u8 {
-        self.env.tx.get_address().to_fixed_bytes().as_ptr()
+        guard_syscall!(self, std::ptr::null(), { self.call_frame.address.as_ptr() })
     }
 
     pub extern "C" fn get_prevrandao(&self, prevrandao: &mut U256) {
-        let randao = self.env.block.prevrandao.unwrap_or_default();
-        *prevrandao = U256::from_fixed_be_bytes(randao.into());
+        guard_syscall!(self, {
+            // Before the merge, the opcode returned the block difficulty instead
+            if !self.env.cfg.spec_id.is_enabled_in(SpecId::Merge) {
+                let difficulty = self.env.block.difficulty;
+                prevrandao.hi = (difficulty >> 128).low_u128();
+                prevrandao.lo = difficulty.low_u128();
+                return;
+            }
+            let randao = self.env.block.prevrandao.unwrap_or_default();
+            *prevrandao = U256::from_fixed_be_bytes(randao.into());
+        })
     }
 
     pub extern "C" fn get_coinbase_ptr(&self) -> *const u8 {
-        self.env.block.coinbase.as_ptr()
+        guard_syscall!(self, std::ptr::null(), { self.env.block.coinbase.as_ptr() })
     }
 
     pub extern "C" fn store_in_timestamp_ptr(&self, value: &mut U256) {
-        let aux = &self.env.block.timestamp;
-        value.lo = aux.low_u128();
-        value.hi = (aux >> 128).low_u128();
+        guard_syscall!(self, {
+            let aux = &self.env.block.timestamp;
+            value.lo = aux.low_u128();
+            value.hi = (aux >> 128).low_u128();
+        })
     }
 
     pub extern "C" fn store_in_basefee_ptr(&self, basefee: &mut U256) {
-        basefee.hi = (self.env.block.basefee >> 128).low_u128();
-        basefee.lo = self.env.block.basefee.low_u128();
+        guard_syscall!(self, {
+            basefee.hi = (self.env.block.basefee >> 128).low_u128();
+            basefee.lo = self.env.block.basefee.low_u128();
+        })
     }
 
     pub extern "C" fn store_in_balance(&mut self, address: &U256, balance: &mut U256) {
-        // addresses longer than 20 bytes should be invalid
-        if (address.hi >> 32) != 0 {
-            balance.hi = 0;
-            balance.lo = 0;
-        } else {
-            let address_hi_slice = address.hi.to_be_bytes();
-            let address_lo_slice = address.lo.to_be_bytes();
-
-            let address_slice = [&address_hi_slice[12..16], &address_lo_slice[..]].concat();
-
-            let address = Address::from_slice(&address_slice);
-
-            match self.journal.get_account(&address) {
-                Some(a) => {
-                    balance.hi = (a.balance >> 128).low_u128();
-                    balance.lo = a.balance.low_u128();
-                }
-                None => {
-                    balance.hi = 0;
-                    balance.lo = 0;
-                }
-            };
-        }
+        guard_syscall!(self, {
+            // addresses longer than 20 bytes should be invalid
+            if (address.hi >> 32) != 0 {
+                balance.hi = 0;
+                balance.lo = 0;
+            } else {
+                let address_hi_slice = address.hi.to_be_bytes();
+                let address_lo_slice = address.lo.to_be_bytes();
+
+                let address_slice = [&address_hi_slice[12..16], &address_lo_slice[..]].concat();
+
+                let address = Address::from_slice(&address_slice);
+
+                match self.journal.get_account(&address) {
+                    Some(a) => {
+                        balance.hi = (a.balance >> 128).low_u128();
+                        balance.lo = a.balance.low_u128();
+                    }
+                    None => {
+                        balance.hi = 0;
+                        balance.lo = 0;
+                    }
+                };
+            }
+        })
     }
 
     pub extern "C" fn get_blob_hash_at_index(&mut self, index: &U256, blobhash: &mut U256) {
-        if index.hi != 0 {
-            *blobhash = U256::default();
-            return;
-        }
-        *blobhash = usize::try_from(index.lo)
-            .ok()
-            .and_then(|idx| self.env.tx.blob_hashes.get(idx).cloned())
-            .map(|x| U256::from_fixed_be_bytes(x.into()))
-            .unwrap_or_default();
+        guard_syscall!(self, {
+            if index.hi != 0 {
+                *blobhash = U256::default();
+                return;
+            }
+            *blobhash = usize::try_from(index.lo)
+                .ok()
+                .and_then(|idx| self.env.tx.blob_hashes.get(idx).cloned())
+                .map(|x| U256::from_fixed_be_bytes(x.into()))
+                .unwrap_or_default();
+        })
     }
 
     pub extern "C" fn copy_ext_code_to_memory(
@@ -831,32 +1174,29 @@ impl<'c> SyscallContext<'c> {
         size: u32,
         dest_offset: u32,
     ) {
-        let size = size as usize;
-        let code_offset = code_offset as usize;
-        let dest_offset = dest_offset as usize;
-        let address = Address::from(address_value);
-        // TODO: Check if returning default bytecode on database failure is ok
-        // A silenced error like this may produce unexpected code behaviour
-        let code = self.journal.code_by_address(&address);
-        let code_size = code.len();
-        let code_to_copy_size = code_size.saturating_sub(code_offset);
-        let code_slice = &code[code_offset..code_offset + code_to_copy_size];
-        let padding_size = size - code_to_copy_size;
-        let padding_offset = dest_offset + code_to_copy_size;
-        // copy the program into memory
-        self.inner_context.memory[dest_offset..dest_offset + code_to_copy_size]
-            .copy_from_slice(code_slice);
-        // pad the left part with zero
-        self.inner_context.memory[padding_offset..padding_offset + padding_size].fill(0);
+        guard_syscall!(self, {
+            let address = Address::from(address_value);
+            let code = self.journal.code_by_address(&address);
+            let Some(dest) = self.memory_range(dest_offset, size) else {
+                return;
+            };
+            Self::copy_padded(
+                &mut self.inner_context.memory[dest],
+                &code,
+                code_offset as usize,
+            );
+        })
     }
 
     pub extern "C" fn get_code_hash(&mut self, address: &mut U256) {
-        let hash = match self.journal.get_account(&Address::from(address as &U256)) {
-            Some(account_info) => account_info.code_hash,
-            _ => B256::zero(),
-        };
+        guard_syscall!(self, {
+            let hash = match self.journal.get_account(&Address::from(address as &U256)) {
+                Some(account_info) => account_info.code_hash,
+                _ => B256::zero(),
+            };
 
-        *address = U256::from_fixed_be_bytes(hash.to_fixed_bytes());
+            *address = U256::from_fixed_be_bytes(hash.to_fixed_bytes());
+        })
     }
 
     fn create_aux(
@@ -868,82 +1208,126 @@ impl<'c> SyscallContext<'c> {
         salt: Option<&U256>,
     ) -> u8 {
         let value_as_u256 = value.to_primitive_u256();
-        let offset = offset as usize;
+        let available_gas = *remaining_gas;
+        self.inner_context.last_call_return_data.clear();
         let size = size as usize;
         let minimum_word_size = ((size + 31) / 32) as u64;
-        let sender_address = self.env.tx.get_address();
+        let sender_address = self.call_frame.address;
+        let spec_id = self.env.cfg.spec_id;
 
-        let initialization_bytecode = &self.inner_context.memory[offset..offset + size];
-        let program = Program::from_bytecode(initialization_bytecode);
+        // EIP-3860: Limit and meter initcode
+        let meters_initcode = spec_id.is_enabled_in(SpecId::Shanghai);
+        if meters_initcode && size > self.env.cfg.max_initcode_size() {
+            return 1;
+        }
+        let init_code_cost = if meters_initcode {
+            minimum_word_size * gas_cost::INIT_WORD_COST as u64
+        } else {
+            0
+        };
+        let hash_cost = match salt {
+            Some(_) => minimum_word_size * gas_cost::HASH_WORD_COST as u64,
+            None => 0,
+        };
+        let Some(gas_left) = available_gas.checked_sub(init_code_cost + hash_cost) else {
+            return 1;
+        };
 
-        let sender_account = self.journal.get_account(&sender_address).unwrap();
+        // All but one 64th of the remaining gas is forwarded to the init code (EIP-150)
+        let gas_limit = if spec_id.is_enabled_in(SpecId::Tangerine) {
+            gas_left - gas_left / call_opcode::GAS_CAP_DIVISION_FACTOR
+        } else {
+            gas_left
+        };
 
-        let (dest_addr, hash_cost) = match salt {
-            Some(s) => (
-                compute_contract_address2(
-                    sender_address,
-                    s.to_primitive_u256(),
-                    initialization_bytecode,
-                ),
-                minimum_word_size * gas_cost::HASH_WORD_COST as u64,
-            ),
-            _ => (
-                compute_contract_address(sender_address, sender_account.nonce),
-                0,
+        // Unless stated otherwise, failures return the forwarded gas and push zero
+        *value = U256::zero();
+        *remaining_gas = init_code_cost + hash_cost;
+
+        let sender_account = self
+            .journal
+            .get_account(&sender_address)
+            .unwrap_or_default();
+        if self.call_frame.depth >= self.env.cfg.max_call_depth()
+            || sender_account.balance < value_as_u256
+            || sender_account.nonce == u64::MAX
+        {
+            return 0;
+        }
+
+        let Some(range) = self.memory_range(offset, size as u32) else {
+            return 1;
+        };
+        let initialization_bytecode = Bytes::copy_from_slice(&self.inner_context.memory[range]);
+
+        let dest_addr = match salt {
+            Some(s) => compute_contract_address2(
+                sender_address,
+                s.to_primitive_u256(),
+                &initialization_bytecode,
             ),
+            None => compute_contract_address(sender_address, sender_account.nonce),
         };
 
-        // Check if there is already a contract stored in dest_address
-        if self.journal.get_account(&dest_addr).is_some() {
-            return 1;
-        }
+        // The new address stays warm and the nonce stays bumped even if the creation fails
+        self.journal.access_account(&dest_addr);
+        self.journal
+            .set_nonce(&sender_address, sender_account.nonce + 1);
 
-        // Create subcontext for the initialization code
-        // TODO: Add call depth check
-        let mut new_env = self.env.clone();
-        new_env.tx.transact_to = TransactTo::Call(dest_addr);
-        new_env.tx.gas_limit = *remaining_gas;
-        let call_frame = CallFrame::new(sender_address);
-
-        // Execute initialization code
-        let context = Context::new();
-        let module = context
-            .compile(&program, Default::default())
-            .expect("failed to compile program");
-
-        // NOTE: Here we are not taking into account what happens if the deployment code reverts
-        let ctx_journal = self.journal.eject_base();
-        let mut context = SyscallContext::new(new_env.clone(), ctx_journal, call_frame);
-        let executor = Executor::new(&module, &context, OptLevel::Aggressive);
-        executor.execute(&mut context, new_env.tx.gas_limit);
-        let result = context.get_result().unwrap().result;
-        let bytecode = result.output().cloned().unwrap_or_default();
-
-        self.journal.extend_from_successful(context.journal);
-
-        // Set the gas cost
-        let init_code_cost = minimum_word_size * gas_cost::INIT_WORD_COST as u64;
-        let code_deposit_cost = (bytecode.len() as u64) * gas_cost::BYTE_DEPOSIT_COST as u64;
-        let gas_cost = init_code_cost + code_deposit_cost + hash_cost + result.gas_used()
-            - result.gas_refunded();
-        *remaining_gas = gas_cost;
-
-        // Check if balance is enough
-        let Some(sender_balance) = sender_account.balance.checked_sub(value_as_u256) else {
-            *value = U256::zero();
-            return 0;
+        // A collision consumes the forwarded gas (EIP-684)
+        let dest_balance = match self.journal.get_account(&dest_addr) {
+            Some(dest) if dest.has_code() || dest.nonce != 0 => {
+                *remaining_gas += gas_limit;
+                return 0;
+            }
+            Some(dest) => dest.balance,
+            None => EU256::zero(),
         };
 
-        // Create new contract and update sender account
+        // The account creation and the value transfer are undone if the creation fails
+        let checkpoint = self.journal.checkpoint();
         self.journal
-            .new_contract(dest_addr, bytecode, value_as_u256);
+            .new_account(dest_addr, dest_balance + value_as_u256);
+        // EIP-161: contracts start with nonce one
+        if spec_id.is_enabled_in(SpecId::SpuriousDragon) {
+            self.journal.set_nonce(&dest_addr, 1);
+        }
         self.journal
-            .set_nonce(&sender_address, sender_account.nonce + 1);
-        self.journal.set_balance(&sender_address, sender_balance);
+            .set_balance(&sender_address, sender_account.balance - value_as_u256);
 
-        value.copy_from(&dest_addr);
+        // Create subcontext for the initialization code
+        let call_frame = CallFrame {
+            code_address: dest_addr,
+            address: dest_addr,
+            caller: sender_address,
+            value: value_as_u256,
+            calldata: Bytes::new(),
+            bytecode: initialization_bytecode,
+            gas_limit,
+            depth: self.call_frame.depth + 1,
+            ctx_is_static: false,
+        };
+
+        let result = self.execute_subcontext(call_frame);
+        let result = deposit_code(
+            &mut self.journal,
+            &dest_addr,
+            result,
+            gas_limit,
+            &self.env.cfg,
+        );
 
-        // TODO: add dest_addr as warm in the access list
+        *remaining_gas += result.gas_used();
+        // The return data of a creation is only kept when it reverts
+        if let ExecutionResult::Revert { output, .. } = &result {
+            self.inner_context.last_call_return_data = output.to_vec();
+        }
+        if result.is_success() {
+            self.journal.checkpoint_commit(checkpoint);
+            value.copy_from(&dest_addr);
+        } else {
+            self.journal.checkpoint_revert(checkpoint);
+        }
         0
     }
 
@@ -954,7 +1338,9 @@ impl<'c> SyscallContext<'c> {
         value: &mut U256,
         remaining_gas: &mut u64,
     ) -> u8 {
-        self.create_aux(size, offset, value, remaining_gas, None)
+        guard_syscall!(self, 1, {
+            self.create_aux(size, offset, value, remaining_gas, None)
+        })
     }
 
     pub extern "C" fn create2(
@@ -965,75 +1351,116 @@ impl<'c> SyscallContext<'c> {
         remaining_gas: &mut u64,
         salt: &U256,
     ) -> u8 {
-        self.create_aux(size, offset, value, remaining_gas, Some(salt))
+        guard_syscall!(self, 1, {
+            self.create_aux(size, offset, value, remaining_gas, Some(salt))
+        })
     }
 
     pub extern "C" fn selfdestruct(&mut self, receiver_address: &U256) -> u64 {
-        let sender_address = self.env.tx.get_address();
-        let receiver_address = Address::from(receiver_address);
-
-        let sender_balance = self
-            .journal
-            .get_account(&sender_address)
-            .unwrap_or_default()
-            .balance;
+        guard_syscall!(self, {
+            let sender_address = self.call_frame.address;
+            let receiver_address = Address::from(receiver_address);
+            let receiver_is_cold = self.journal.access_account(&receiver_address);
+            let spec_id = self.env.cfg.spec_id;
+
+            let sender_balance = self
+                .journal
+                .get_account(&sender_address)
+                .unwrap_or_default()
+                .balance;
+
+            let receiver = self.journal.get_account(&receiver_address);
+            let receiver_is_new = receiver.is_none();
+            // EIP-161: sending nothing to a non-existent account doesn't create it
+            let receiver_is_created =
+                !sender_balance.is_zero() || !spec_id.is_enabled_in(SpecId::SpuriousDragon);
+            let receiver_is_empty = match receiver {
+                Some(receiver) => {
+                    let is_empty = receiver.is_empty();
+                    if receiver_address != sender_address && !sender_balance.is_zero() {
+                        self.journal
+                            .set_balance(&receiver_address, receiver.balance + sender_balance);
+                    }
+                    is_empty
+                }
+                None => {
+                    if receiver_is_created {
+                        self.journal
+                            .new_touched_account(receiver_address, sender_balance);
+                    }
+                    true
+                }
+            };
 
-        let receiver_is_empty = match self.journal.get_account(&receiver_address) {
-            Some(receiver) => {
-                let is_empty = receiver.is_empty();
-                self.journal
-                    .set_balance(&receiver_address, receiver.balance + sender_balance);
-                is_empty
+            // EIP-6780: since Cancun, only accounts created in the same transaction are
+            // deleted, and only for them sending the balance to themselves burns it
+            let is_deleted = !spec_id.is_enabled_in(SpecId::Cancun)
+                || self.journal.account_is_created(&sender_address);
+            if receiver_address != sender_address || is_deleted {
+                self.journal.set_balance(&sender_address, EU256::zero());
             }
-            None => {
-                self.journal.new_account(receiver_address, sender_balance);
-                true
+            if is_deleted {
+                // EIP-3529 removed the refund for deleting an account
+                if !spec_id.is_enabled_in(SpecId::London)
+                    && !self.journal.account_is_selfdestructed(&sender_address)
+                {
+                    self.journal.record_refund(gas_cost::SELFDESTRUCT_REFUND);
+                }
+                self.journal
+                    .set_status(&sender_address, AccountStatus::SelfDestructed);
             }
-        };
 
-        self.journal.set_balance(&sender_address, EU256::zero());
+            // EIP-161: only sending a balance to an empty account creates it. Before,
+            // sending to a non-existent account always did, and before EIP-150 it was free
+            let creates_receiver = if spec_id.is_enabled_in(SpecId::SpuriousDragon) {
+                !sender_balance.is_zero() && receiver_is_empty
+            } else {
+                spec_id.is_enabled_in(SpecId::Tangerine) && receiver_is_new
+            };
+            let mut gas_cost = if creates_receiver {
+                gas_cost::SELFDESTRUCT_DYNAMIC_GAS as u64
+            } else {
+                0
+            };
 
-        if self.journal.get_account(&sender_address).is_some() {
-            self.journal
-                .set_status(&sender_address, AccountStatus::SelfDestructed);
-        }
+            if spec_id.is_enabled_in(SpecId::Berlin) && receiver_is_cold {
+                gas_cost += gas_cost::COLD_ACCOUNT_ACCESS_COST as u64;
+            }
 
-        if !sender_balance.is_zero() && receiver_is_empty {
-            gas_cost::SELFDESTRUCT_DYNAMIC_GAS as u64
-        } else {
-            0
-        }
-        // TODO: add gas cost for cold addresses
+            gas_cost
+        })
     }
 
     pub extern "C" fn read_transient_storage(&mut self, stg_key: &U256, stg_value: &mut U256) {
-        let key = stg_key.to_primitive_u256();
-        let address = self.env.tx.get_address();
+        guard_syscall!(self, {
+            let key = stg_key.to_primitive_u256();
+            let address = self.call_frame.address;
 
-        let result = self
-            .transient_storage
-            .get(&(address, key))
-            .cloned()
-            .unwrap_or(EU256::zero());
+            let result = self.journal.read_transient_storage(&address, &key);
 
-        stg_value.hi = (result >> 128).low_u128();
-        stg_value.lo = result.low_u128();
+            stg_value.hi = (result >> 128).low_u128();
+            stg_value.lo = result.low_u128();
+        })
     }
 
     pub extern "C" fn write_transient_storage(&mut self, stg_key: &U256, stg_value: &mut U256) {
-        let address = self.env.tx.get_address();
+        guard_syscall!(self, {
+            let address = self.call_frame.address;
 
-        let key = stg_key.to_primitive_u256();
-        let value = stg_value.to_primitive_u256();
-        self.transient_storage.insert((address, key), value);
+            let key = stg_key.to_primitive_u256();
+            let value = stg_value.to_primitive_u256();
+            self.journal.write_transient_storage(&address, key, value);
+        })
     }
 }
 
 pub mod symbols {
-    // Global variables
-    pub const CONTEXT_IS_STATIC: &str = "evm_mlir__context_is_static";
     // Syscalls
+    pub const HAS_HALTED: &str = "evm_mlir__has_halted";
+    pub const IS_STATIC: &str = "evm_mlir__is_static";
+    pub const GET_MEMORY_LIMIT: &str = "evm_mlir__get_memory_limit";
     pub const WRITE_RESULT: &str = "evm_mlir__write_result";
+    pub const GET_MEMORY_PTR: &str = "evm_mlir__get_memory_ptr";
     pub const EXTEND_MEMORY: &str = "evm_mlir__extend_memory";
     pub const KECCAK256_HASHER: &str = "evm_mlir__keccak256_hasher";
     pub const STORAGE_WRITE: &str = "evm_mlir__write_storage";
@@ -1046,9 +1473,10 @@ pub mod symbols {
     pub const GET_CALLDATA_PTR: &str = "evm_mlir__get_calldata_ptr";
     pub const GET_CALLDATA_SIZE: &str = "evm_mlir__get_calldata_size";
     pub const GET_CODESIZE_FROM_ADDRESS: &str = "evm_mlir__get_codesize_from_address";
+    pub const ACCESS_ACCOUNT: &str = "evm_mlir__access_account";
     pub const COPY_CODE_TO_MEMORY: &str = "evm_mlir__copy_code_to_memory";
     pub const GET_ADDRESS_PTR: &str = "evm_mlir__get_address_ptr";
-    pub const GET_GASLIMIT: &str = "evm_mlir__get_gaslimit";
+    pub const STORE_IN_GASLIMIT_PTR: &str = "evm_mlir__store_in_gaslimit_ptr";
     pub const STORE_IN_CALLVALUE_PTR: &str = "evm_mlir__store_in_callvalue_ptr";
     pub const STORE_IN_BLOBBASEFEE_PTR: &str = "evm_mlir__store_in_blobbasefee_ptr";
     pub const GET_BLOB_HASH_AT_INDEX: &str = "evm_mlir__get_blob_hash_at_index";
@@ -1076,66 +1504,78 @@ pub mod symbols {
     pub const SELFDESTRUCT: &str = "evm_mlir__selfdestruct";
 }
 
-impl<'c> SyscallContext<'c> {
+impl<'c, DB> SyscallContext<'c, DB>
+where
+    DB: Database + Send,
+    DB::Error: Send,
+{
     /// Registers all the syscalls as symbols in the execution engine
     ///
     /// This allows the generated code to call the syscalls by name.
     pub fn register_symbols(&self, engine: &ExecutionEngine) {
         unsafe {
-            // Global variables
+            // Syscalls
             engine.register_symbol(
-                symbols::CONTEXT_IS_STATIC,
-                &self.call_frame.ctx_is_static as *const bool as *mut (),
+                symbols::HAS_HALTED,
+                Self::has_halted as *const fn(*mut c_void) as *mut (),
+            );
+            engine.register_symbol(
+                symbols::IS_STATIC,
+                Self::is_static as *const fn(*mut c_void) as *mut (),
+            );
+            engine.register_symbol(
+                symbols::GET_MEMORY_LIMIT,
+                Self::get_memory_limit as *const fn(*mut c_void) as *mut (),
             );
-            // Syscalls
             engine.register_symbol(
                 symbols::WRITE_RESULT,
-                SyscallContext::write_result as *const fn(*mut c_void, u32, u32, u64, u8)
-                    as *mut (),
+                Self::write_result as *const fn(*mut c_void, u32, u32, u64, u8) as *mut (),
             );
             engine.register_symbol(
                 symbols::KECCAK256_HASHER,
-                SyscallContext::keccak256_hasher as *const fn(*mut c_void, u32, u32, *const U256)
-                    as *mut (),
+                Self::keccak256_hasher as *const fn(*mut c_void, u32, u32, *const U256) as *mut (),
+            );
+            engine.register_symbol(
+                symbols::GET_MEMORY_PTR,
+                Self::get_memory_ptr as *const fn(*mut c_void) as *mut (),
             );
             engine.register_symbol(
                 symbols::EXTEND_MEMORY,
-                SyscallContext::extend_memory as *const fn(*mut c_void, u32) as *mut (),
+                Self::extend_memory as *const fn(*mut c_void, u32) as *mut (),
             );
             engine.register_symbol(
                 symbols::STORAGE_READ,
-                SyscallContext::read_storage as *const fn(*const c_void, *const U256, *mut U256)
+                Self::read_storage as *const fn(*const c_void, *const U256, *mut U256) -> u64
                     as *mut (),
             );
             engine.register_symbol(
                 symbols::STORAGE_WRITE,
-                SyscallContext::write_storage as *const fn(*mut c_void, *const U256, *const U256)
-                    as *mut (),
+                Self::write_storage as *const fn(*mut c_void, *const U256, *const U256) as *mut (),
             );
             engine.register_symbol(
                 symbols::APPEND_LOG,
-                SyscallContext::append_log as *const fn(*mut c_void, u32, u32) as *mut (),
+                Self::append_log as *const fn(*mut c_void, u32, u32) as *mut (),
             );
             engine.register_symbol(
                 symbols::APPEND_LOG_ONE_TOPIC,
-                SyscallContext::append_log_with_one_topic
-                    as *const fn(*mut c_void, u32, u32, *const U256) as *mut (),
+                Self::append_log_with_one_topic as *const fn(*mut c_void, u32, u32, *const U256)
+                    as *mut (),
             );
             engine.register_symbol(
                 symbols::APPEND_LOG_TWO_TOPICS,
-                SyscallContext::append_log_with_two_topics
+                Self::append_log_with_two_topics
                     as *const fn(*mut c_void, u32, u32, *const U256, *const U256)
                     as *mut (),
             );
             engine.register_symbol(
                 symbols::APPEND_LOG_THREE_TOPICS,
-                SyscallContext::append_log_with_three_topics
+                Self::append_log_with_three_topics
                     as *const fn(*mut c_void, u32, u32, *const U256, *const U256, *const U256)
                     as *mut (),
             );
             engine.register_symbol(
                 symbols::APPEND_LOG_FOUR_TOPICS,
-                SyscallContext::append_log_with_four_topics
+                Self::append_log_with_four_topics
                     as *const fn(
                         *mut c_void,
                         u32,
@@ -1148,7 +1588,7 @@ impl<'c> SyscallContext<'c> {
             );
             engine.register_symbol(
                 symbols::CALL,
-                SyscallContext::call
+                Self::call
                     as *const fn(
                         *mut c_void,
                         u64,
@@ -1165,155 +1605,149 @@ impl<'c> SyscallContext<'c> {
             );
             engine.register_symbol(
                 symbols::GET_CALLDATA_PTR,
-                SyscallContext::get_calldata_ptr as *const fn(*mut c_void) as *mut (),
+                Self::get_calldata_ptr as *const fn(*mut c_void) as *mut (),
             );
             engine.register_symbol(
                 symbols::GET_CALLDATA_SIZE,
-                SyscallContext::get_calldata_size_syscall as *const fn(*mut c_void) as *mut (),
+                Self::get_calldata_size_syscall as *const fn(*mut c_void) as *mut (),
             );
             engine.register_symbol(
                 symbols::EXTEND_MEMORY,
-                SyscallContext::extend_memory as *const fn(*mut c_void, u32) as *mut (),
+                Self::extend_memory as *const fn(*mut c_void, u32) as *mut (),
             );
             engine.register_symbol(
                 symbols::COPY_CODE_TO_MEMORY,
-                SyscallContext::copy_code_to_memory as *const fn(*mut c_void, u32, u32, u32)
-                    as *mut (),
+                Self::copy_code_to_memory as *const fn(*mut c_void, u32, u32, u32) as *mut (),
             );
             engine.register_symbol(
                 symbols::GET_ORIGIN,
-                SyscallContext::get_origin as *const fn(*mut c_void, *mut U256) as *mut (),
+                Self::get_origin as *const fn(*mut c_void, *mut U256) as *mut (),
             );
             engine.register_symbol(
                 symbols::GET_ADDRESS_PTR,
-                SyscallContext::get_address_ptr as *const fn(*mut c_void) as *mut (),
+                Self::get_address_ptr as *const fn(*mut c_void) as *mut (),
             );
             engine.register_symbol(
                 symbols::STORE_IN_CALLVALUE_PTR,
-                SyscallContext::store_in_callvalue_ptr as *const fn(*mut c_void, *mut U256)
-                    as *mut (),
+                Self::store_in_callvalue_ptr as *const fn(*mut c_void, *mut U256) as *mut (),
             );
             engine.register_symbol(
                 symbols::STORE_IN_BLOBBASEFEE_PTR,
-                SyscallContext::store_in_blobbasefee_ptr
-                    as *const extern "C" fn(&SyscallContext, *mut u128) -> ()
+                Self::store_in_blobbasefee_ptr as *const extern "C" fn(&Self, *mut u128) -> ()
                     as *mut (),
             );
             engine.register_symbol(
                 symbols::GET_CODESIZE_FROM_ADDRESS,
-                SyscallContext::get_codesize_from_address as *const fn(*mut c_void, *mut U256)
-                    as *mut (),
+                Self::get_codesize_from_address as *const fn(*mut c_void, *mut U256) as *mut (),
             );
             engine.register_symbol(
                 symbols::GET_COINBASE_PTR,
-                SyscallContext::get_coinbase_ptr as *const fn(*mut c_void) as *mut (),
+                Self::get_coinbase_ptr as *const fn(*mut c_void) as *mut (),
             );
             engine.register_symbol(
                 symbols::STORE_IN_TIMESTAMP_PTR,
-                SyscallContext::store_in_timestamp_ptr as *const fn(*mut c_void, *mut U256)
-                    as *mut (),
+                Self::store_in_timestamp_ptr as *const fn(*mut c_void, *mut U256) as *mut (),
             );
             engine.register_symbol(
                 symbols::STORE_IN_BASEFEE_PTR,
-                SyscallContext::store_in_basefee_ptr as *const fn(*mut c_void, *mut U256)
-                    as *mut (),
+                Self::store_in_basefee_ptr as *const fn(*mut c_void, *mut U256) as *mut (),
             );
             engine.register_symbol(
                 symbols::STORE_IN_CALLER_PTR,
-                SyscallContext::store_in_caller_ptr as *const fn(*mut c_void, *mut U256) as *mut (),
+                Self::store_in_caller_ptr as *const fn(*mut c_void, *mut U256) as *mut (),
             );
             engine.register_symbol(
-                symbols::GET_GASLIMIT,
-                SyscallContext::get_gaslimit as *const fn(*mut c_void) as *mut (),
+                symbols::STORE_IN_GASLIMIT_PTR,
+                Self::store_in_gaslimit_ptr as *const fn(*mut c_void, *mut U256) as *mut (),
             );
             engine.register_symbol(
                 symbols::STORE_IN_GASPRICE_PTR,
-                SyscallContext::store_in_gasprice_ptr as *const fn(*mut c_void, *mut U256)
-                    as *mut (),
+                Self::store_in_gasprice_ptr as *const fn(*mut c_void, *mut U256) as *mut (),
             );
             engine.register_symbol(
                 symbols::GET_BLOCK_NUMBER,
-                SyscallContext::get_block_number as *const fn(*mut c_void, *mut U256) as *mut (),
+                Self::get_block_number as *const fn(*mut c_void, *mut U256) as *mut (),
             );
             engine.register_symbol(
                 symbols::GET_PREVRANDAO,
-                SyscallContext::get_prevrandao as *const fn(*mut c_void, *mut U256) as *mut (),
+                Self::get_prevrandao as *const fn(*mut c_void, *mut U256) as *mut (),
             );
             engine.register_symbol(
                 symbols::GET_BLOB_HASH_AT_INDEX,
-                SyscallContext::get_blob_hash_at_index
-                    as *const fn(*mut c_void, *mut U256, *mut U256) as *mut (),
+                Self::get_blob_hash_at_index as *const fn(*mut c_void, *mut U256, *mut U256)
+                    as *mut (),
             );
             engine.register_symbol(
                 symbols::GET_CHAINID,
-                SyscallContext::get_chainid as *const extern "C" fn(&SyscallContext) -> u64
-                    as *mut (),
+                Self::get_chainid as *const extern "C" fn(&Self) -> u64 as *mut (),
             );
             engine.register_symbol(
                 symbols::STORE_IN_BALANCE,
-                SyscallContext::store_in_balance as *const fn(*mut c_void, *const U256, *mut U256)
-                    as *mut (),
+                Self::store_in_balance as *const fn(*mut c_void, *const U256, *mut U256) as *mut (),
             );
             engine.register_symbol(
                 symbols::STORE_IN_SELFBALANCE_PTR,
-                SyscallContext::store_in_selfbalance_ptr
-                    as *const extern "C" fn(&SyscallContext) -> u64 as *mut (),
+                Self::store_in_selfbalance_ptr as *const extern "C" fn(&Self) -> u64 as *mut (),
             );
             engine.register_symbol(
                 symbols::COPY_EXT_CODE_TO_MEMORY,
-                SyscallContext::copy_ext_code_to_memory
+                Self::copy_ext_code_to_memory
                     as *const extern "C" fn(*mut c_void, *mut U256, u32, u32, u32)
                     as *mut (),
             );
             engine.register_symbol(
                 symbols::GET_BLOCK_HASH,
-                SyscallContext::get_block_hash as *const fn(*mut c_void, *mut U256) as *mut (),
+                Self::get_block_hash as *const fn(*mut c_void, *mut U256) as *mut (),
+            );
+
+            engine.register_symbol(
+                symbols::ACCESS_ACCOUNT,
+                Self::access_account as *const fn(*mut c_void, *const U256) -> u64 as *mut (),
             );
 
             engine.register_symbol(
                 symbols::GET_CODE_HASH,
-                SyscallContext::get_code_hash as *const fn(*mut c_void, *mut U256) as *mut (),
+                Self::get_code_hash as *const fn(*mut c_void, *mut U256) as *mut (),
             );
 
             engine.register_symbol(
                 symbols::CREATE,
-                SyscallContext::create
-                    as *const extern "C" fn(*mut c_void, u32, u32, *mut U256, *mut u64)
+                Self::create as *const extern "C" fn(*mut c_void, u32, u32, *mut U256, *mut u64)
                     as *mut (),
             );
 
             engine.register_symbol(
                 symbols::CREATE2,
-                SyscallContext::create2
+                Self::create2
                     as *const extern "C" fn(*mut c_void, u32, u32, *mut U256, *mut u64, *mut U256)
                     as *mut (),
             );
 
             engine.register_symbol(
                 symbols::GET_RETURN_DATA_SIZE,
-                SyscallContext::get_return_data_size as *const fn(*mut c_void) as *mut (),
+                Self::get_return_data_size as *const fn(*mut c_void) as *mut (),
             );
             engine.register_symbol(
                 symbols::COPY_RETURN_DATA_INTO_MEMORY,
-                SyscallContext::copy_return_data_into_memory
-                    as *const fn(*mut c_void, u32, u32, u32) as *mut (),
+                Self::copy_return_data_into_memory as *const fn(*mut c_void, u32, u32, u32)
+                    as *mut (),
             );
 
             engine.register_symbol(
                 symbols::SELFDESTRUCT,
-                SyscallContext::selfdestruct as *const fn(*mut c_void, *mut U256) as *mut (),
+                Self::selfdestruct as *const fn(*mut c_void, *mut U256) as *mut (),
             );
 
             engine.register_symbol(
                 symbols::TRANSIENT_STORAGE_READ,
-                SyscallContext::read_transient_storage
-                    as *const fn(*const c_void, *const U256, *mut U256) as *mut (),
+                Self::read_transient_storage as *const fn(*const c_void, *const U256, *mut U256)
+                    as *mut (),
             );
 
             engine.register_symbol(
                 symbols::TRANSIENT_STORAGE_WRITE,
-                SyscallContext::write_transient_storage
-                    as *const fn(*const c_void, *const U256, *mut U256) as *mut (),
+                Self::write_transient_storage as *const fn(*const c_void, *const U256, *mut U256)
+                    as *mut (),
             );
         }
     }
@@ -1322,10 +1756,7 @@ impl<'c> SyscallContext<'c> {
 /// MLIR util for declaring syscalls
 pub(crate) mod mlir {
     use melior::{
-        dialect::{
-            func,
-            llvm::{attributes::Linkage, r#type::pointer},
-        },
+        dialect::{func, llvm::r#type::pointer},
         ir::{
             attribute::{FlatSymbolRefAttribute, StringAttribute, TypeAttribute},
             r#type::{FunctionType, IntegerType},
@@ -1334,7 +1765,7 @@ pub(crate) mod mlir {
         Context as MeliorContext,
     };
 
-    use crate::{errors::CodegenError, utils::llvm_mlir};
+    use crate::errors::CodegenError;
 
     use super::symbols;
 
@@ -1352,15 +1783,34 @@ pub(crate) mod mlir {
             StringAttribute::new(context, "private").into(),
         )];
 
-        // Globals declaration
-        module.body().append_operation(llvm_mlir::global(
+        // Syscall declarations
+        module.body().append_operation(func::func(
             context,
-            symbols::CONTEXT_IS_STATIC,
-            ptr_type,
-            Linkage::External,
+            StringAttribute::new(context, symbols::HAS_HALTED),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type], &[uint8]).into()),
+            Region::new(),
+            attributes,
             location,
         ));
-        // Syscall declarations
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::IS_STATIC),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type], &[uint8]).into()),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::GET_MEMORY_LIMIT),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type], &[uint64]).into()),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
         module.body().append_operation(func::func(
             context,
             StringAttribute::new(context, symbols::WRITE_RESULT),
@@ -1456,8 +1906,17 @@ pub(crate) mod mlir {
 
         module.body().append_operation(func::func(
             context,
-            StringAttribute::new(context, symbols::GET_GASLIMIT),
-            TypeAttribute::new(FunctionType::new(context, &[ptr_type], &[uint64]).into()),
+            StringAttribute::new(context, symbols::STORE_IN_GASLIMIT_PTR),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type, ptr_type], &[]).into()),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::GET_MEMORY_PTR),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type], &[ptr_type]).into()),
             Region::new(),
             attributes,
             location,
@@ -1466,7 +1925,7 @@ pub(crate) mod mlir {
         module.body().append_operation(func::func(
             context,
             StringAttribute::new(context, symbols::EXTEND_MEMORY),
-            TypeAttribute::new(FunctionType::new(context, &[ptr_type, uint32], &[ptr_type]).into()),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type, uint32], &[]).into()),
             Region::new(),
             attributes,
             location,
@@ -1487,7 +1946,7 @@ pub(crate) mod mlir {
             context,
             StringAttribute::new(context, symbols::STORAGE_READ),
             r#TypeAttribute::new(
-                FunctionType::new(context, &[ptr_type, ptr_type, ptr_type], &[]).into(),
+                FunctionType::new(context, &[ptr_type, ptr_type, ptr_type], &[uint64]).into(),
             ),
             Region::new(),
             attributes,
@@ -1705,6 +2164,15 @@ pub(crate) mod mlir {
             location,
         ));
 
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::ACCESS_ACCOUNT),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type, ptr_type], &[uint64]).into()),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
         module.body().append_operation(func::func(
             context,
             StringAttribute::new(context, symbols::GET_CODE_HASH),
@@ -1856,27 +2324,47 @@ pub(crate) mod mlir {
         Ok(value.into())
     }
 
-    /// Returns a pointer to the start of the calldata
-    pub(crate) fn get_calldata_ptr_syscall<'c>(
+    /// Returns whether a syscall made by the frame failed and halted it, as an `i8`
+    pub(crate) fn has_halted_syscall<'c>(
         mlir_ctx: &'c MeliorContext,
         syscall_ctx: Value<'c, 'c>,
         block: &'c Block,
         location: Location<'c>,
     ) -> Result<Value<'c, 'c>, CodegenError> {
-        let ptr_type = pointer(mlir_ctx, 0);
+        let uint8 = IntegerType::new(mlir_ctx, 8).into();
         let value = block
             .append_operation(func::call(
                 mlir_ctx,
-                FlatSymbolRefAttribute::new(mlir_ctx, symbols::GET_CALLDATA_PTR),
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::HAS_HALTED),
                 &[syscall_ctx],
-                &[ptr_type],
+                &[uint8],
                 location,
             ))
             .result(0)?;
         Ok(value.into())
     }
 
-    pub(crate) fn get_gaslimit<'c>(
+    /// Returns whether the frame is forbidden from modifying the state, as an `i8`
+    pub(crate) fn is_static_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint8 = IntegerType::new(mlir_ctx, 8).into();
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::IS_STATIC),
+                &[syscall_ctx],
+                &[uint8],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
+
+    pub(crate) fn get_memory_limit_syscall<'c>(
         mlir_ctx: &'c MeliorContext,
         syscall_ctx: Value<'c, 'c>,
         block: &'c Block,
@@ -1886,7 +2374,7 @@ pub(crate) mod mlir {
         let value = block
             .append_operation(func::call(
                 mlir_ctx,
-                FlatSymbolRefAttribute::new(mlir_ctx, symbols::GET_GASLIMIT),
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::GET_MEMORY_LIMIT),
                 &[syscall_ctx],
                 &[uint64],
                 location,
@@ -1895,6 +2383,42 @@ pub(crate) mod mlir {
         Ok(value.into())
     }
 
+    /// Returns a pointer to the start of the calldata
+    pub(crate) fn get_calldata_ptr_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let ptr_type = pointer(mlir_ctx, 0);
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::GET_CALLDATA_PTR),
+                &[syscall_ctx],
+                &[ptr_type],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
+
+    pub(crate) fn store_in_gaslimit_ptr<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        location: Location<'c>,
+        gaslimit_ptr: Value<'c, 'c>,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::STORE_IN_GASLIMIT_PTR),
+            &[syscall_ctx, gaslimit_ptr],
+            &[],
+            location,
+        ));
+    }
+
     pub(crate) fn get_chainid_syscall<'c>(
         mlir_ctx: &'c MeliorContext,
         syscall_ctx: Value<'c, 'c>,
@@ -1978,21 +2502,20 @@ pub(crate) mod mlir {
         ));
     }
 
-    /// Extends the memory segment of the syscall context.
-    /// Returns a pointer to the start of the memory segment.
-    pub(crate) fn extend_memory_syscall<'c>(
+    /// Reserves the memory segment of the syscall context.
+    /// Returns a pointer to its start, which stays the same for the whole frame.
+    pub(crate) fn get_memory_ptr_syscall<'c>(
         mlir_ctx: &'c MeliorContext,
         syscall_ctx: Value<'c, 'c>,
         block: &'c Block,
-        new_size: Value<'c, 'c>,
         location: Location<'c>,
     ) -> Result<Value<'c, 'c>, CodegenError> {
         let ptr_type = pointer(mlir_ctx, 0);
         let value = block
             .append_operation(func::call(
                 mlir_ctx,
-                FlatSymbolRefAttribute::new(mlir_ctx, symbols::EXTEND_MEMORY),
-                &[syscall_ctx, new_size],
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::GET_MEMORY_PTR),
+                &[syscall_ctx],
                 &[ptr_type],
                 location,
             ))
@@ -2000,6 +2523,23 @@ pub(crate) mod mlir {
         Ok(value.into())
     }
 
+    /// Extends the memory segment of the syscall context.
+    pub(crate) fn extend_memory_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        new_size: Value<'c, 'c>,
+        location: Location<'c>,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::EXTEND_MEMORY),
+            &[syscall_ctx, new_size],
+            &[],
+            location,
+        ));
+    }
+
     pub(crate) fn store_in_selfbalance_ptr<'c>(
         mlir_ctx: &'c MeliorContext,
         syscall_ctx: Value<'c, 'c>,
@@ -2016,7 +2556,7 @@ pub(crate) mod mlir {
         ));
     }
 
-    /// Reads the storage given a key
+    /// Reads the storage given a key, returning the gas cost of the access
     pub(crate) fn storage_read_syscall<'c>(
         mlir_ctx: &'c MeliorContext,
         syscall_ctx: Value<'c, 'c>,
@@ -2024,14 +2564,18 @@ pub(crate) mod mlir {
         key: Value<'c, 'c>,
         value: Value<'c, 'c>,
         location: Location<'c>,
-    ) {
-        block.append_operation(func::call(
-            mlir_ctx,
-            FlatSymbolRefAttribute::new(mlir_ctx, symbols::STORAGE_READ),
-            &[syscall_ctx, key, value],
-            &[],
-            location,
-        ));
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint64 = IntegerType::new(mlir_ctx, 64).into();
+        let gas_cost = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::STORAGE_READ),
+                &[syscall_ctx, key, value],
+                &[uint64],
+                location,
+            ))
+            .result(0)?;
+        Ok(gas_cost.into())
     }
 
     /// Writes the storage given a key value pair
@@ -2484,6 +3028,27 @@ pub(crate) mod mlir {
         ));
     }
 
+    /// Marks the account as accessed, returning the gas cost of the access
+    pub(crate) fn access_account_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        address: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint64 = IntegerType::new(mlir_ctx, 64).into();
+        let gas_cost = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::ACCESS_ACCOUNT),
+                &[syscall_ctx, address],
+                &[uint64],
+                location,
+            ))
+            .result(0)?;
+        Ok(gas_cost.into())
+    }
+
     pub(crate) fn get_code_hash_syscall<'c>(
         mlir_ctx: &'c MeliorContext,
         syscall_ctx: Value<'c, 'c>,
@@ -2609,3 +3174,60 @@ pub(crate) mod mlir {
         Ok(result.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{constants::EMPTY_CODE_HASH_STR, db::Db};
+    use std::str::FromStr;
+
+    #[test]
+    fn out_of_bounds_memory_access_halts_the_frame() {
+        let mut db = Db::new();
+        let journal = Journal::new(&mut db);
+        let mut context = SyscallContext::new(Env::default(), journal, Default::default());
+        context.write_result(0, 0, 0, ExitStatusCode::Stop.to_u8());
+
+        let mut hash = U256::default();
+        context.keccak256_hasher(64, 32, &mut hash);
+
+        assert_eq!(
+            context.execution_result(),
+            ExecutionResult::Halt {
+                reason: HaltReason::OutOfOffset,
+                gas_used: context.call_frame.gas_limit,
+            }
+        );
+    }
+
+    #[test]
+    fn static_call_violation_halts_the_frame() {
+        let mut db = Db::new();
+        let journal = Journal::new(&mut db);
+        let mut context = SyscallContext::new(Env::default(), journal, Default::default());
+        context.write_result(0, 0, 0, ExitStatusCode::StaticCallViolation.to_u8());
+
+        assert_eq!(
+            context.execution_result(),
+            ExecutionResult::Halt {
+                reason: HaltReason::StateChangeDuringStaticCall,
+                gas_used: context.call_frame.gas_limit,
+            }
+        );
+    }
+
+    #[test]
+    fn empty_memory_range_is_valid_at_any_offset() {
+        let mut db = Db::new();
+        let journal = Journal::new(&mut db);
+        let mut context = SyscallContext::new(Env::default(), journal, Default::default());
+        context.write_result(0, 0, 0, ExitStatusCode::Stop.to_u8());
+
+        let mut hash = U256::default();
+        context.keccak256_hasher(u32::MAX, 0, &mut hash);
+
+        assert!(context.execution_result().is_success());
+        let empty_hash = B256::from_str(EMPTY_CODE_HASH_STR).unwrap();
+        assert_eq!(hash, U256::from_fixed_be_bytes(empty_hash.to_fixed_bytes()));
+    }
+}
@@ -10,7 +10,7 @@ use melior::{
         attribute::{DenseI32ArrayAttribute, IntegerAttribute, TypeAttribute},
         operation::OperationResult,
         r#type::IntegerType,
-        Block, Location, Region, Value, ValueLike,
+        Block, BlockRef, Location, Region, Value, ValueLike,
     },
     Context as MeliorContext,
 };
@@ -19,12 +19,14 @@ use sha3::{Digest, Keccak256};
 use crate::{
     codegen::context::OperationCtx,
     constants::{
-        gas_cost, CALLDATA_PTR_GLOBAL, CALLDATA_SIZE_GLOBAL, GAS_COUNTER_GLOBAL, MAX_STACK_SIZE,
+        gas_cost, CALLDATA_PTR_GLOBAL, CALLDATA_SIZE_GLOBAL, CONTEXT_IS_STATIC_GLOBAL,
+        GAS_COUNTER_GLOBAL, MAX_MEMORY_SIZE, MAX_STACK_SIZE, MEMORY_LIMIT_GLOBAL,
         MEMORY_PTR_GLOBAL, MEMORY_SIZE_GLOBAL, STACK_BASEPTR_GLOBAL, STACK_PTR_GLOBAL,
+        TARGET_BLOB_GAS_PER_BLOCK,
     },
     errors::CodegenError,
     primitives::{Address, H160, U256},
-    syscall::{symbols::CONTEXT_IS_STATIC, ExitStatusCode},
+    syscall::{self, ExitStatusCode},
 };
 
 // NOTE: the value is of type i64
@@ -155,6 +157,77 @@ pub(crate) fn check_context_is_not_static<'c>(
     Ok(is_not_static)
 }
 
+/// Generates a conditional branch to the static call violation block, which halts the
+/// execution, if the current context is static. Returns the block where execution continues.
+pub(crate) fn ensure_context_is_not_static<'c, 'r>(
+    op_ctx: &OperationCtx<'c>,
+    region: &'r Region<'c>,
+    block: &Block<'c>,
+) -> Result<BlockRef<'c, 'r>, CodegenError> {
+    let context = op_ctx.mlir_context;
+    let location = Location::unknown(context);
+
+    let is_not_static = check_context_is_not_static(op_ctx, block)?;
+    let ok_block = region.append_block(Block::new(&[]));
+    block.append_operation(cf::cond_br(
+        context,
+        is_not_static,
+        &ok_block,
+        &op_ctx.static_call_violation_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    Ok(ok_block)
+}
+
+/// Generates a conditional branch to the revert block, which halts the execution, if the
+/// last syscall failed. Returns the block where execution continues.
+pub(crate) fn check_syscall_status<'c, 'r>(
+    op_ctx: &OperationCtx<'c>,
+    region: &'r Region<'c>,
+    block: &Block<'c>,
+) -> Result<BlockRef<'c, 'r>, CodegenError> {
+    let context = op_ctx.mlir_context;
+    let location = Location::unknown(context);
+    let uint8 = IntegerType::new(context, 8);
+
+    let has_halted =
+        syscall::mlir::has_halted_syscall(context, op_ctx.syscall_ctx, block, location)?;
+    let zero = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint8.into(), 0).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let succeeded = block
+        .append_operation(arith::cmpi(
+            context,
+            CmpiPredicate::Eq,
+            has_halted,
+            zero,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+    block.append_operation(cf::cond_br(
+        context,
+        succeeded,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    Ok(ok_block)
+}
+
 pub(crate) fn context_is_static<'c>(
     op_ctx: &'c OperationCtx,
     block: &'c Block,
@@ -166,7 +239,7 @@ pub(crate) fn context_is_static<'c>(
     let static_flag_ptr = block
         .append_operation(llvm_mlir::addressof(
             context,
-            CONTEXT_IS_STATIC,
+            CONTEXT_IS_STATIC_GLOBAL,
             ptr_type,
             location,
         ))
@@ -1141,8 +1214,152 @@ pub(crate) fn get_calldata_size<'c>(
     Ok(calldata_size)
 }
 
+/// Truncates a 256-bit value to 32 bits, saturating to `u32::MAX` if it doesn't fit.
+///
+/// Used for memory offsets and sizes, so that huge values keep being huge
+/// instead of wrapping around to a small valid offset.
+pub(crate) fn truncate_to_u32_saturating<'c>(
+    context: &'c MeliorContext,
+    block: &'c Block,
+    value: Value<'c, 'c>,
+) -> Result<Value<'c, 'c>, CodegenError> {
+    let location = Location::unknown(context);
+    let uint32 = IntegerType::new(context, 32);
+
+    let max_u32 = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(value.r#type(), u32::MAX as i64).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let overflows = compare_values(context, block, CmpiPredicate::Ugt, value, max_u32)?;
+    let truncated = block
+        .append_operation(arith::trunci(value, uint32.into(), location))
+        .result(0)?
+        .into();
+    let saturated = block
+        .append_operation(arith::trunci(max_u32, uint32.into(), location))
+        .result(0)?
+        .into();
+    let result = block
+        .append_operation(arith::select(overflows, saturated, truncated, location))
+        .result(0)?
+        .into();
+
+    Ok(result)
+}
+
+/// Truncates `value` to 64 bits, saturating to `u64::MAX` if it doesn't fit.
+pub(crate) fn truncate_to_u64_saturating<'c>(
+    context: &'c MeliorContext,
+    block: &'c Block,
+    value: Value<'c, 'c>,
+) -> Result<Value<'c, 'c>, CodegenError> {
+    let location = Location::unknown(context);
+    let uint64 = IntegerType::new(context, 64);
+
+    let shift = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(value.r#type(), 64).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let zero = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(value.r#type(), 0).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let high_bits = block
+        .append_operation(arith::shrui(value, shift, location))
+        .result(0)?
+        .into();
+    let overflows = compare_values(context, block, CmpiPredicate::Ne, high_bits, zero)?;
+    let truncated = block
+        .append_operation(arith::trunci(value, uint64.into(), location))
+        .result(0)?
+        .into();
+    // All ones, i.e. `u64::MAX`
+    let saturated = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), -1).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let result = block
+        .append_operation(arith::select(overflows, saturated, truncated, location))
+        .result(0)?
+        .into();
+
+    Ok(result)
+}
+
+/// Returns the memory size needed to access `size` bytes starting at `offset`, as a 64-bit value.
+///
+/// Empty ranges don't need any memory, regardless of their offset.
+pub(crate) fn compute_required_memory_size<'c>(
+    context: &'c MeliorContext,
+    block: &'c Block,
+    offset: Value<'c, 'c>,
+    size: Value<'c, 'c>,
+) -> Result<Value<'c, 'c>, CodegenError> {
+    let location = Location::unknown(context);
+    let uint32 = IntegerType::new(context, 32);
+    let uint64 = IntegerType::new(context, 64);
+
+    let zero = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), 0).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let size_is_zero = compare_values(context, block, CmpiPredicate::Eq, size, zero)?;
+
+    // Both operands fit in 32 bits, so their sum can't overflow 64 bits
+    let offset = block
+        .append_operation(arith::extui(offset, uint64.into(), location))
+        .result(0)?
+        .into();
+    let size = block
+        .append_operation(arith::extui(size, uint64.into(), location))
+        .result(0)?
+        .into();
+    let range_end = block
+        .append_operation(arith::addi(offset, size, location))
+        .result(0)?
+        .into();
+    let zero = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), 0).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let required_size = block
+        .append_operation(arith::select(size_is_zero, zero, range_end, location))
+        .result(0)?
+        .into();
+
+    Ok(required_size)
+}
+
 /// Wrapper for calling the [`extend_memory`](crate::syscall::SyscallContext::extend_memory) syscall.
 /// Extends memory only if the current memory size is less than the required size, consuming the corresponding gas.
+///
+/// The required size is a 64-bit value (see [`compute_required_memory_size`]). Sizes that can't
+/// be paid for halt the execution with an out of gas error, and those that can but are over the
+/// configured memory limit halt it with a memory limit error.
 pub(crate) fn extend_memory<'c>(
     op_ctx: &'c OperationCtx,
     block: &'c Block,
@@ -1157,6 +1374,41 @@ pub(crate) fn extend_memory<'c>(
     let uint32 = IntegerType::new(context, 32);
     let uint64 = IntegerType::new(context, 64);
 
+    // Sizes over MAX_MEMORY_SIZE can't be addressed, so expanding the memory to them
+    // runs out of gas even when there's gas enough to pay for it
+    let max_memory_size = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), MAX_MEMORY_SIZE as i64).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let addressable_flag = compare_values(
+        context,
+        block,
+        CmpiPredicate::Ule,
+        required_size,
+        max_memory_size,
+    )?;
+    let addressable_block = region.append_block(Block::new(&[]));
+    block.append_operation(cf::cond_br(
+        context,
+        addressable_flag,
+        &addressable_block,
+        &op_ctx.out_of_gas_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    // The required size is at most MAX_MEMORY_SIZE, so it fits in 32 bits
+    let block: &Block = &addressable_block;
+    let required_size = block
+        .append_operation(arith::trunci(required_size, uint32.into(), location))
+        .result(0)?
+        .into();
+
     // Load memory size
     let memory_size_ptr = block
         .append_operation(llvm_mlir::addressof(
@@ -1223,48 +1475,77 @@ pub(crate) fn extend_memory<'c>(
         .into();
     let extension_gas_flag = consume_gas_as_value(context, &extension_block, total_gas)?;
 
-    // Consume gas for no memory extension case
-    let no_extension_gas_flag = consume_gas(context, &no_extension_block, fixed_gas)?;
-
-    let memory_ptr =
-        op_ctx.extend_memory_syscall(&extension_block, rounded_required_size, location)?;
-
-    // Store new memory size and pointer
-    let res = extension_block.append_operation(llvm::store(
+    let limit_check_block = region.append_block(Block::new(&[]));
+    extension_block.append_operation(cf::cond_br(
         context,
-        rounded_required_size,
-        memory_size_ptr,
+        extension_gas_flag,
+        &limit_check_block,
+        &op_ctx.out_of_gas_block,
+        &[],
+        &[],
         location,
-        LoadStoreOptions::default(),
     ));
-    assert!(res.verify());
-    let memory_ptr_ptr = extension_block
+
+    // Only expansions that were paid for are checked against the memory limit
+    let memory_limit_ptr = limit_check_block
         .append_operation(llvm_mlir::addressof(
             context,
-            MEMORY_PTR_GLOBAL,
+            MEMORY_LIMIT_GLOBAL,
             ptr_type,
             location,
         ))
-        .result(0)?;
-    let res = extension_block.append_operation(llvm::store(
+        .result(0)?
+        .into();
+    let memory_limit = limit_check_block
+        .append_operation(llvm::load(
+            context,
+            memory_limit_ptr,
+            uint64.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+    let extended_size = limit_check_block
+        .append_operation(arith::extui(rounded_required_size, uint64.into(), location))
+        .result(0)?
+        .into();
+    let within_limit_flag = compare_values(
+        context,
+        &limit_check_block,
+        CmpiPredicate::Ule,
+        extended_size,
+        memory_limit,
+    )?;
+    let allocation_block = region.append_block(Block::new(&[]));
+    limit_check_block.append_operation(cf::cond_br(
         context,
-        memory_ptr,
-        memory_ptr_ptr.into(),
+        within_limit_flag,
+        &allocation_block,
+        &op_ctx.memory_limit_block,
+        &[],
+        &[],
         location,
-        LoadStoreOptions::default(),
     ));
-    assert!(res.verify());
 
-    // Jump to finish block
-    extension_block.append_operation(cf::cond_br(
+    // Consume gas for no memory extension case
+    let no_extension_gas_flag = consume_gas(context, &no_extension_block, fixed_gas)?;
+
+    op_ctx.extend_memory_syscall(&allocation_block, rounded_required_size, location);
+    let allocation_block = check_syscall_status(op_ctx, region, &allocation_block)?;
+
+    // Store new memory size. The memory pointer never changes, so it isn't reloaded.
+    let res = allocation_block.append_operation(llvm::store(
         context,
-        extension_gas_flag,
-        finish_block,
-        &op_ctx.revert_block,
-        &[],
-        &[],
+        rounded_required_size,
+        memory_size_ptr,
         location,
+        LoadStoreOptions::default(),
     ));
+    assert!(res.verify());
+
+    // Jump to finish block
+    allocation_block.append_operation(cf::br(finish_block, &[], location));
 
     no_extension_block.append_operation(cf::cond_br(
         context,
@@ -1347,27 +1628,13 @@ pub(crate) fn return_result_from_stack(
     location: Location,
 ) -> Result<(), CodegenError> {
     let context = op_ctx.mlir_context;
-    let uint32 = IntegerType::new(context, 32);
 
     let offset_u256 = stack_pop(context, block)?;
     let size_u256 = stack_pop(context, block)?;
 
-    let offset = block
-        .append_operation(arith::trunci(offset_u256, uint32.into(), location))
-        .result(0)
-        .unwrap()
-        .into();
-
-    let size = block
-        .append_operation(arith::trunci(size_u256, uint32.into(), location))
-        .result(0)
-        .unwrap()
-        .into();
-
-    let required_size = block
-        .append_operation(arith::addi(offset, size, location))
-        .result(0)?
-        .into();
+    let offset = truncate_to_u32_saturating(context, block, offset_u256)?;
+    let size = truncate_to_u32_saturating(context, block, size_u256)?;
+    let required_size = compute_required_memory_size(context, block, offset, size)?;
 
     let return_block = region.append_block(Block::new(&[]));
 
@@ -1612,6 +1879,16 @@ pub(crate) fn get_basefee<'a>(
     Ok(basefee)
 }
 
+/// Calculates the excess blob gas of a block from its parent's excess blob gas and blob gas used.
+///
+/// See also [the EIP-4844 helpers](https://eips.ethereum.org/EIPS/eip-4844#helpers)
+/// (`calc_excess_blob_gas`). Invalid headers with values that overflow saturate instead.
+pub fn calc_excess_blob_gas(parent_excess_blob_gas: u64, parent_blob_gas_used: u64) -> u64 {
+    parent_excess_blob_gas
+        .saturating_add(parent_blob_gas_used)
+        .saturating_sub(TARGET_BLOB_GAS_PER_BLOCK)
+}
+
 /// Calculates the blob gas price from the header's excess blob gas field.
 ///
 /// See also [the EIP-4844 helpers](https://eips.ethereum.org/EIPS/eip-4844#helpers)
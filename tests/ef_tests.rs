@@ -4,7 +4,15 @@ use std::{
 };
 mod ef_tests_executor;
 use ef_tests_executor::models::{AccountInfo, TestSuite};
-use evm_mlir::{db::Db, env::TransactTo, Env, Evm};
+use evm_mlir::{db::Db, env::TransactTo, spec::SpecId, utils::calc_excess_blob_gas, Env, Evm};
+
+/// Specs whose expected post states are checked
+const SUPPORTED_SPECS: [SpecId; 4] = [
+    SpecId::Berlin,
+    SpecId::London,
+    SpecId::Shanghai,
+    SpecId::Cancun,
+];
 
 fn get_group_name_from_path(path: &Path) -> String {
     // Gets the parent directory's name.
@@ -51,7 +59,6 @@ fn get_ignored_groups() -> HashSet<String> {
         "stCallCreateCallCodeTest".into(),
         "stPreCompiledContracts2".into(),
         "stZeroKnowledge2".into(),
-        "stDelegatecallTestHomestead".into(),
         "stTimeConsuming".into(),
         "stEIP150singleCodeGasPrices".into(),
         "stTransitionTest".into(),
@@ -61,10 +68,8 @@ fn get_ignored_groups() -> HashSet<String> {
         "eip3651_warm_coinbase".into(),
         "stSLoadTest".into(),
         "stExtCodeHash".into(),
-        "stCallCodes".into(),
         "stRandom2".into(),
         "stMemoryStressTest".into(),
-        "stStaticFlagEnabled".into(),
         "vmTests".into(),
         "opcodes".into(),
         "stEIP158Specific".into(),
@@ -75,7 +80,6 @@ fn get_ignored_groups() -> HashSet<String> {
         "stBugs".into(),
         "stEIP1559".into(),
         "stSelfBalance".into(),
-        "stStaticCall".into(),
         "stCallDelegateCodesHomestead".into(),
         "stMemExpandingEIP150Calls".into(),
         "stTransactionTest".into(),
@@ -112,6 +116,23 @@ fn get_ignored_suites() -> HashSet<String> {
     HashSet::from([
         "ValueOverflow".into(),      // TODO: parse bigint tx value
         "ValueOverflowParis".into(), // TODO: parse bigint tx value
+        // TODO: these call the elliptic curve precompiles (0x06..=0x08), not implemented yet
+        "StaticcallToPrecompileFromCalledContract".into(),
+        "StaticcallToPrecompileFromContractInitialization".into(),
+        "StaticcallToPrecompileFromTransaction".into(),
+        "CallWithZeroValueToPrecompileFromCalledContract".into(),
+        "CallWithZeroValueToPrecompileFromContractInitialization".into(),
+        "CallWithZeroValueToPrecompileFromTransaction".into(),
+        "CallWithNOTZeroValueToPrecompileFromCalledContract".into(),
+        "CallWithNOTZeroValueToPrecompileFromContractInitialization".into(),
+        "CallWithNOTZeroValueToPrecompileFromTransaction".into(),
+        "CallcodeToPrecompileFromCalledContract".into(),
+        "CallcodeToPrecompileFromContractInitialization".into(),
+        "CallcodeToPrecompileFromTransaction".into(),
+        "DelegatecallToPrecompileFromCalledContract".into(),
+        "DelegatecallToPrecompileFromContractInitialization".into(),
+        "DelegatecallToPrecompileFromTransaction".into(),
+        "StaticcallForPrecompilesIssue683".into(),
     ])
 }
 
@@ -131,88 +152,112 @@ fn run_test(path: &Path, contents: String) -> datatest_stable::Result<()> {
         .unwrap_or_else(|_| panic!("Failed to parse JSON test {}", path.display()));
 
     for (_name, unit) in test_suite.0 {
-        // NOTE: currently we only support Cancun spec
-        let Some(tests) = unit.post.get("Cancun") else {
-            continue;
-        };
-        let Some(to) = unit.transaction.to else {
-            return Err("`to` field is None".into());
-        };
-        let Some(account) = unit.pre.get(&to) else {
-            return Err("Callee doesn't exist".into());
-        };
-        let sender = unit.transaction.sender.unwrap_or_default();
-        let gas_price = unit.transaction.gas_price.unwrap_or_default();
-
-        for test in tests {
-            let mut env = Env::default();
-            env.tx.transact_to = TransactTo::Call(to);
-            env.tx.gas_price = gas_price;
-            env.tx.caller = sender;
-            env.tx.gas_limit = unit.transaction.gas_limit[test.indexes.gas].as_u64();
-            env.tx.value = unit.transaction.value[test.indexes.value];
-            env.tx.data = unit.transaction.data[test.indexes.data].clone();
-
-            env.block.number = unit.env.current_number;
-            env.block.coinbase = unit.env.current_coinbase;
-            env.block.timestamp = unit.env.current_timestamp;
-            let excess_blob_gas = unit
-                .env
-                .current_excess_blob_gas
-                .unwrap_or_default()
-                .as_u64();
-            env.block.set_blob_base_fee(excess_blob_gas);
-
-            if let Some(basefee) = unit.env.current_base_fee {
-                env.block.basefee = basefee;
+        for spec_id in SUPPORTED_SPECS {
+            let Some(tests) = unit.post.get(&spec_id.to_string()) else {
+                continue;
             };
-            let mut db = Db::new().with_contract(to, account.code.clone());
-
-            // Load pre storage into db
-            for (address, account_info) in unit.pre.iter() {
-                db = db.with_contract(address.to_owned(), account_info.code.clone());
-                db.set_account(
-                    address.to_owned(),
-                    account_info.nonce,
-                    account_info.balance,
-                    account_info.storage.clone(),
-                );
-            }
-            let mut evm = Evm::new(env, db);
+            // Transactions without a callee create a contract
+            let transact_to = match unit.transaction.to {
+                Some(to) if !unit.pre.contains_key(&to) => {
+                    return Err("Callee doesn't exist".into());
+                }
+                Some(to) => TransactTo::Call(to),
+                None => TransactTo::Create,
+            };
+            let sender = unit.transaction.sender.unwrap_or_default();
+            // EIP-1559 transactions carry the max fee instead of the gas price
+            let gas_price = unit
+                .transaction
+                .gas_price
+                .or(unit.transaction.max_fee_per_gas)
+                .unwrap_or_default();
 
-            let res = evm.transact().unwrap();
+            for test in tests {
+                let mut env = Env::default();
+                env.cfg.spec_id = spec_id;
+                env.tx.transact_to = transact_to.clone();
+                env.tx.gas_price = gas_price;
+                env.tx.gas_priority_fee = unit.transaction.max_priority_fee_per_gas;
+                env.tx.nonce = Some(unit.transaction.nonce.as_u64());
+                env.tx.caller = sender;
+                env.tx.gas_limit = unit.transaction.gas_limit[test.indexes.gas].as_u64();
+                env.tx.value = unit.transaction.value[test.indexes.value];
+                env.tx.data = unit.transaction.data[test.indexes.data].clone();
 
-            if test.expect_exception.is_some() {
-                assert!(!res.result.is_success());
-                // NOTE: the expect_exception string is an error description, we don't check the expected error
-                continue;
-            }
+                env.block.number = unit.env.current_number;
+                env.block.coinbase = unit.env.current_coinbase;
+                env.block.timestamp = unit.env.current_timestamp;
+                env.block.gas_limit = unit.env.current_gas_limit;
+                env.block.difficulty = unit.env.current_difficulty;
+                env.block.prevrandao = unit.env.current_random.or(env.block.prevrandao);
+                env.block.parent_beacon_block_root = unit.env.current_beacon_root;
+                env.tx.blob_hashes = unit.transaction.blob_versioned_hashes.clone();
+                env.tx.max_fee_per_blob_gas = unit.transaction.max_fee_per_blob_gas;
+                let excess_blob_gas = match unit.env.current_excess_blob_gas {
+                    Some(excess_blob_gas) => excess_blob_gas.as_u64(),
+                    None => calc_excess_blob_gas(
+                        unit.env.parent_excess_blob_gas.unwrap_or_default().as_u64(),
+                        unit.env.parent_blob_gas_used.unwrap_or_default().as_u64(),
+                    ),
+                };
+                env.block.set_blob_base_fee(excess_blob_gas);
+
+                if let Some(basefee) = unit.env.current_base_fee {
+                    env.block.basefee = basefee;
+                };
+                let mut db = Db::new();
+
+                // Load pre storage into db
+                for (address, account_info) in unit.pre.iter() {
+                    db = db.with_contract(address.to_owned(), account_info.code.clone());
+                    db.set_account(
+                        address.to_owned(),
+                        account_info.nonce,
+                        account_info.balance,
+                        account_info.storage.clone(),
+                    );
+                }
+                let mut evm = Evm::new(env, db);
+
+                let res = match evm.transact() {
+                    Ok(res) => res,
+                    // Invalid transactions are rejected before execution
+                    Err(_) if test.expect_exception.is_some() => continue,
+                    Err(err) => return Err(format!("{err:?}").into()),
+                };
+
+                if test.expect_exception.is_some() {
+                    assert!(!res.result.is_success());
+                    // NOTE: the expect_exception string is an error description, we don't check the expected error
+                    continue;
+                }
+
+                assert!(res.result.is_success());
+                assert_eq!(res.result.output().cloned(), unit.out);
+
+                // TODO: use rlp and hash to check logs
 
-            assert!(res.result.is_success());
-            assert_eq!(res.result.output().cloned(), unit.out);
-
-            // TODO: use rlp and hash to check logs
-
-            // Test the resulting storage is the same as the expected storage
-            let mut result_state = HashMap::new();
-            for address in test.post_state.keys() {
-                let account = res.state.get(address).unwrap();
-                result_state.insert(
-                    address.to_owned(),
-                    AccountInfo {
-                        balance: account.info.balance,
-                        code: account.info.code.clone().unwrap(),
-                        nonce: account.info.nonce,
-                        storage: account
-                            .storage
-                            .clone()
-                            .into_iter()
-                            .map(|(addr, slot)| (addr, slot.present_value))
-                            .collect(),
-                    },
-                );
+                // Test the resulting storage is the same as the expected storage
+                let mut result_state = HashMap::new();
+                for address in test.post_state.keys() {
+                    let account = res.state.get(address).unwrap();
+                    result_state.insert(
+                        address.to_owned(),
+                        AccountInfo {
+                            balance: account.info.balance,
+                            code: account.info.code.clone().unwrap(),
+                            nonce: account.info.nonce,
+                            storage: account
+                                .storage
+                                .clone()
+                                .into_iter()
+                                .map(|(addr, slot)| (addr, slot.present_value))
+                                .collect(),
+                        },
+                    );
+                }
+                assert_eq!(test.post_state, result_state);
             }
-            assert_eq!(test.post_state, result_state);
         }
     }
     Ok(())
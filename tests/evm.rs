@@ -6,17 +6,24 @@ use evm_mlir::{
     constants::{
         call_opcode, gas_cost,
         precompiles::{
-            BLAKE2F_ADDRESS, ECRECOVER_ADDRESS, IDENTITY_ADDRESS, MODEXP_ADDRESS,
-            RIPEMD_160_ADDRESS, SHA2_256_ADDRESS,
+            BLAKE2F_ADDRESS, ECADD_ADDRESS, ECMUL_ADDRESS, ECPAIRING_ADDRESS, ECRECOVER_ADDRESS,
+            IDENTITY_ADDRESS, MODEXP_ADDRESS, POINT_EVALUATION_ADDRESS, RIPEMD_160_ADDRESS,
+            SHA2_256_ADDRESS,
         },
-        EMPTY_CODE_HASH_STR,
+        EMPTY_CODE_HASH_STR, GAS_PER_BLOB, MAX_BLOB_GAS_PER_BLOCK, MAX_BLOB_NUMBER_PER_BLOCK,
+        TARGET_BLOB_GAS_PER_BLOCK, VERSIONED_HASH_VERSION_KZG,
     },
-    db::{Bytecode, Database, Db},
-    env::TransactTo,
+    db::{AccountInfo, Bytecode, Database, DatabaseError, Db},
+    env::{CfgEnv, ChainConfig, TransactTo},
     primitives::{Address, Bytes, B256, U256 as EU256},
     program::{Operation, Program},
+    result::{
+        EVMError, ExecutionResult, HaltReason, InvalidHeader, InvalidTransaction, OutOfGasError,
+        Output, ResultAndState, SuccessReason,
+    },
+    spec::SpecId,
     syscall::{LogData, U256},
-    utils::compute_contract_address2,
+    utils::{calc_excess_blob_gas, compute_contract_address, compute_contract_address2},
     Env, Evm,
 };
 
@@ -80,8 +87,10 @@ fn run_program_assert_gas_exact_with_db(mut env: Env, db: Db, needed_gas: u64) {
     assert!(result.is_halt());
 }
 
-fn run_program_assert_gas_exact(operations: Vec<Operation>, env: Env, needed_gas: u64) {
+fn run_program_assert_gas_exact(operations: Vec<Operation>, mut env: Env, needed_gas: u64) {
     let address = env.tx.get_address();
+    // The sender can't be the contract itself (EIP-3607)
+    env.tx.caller = Address::from_low_u64_be(4321);
 
     //Ok run
     let program = Program::from(operations.clone());
@@ -116,10 +125,23 @@ fn run_program_assert_gas_and_refund(
 
     let result = evm.transact_commit().unwrap();
     assert!(result.is_success());
-    assert_eq!(result.gas_used(), used_gas);
+    // The intrinsic cost is also charged, and the refund is given back at the end
+    assert_eq!(
+        result.gas_used(),
+        gas_cost::TX_BASE_COST + used_gas - refunded_gas
+    );
     assert_eq!(result.gas_refunded(), refunded_gas);
 }
 
+/// Gas cost of expanding the memory from `last_size` to `new_size` bytes
+fn memory_expansion_cost(last_size: u32, new_size: u32) -> i64 {
+    let memory_cost = |size: u32| {
+        let words = (size as i64 + 31) / 32;
+        words * words / 512 + 3 * words
+    };
+    memory_cost(new_size) - memory_cost(last_size)
+}
+
 fn get_fibonacci_program(n: u64) -> Vec<Operation> {
     assert!(n > 0, "n must be greater than 0");
 
@@ -609,7 +631,10 @@ fn log1() {
     let mut env = Env::default();
     env.tx.gas_limit = 999_999;
 
-    let (address, bytecode) = (Address::zero(), Bytecode::from(program.to_bytecode()));
+    let (address, bytecode) = (
+        Address::from_low_u64_be(40),
+        Bytecode::from(program.to_bytecode()),
+    );
     env.tx.transact_to = TransactTo::Call(address);
     let db = Db::new().with_contract(address, bytecode);
     let mut evm = Evm::new(env, db);
@@ -777,6 +802,61 @@ fn log4() {
     assert_eq!(logs, expected_logs);
 }
 
+#[rstest]
+#[case(false)]
+#[case(true)]
+fn logs_from_nested_calls_are_tagged_and_kept_unless_reverted(#[case] callee_reverts: bool) {
+    let caller_address = Address::from_low_u64_be(4040);
+    let callee_address = Address::from_low_u64_be(8080);
+
+    // Callee emits an empty log, and then reverts or stops
+    let mut callee_ops = vec![Operation::Push0, Operation::Push0, Operation::Log(0)];
+    if callee_reverts {
+        callee_ops.extend([Operation::Push0, Operation::Push0, Operation::Revert]);
+    }
+    let callee_bytecode = Bytecode::from(Program::from(callee_ops).to_bytecode());
+
+    // Caller calls the callee, and then emits an empty log too
+    let caller_ops = vec![
+        Operation::Push0, //Ret size
+        Operation::Push0, //Ret offset
+        Operation::Push0, //Args size
+        Operation::Push0, //Args offset
+        Operation::Push0, //Value
+        Operation::Push((20_u8, BigUint::from_bytes_be(callee_address.as_bytes()))), //Address
+        Operation::Push((3_u8, BigUint::from(100_000_u32))), //Gas
+        Operation::Call,
+        Operation::Pop,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Log(0),
+    ];
+    let caller_bytecode = Bytecode::from(Program::from(caller_ops).to_bytecode());
+
+    let mut env = Env::default();
+    env.tx.gas_limit = 999_999;
+    env.tx.transact_to = TransactTo::Call(caller_address);
+    let db = Db::new()
+        .with_contract(caller_address, caller_bytecode)
+        .with_contract(callee_address, callee_bytecode);
+    let mut evm = Evm::new(env, db);
+
+    let result = evm.transact_commit().unwrap();
+    assert!(result.is_success());
+
+    let log_addresses: Vec<Address> = result
+        .into_logs()
+        .into_iter()
+        .map(|log| log.address)
+        .collect();
+    let expected_addresses = if callee_reverts {
+        vec![caller_address]
+    } else {
+        vec![callee_address, caller_address]
+    };
+    assert_eq!(log_addresses, expected_addresses);
+}
+
 #[test]
 fn codecopy() {
     let size = 12_u8;
@@ -852,16 +932,397 @@ fn callvalue_happy_path() {
     let callvalue: u32 = 1500;
     let mut operations = vec![Operation::Callvalue];
     append_return_result_operations(&mut operations);
-    let mut env = Env::default();
-    env.tx.gas_limit = 999_999;
+    let (mut env, mut db) = default_env_and_db_setup(operations);
     env.tx.value = EU256::from(callvalue);
-    let program = Program::from(operations);
-    let bytecode = Bytecode::from(program.to_bytecode());
-    let db = Db::new().with_contract(Address::zero(), bytecode);
+    db.set_balance(env.tx.caller, EU256::from(callvalue));
     let expected_result = BigUint::from(callvalue);
     run_program_assert_num_result(env, db, expected_result);
 }
 
+#[test]
+fn transaction_fees_and_value_are_settled() {
+    let operations = vec![Operation::Push0];
+    let (mut env, mut db) = default_env_and_db_setup(operations);
+    let caller = Address::from_low_u64_be(1000);
+    let coinbase = Address::from_low_u64_be(2000);
+    let callee = env.tx.get_address();
+    let caller_balance = EU256::from(10_000_000);
+    let value = EU256::from(1000);
+    let gas_price = EU256::from(10);
+    let basefee = EU256::from(7);
+    env.tx.caller = caller;
+    env.tx.value = value;
+    env.tx.gas_price = gas_price;
+    env.tx.gas_limit = 100_000;
+    env.block.basefee = basefee;
+    env.block.coinbase = coinbase;
+    db.set_account(caller, 3, caller_balance, HashMap::new());
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    assert!(result.is_success());
+
+    let gas_used = EU256::from(gas_cost::TX_BASE_COST + gas_cost::PUSH0 as u64);
+    let caller_account = evm.db.basic(caller).unwrap().unwrap();
+    let callee_account = evm.db.basic(callee).unwrap().unwrap();
+    let coinbase_account = evm.db.basic(coinbase).unwrap().unwrap();
+    assert_eq!(caller_account.nonce, 4);
+    assert_eq!(
+        caller_account.balance,
+        caller_balance - value - gas_used * gas_price
+    );
+    assert_eq!(callee_account.balance, value);
+    assert_eq!(coinbase_account.balance, gas_used * (gas_price - basefee));
+}
+
+#[test]
+fn coinbase_gets_the_whole_gas_price_before_london() {
+    let (mut env, mut db) = default_env_and_db_setup(vec![Operation::Stop]);
+    let caller = Address::from_low_u64_be(1000);
+    let coinbase = Address::from_low_u64_be(2000);
+    let gas_price = EU256::from(10);
+    env.cfg.spec_id = SpecId::Berlin;
+    env.tx.caller = caller;
+    env.tx.gas_price = gas_price;
+    env.tx.gas_limit = 100_000;
+    env.block.basefee = EU256::from(7);
+    env.block.coinbase = coinbase;
+    db.set_account(caller, 0, EU256::from(10_000_000), HashMap::new());
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    assert!(result.is_success());
+
+    let gas_used = EU256::from(gas_cost::TX_BASE_COST);
+    let coinbase_account = evm.db.basic(coinbase).unwrap().unwrap();
+    assert_eq!(coinbase_account.balance, gas_used * gas_price);
+}
+
+#[test]
+fn transaction_from_account_with_code_is_rejected() {
+    let (mut env, db) = default_env_and_db_setup(vec![Operation::Stop]);
+    env.tx.caller = env.tx.get_address();
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact();
+    assert_eq!(
+        result.unwrap_err(),
+        EVMError::Transaction(InvalidTransaction::RejectCallerWithCode)
+    );
+}
+
+#[test]
+fn transaction_nonce_is_checked_against_caller_nonce() {
+    let (mut env, mut db) = default_env_and_db_setup(vec![Operation::Stop]);
+    let caller = Address::from_low_u64_be(1000);
+    env.tx.caller = caller;
+    db.set_account(caller, 3, EU256::zero(), HashMap::new());
+
+    env.tx.nonce = Some(4);
+    let mut evm = Evm::new(env.clone(), db.clone());
+    assert_eq!(
+        evm.transact().unwrap_err(),
+        EVMError::Transaction(InvalidTransaction::NonceTooHigh { tx: 4, state: 3 })
+    );
+
+    env.tx.nonce = Some(2);
+    let mut evm = Evm::new(env.clone(), db.clone());
+    assert_eq!(
+        evm.transact().unwrap_err(),
+        EVMError::Transaction(InvalidTransaction::NonceTooLow { tx: 2, state: 3 })
+    );
+
+    env.tx.nonce = Some(3);
+    let mut evm = Evm::new(env, db);
+    assert!(evm.transact().unwrap().result.is_success());
+}
+
+#[test]
+fn transaction_without_funds_for_max_fee_is_rejected() {
+    let (mut env, mut db) = default_env_and_db_setup(vec![Operation::Stop]);
+    let caller = Address::from_low_u64_be(1000);
+    let gas_price = EU256::from(10);
+    env.tx.caller = caller;
+    env.tx.gas_limit = 100_000;
+    env.tx.gas_price = gas_price;
+    env.tx.value = EU256::from(1);
+    let max_fee = gas_price * env.tx.gas_limit + env.tx.value;
+    db.set_balance(caller, max_fee - 1);
+
+    let mut evm = Evm::new(env, db);
+    assert_eq!(
+        evm.transact().unwrap_err(),
+        EVMError::Transaction(InvalidTransaction::LackOfFundForMaxFee {
+            fee: Box::new(max_fee),
+            balance: Box::new(max_fee - 1),
+        })
+    );
+}
+
+fn env_with_blobs(env: &mut Env, blob_count: usize, excess_blob_gas: u64) {
+    let mut blob_hash = B256::zero();
+    blob_hash.0[0] = VERSIONED_HASH_VERSION_KZG;
+    env.tx.blob_hashes = vec![blob_hash; blob_count];
+    env.block.set_blob_base_fee(excess_blob_gas);
+    env.tx.max_fee_per_blob_gas = Some(EU256::from(env.block.blob_gasprice.unwrap()));
+}
+
+#[test]
+fn blob_fee_is_charged_to_the_caller_and_burned() {
+    let (mut env, mut db) = default_env_and_db_setup(vec![Operation::Stop]);
+    let caller = Address::from_low_u64_be(1000);
+    let coinbase = Address::from_low_u64_be(2000);
+    let caller_balance = EU256::from(10_000_000);
+    env.tx.caller = caller;
+    env.block.coinbase = coinbase;
+    // Makes the blob gas price bigger than the minimum
+    env_with_blobs(&mut env, 2, 10_000_000);
+    db.set_balance(caller, caller_balance);
+
+    let blob_fee = EU256::from(env.block.blob_gasprice.unwrap()) * 2 * GAS_PER_BLOB;
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    assert!(result.is_success());
+
+    let caller_account = evm.db.basic(caller).unwrap().unwrap();
+    assert_eq!(caller_account.balance, caller_balance - blob_fee);
+    assert!(evm
+        .db
+        .basic(coinbase)
+        .unwrap()
+        .unwrap_or_default()
+        .balance
+        .is_zero());
+}
+
+#[test]
+fn transaction_without_funds_for_max_blob_fee_is_rejected() {
+    let (mut env, mut db) = default_env_and_db_setup(vec![Operation::Stop]);
+    let caller = Address::from_low_u64_be(1000);
+    env.tx.caller = caller;
+    env_with_blobs(&mut env, 1, 0);
+    let max_fee_per_blob_gas = EU256::from(2);
+    env.tx.max_fee_per_blob_gas = Some(max_fee_per_blob_gas);
+    let max_fee = max_fee_per_blob_gas * GAS_PER_BLOB;
+    db.set_balance(caller, max_fee - 1);
+
+    let mut evm = Evm::new(env, db);
+    assert_eq!(
+        evm.transact().unwrap_err(),
+        EVMError::Transaction(InvalidTransaction::LackOfFundForMaxFee {
+            fee: Box::new(max_fee),
+            balance: Box::new(max_fee - 1),
+        })
+    );
+}
+
+#[test]
+fn transaction_with_blobs_and_no_max_blob_fee_is_rejected() {
+    let (mut env, mut db) = default_env_and_db_setup(vec![Operation::Stop]);
+    let caller = Address::from_low_u64_be(1000);
+    env.tx.caller = caller;
+    // Makes the blob fee bigger than the caller's balance
+    env_with_blobs(&mut env, 1, 10_000_000);
+    env.tx.max_fee_per_blob_gas = None;
+    db.set_balance(caller, EU256::from(1));
+
+    let mut evm = Evm::new(env, db);
+    assert_eq!(
+        evm.transact().unwrap_err(),
+        EVMError::Transaction(InvalidTransaction::BlobVersionedHashesNotSupported)
+    );
+}
+
+#[rstest]
+#[case(MAX_BLOB_NUMBER_PER_BLOCK as usize, true)]
+#[case(MAX_BLOB_NUMBER_PER_BLOCK as usize + 1, false)]
+fn blob_count_is_limited_per_block(#[case] blob_count: usize, #[case] is_valid: bool) {
+    let (mut env, mut db) = default_env_and_db_setup(vec![Operation::Stop]);
+    env_with_blobs(&mut env, blob_count, 0);
+    db.set_balance(env.tx.caller, EU256::from(GAS_PER_BLOB) * blob_count as u64);
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact();
+    if is_valid {
+        assert!(result.unwrap().result.is_success());
+    } else {
+        assert_eq!(
+            result.unwrap_err(),
+            EVMError::Transaction(InvalidTransaction::TooManyBlobs {
+                have: blob_count,
+                max: MAX_BLOB_NUMBER_PER_BLOCK as usize,
+            })
+        );
+    }
+}
+
+#[test]
+fn excess_blob_gas_is_relative_to_the_target() {
+    assert_eq!(calc_excess_blob_gas(0, TARGET_BLOB_GAS_PER_BLOCK - 1), 0);
+    assert_eq!(
+        calc_excess_blob_gas(0, MAX_BLOB_GAS_PER_BLOCK),
+        TARGET_BLOB_GAS_PER_BLOCK
+    );
+    assert_eq!(
+        calc_excess_blob_gas(GAS_PER_BLOB, TARGET_BLOB_GAS_PER_BLOCK),
+        GAS_PER_BLOB
+    );
+}
+
+#[test]
+fn excess_blob_gas_saturates_on_overflowing_headers() {
+    assert_eq!(
+        calc_excess_blob_gas(u64::MAX, MAX_BLOB_GAS_PER_BLOCK),
+        u64::MAX - TARGET_BLOB_GAS_PER_BLOCK
+    );
+}
+
+#[test]
+fn transaction_fee_fields_are_checked_against_block_and_config() {
+    let (env, db) = default_env_and_db_setup(vec![Operation::Stop]);
+
+    let mut wrong_chain = env.clone();
+    wrong_chain.cfg.chain_id = 1;
+    wrong_chain.tx.chain_id = Some(2);
+    let mut evm = Evm::new(wrong_chain, db.clone());
+    assert_eq!(
+        evm.transact().unwrap_err(),
+        EVMError::Transaction(InvalidTransaction::InvalidChainId)
+    );
+
+    let mut over_block_limit = env.clone();
+    over_block_limit.block.gas_limit = EU256::from(over_block_limit.tx.gas_limit - 1);
+    let mut evm = Evm::new(over_block_limit, db.clone());
+    assert_eq!(
+        evm.transact().unwrap_err(),
+        EVMError::Transaction(InvalidTransaction::CallerGasLimitMoreThanBlock)
+    );
+
+    let mut under_basefee = env.clone();
+    under_basefee.block.basefee = EU256::from(10);
+    under_basefee.tx.gas_price = EU256::from(9);
+    let mut evm = Evm::new(under_basefee, db.clone());
+    assert_eq!(
+        evm.transact().unwrap_err(),
+        EVMError::Transaction(InvalidTransaction::GasPriceLessThanBasefee)
+    );
+
+    let mut priority_over_max = env;
+    priority_over_max.tx.gas_price = EU256::from(10);
+    priority_over_max.tx.gas_priority_fee = Some(EU256::from(11));
+    let mut evm = Evm::new(priority_over_max, db);
+    assert_eq!(
+        evm.transact().unwrap_err(),
+        EVMError::Transaction(InvalidTransaction::PriorityFeeGreaterThanMaxFee)
+    );
+}
+
+#[rstest]
+#[case::balance(
+    |env: &mut Env| env.tx.gas_price = EU256::from(10),
+    |cfg: &mut CfgEnv| cfg.disable_balance_check = true
+)]
+#[case::nonce(
+    |env: &mut Env| env.tx.nonce = Some(5),
+    |cfg: &mut CfgEnv| cfg.disable_nonce_check = true
+)]
+#[case::base_fee(
+    |env: &mut Env| env.block.basefee = EU256::from(10),
+    |cfg: &mut CfgEnv| cfg.disable_base_fee = true
+)]
+#[case::block_gas_limit(
+    |env: &mut Env| env.block.gas_limit = EU256::from(env.tx.gas_limit - 1),
+    |cfg: &mut CfgEnv| cfg.disable_block_gas_limit = true
+)]
+#[case::eip3607(
+    |env: &mut Env| env.tx.caller = env.tx.get_address(),
+    |cfg: &mut CfgEnv| cfg.disable_eip3607 = true
+)]
+fn transaction_checks_can_be_disabled_one_at_a_time(
+    #[case] break_rule: fn(&mut Env),
+    #[case] relax_rule: fn(&mut CfgEnv),
+) {
+    let (mut env, db) = default_env_and_db_setup(vec![Operation::Stop]);
+    break_rule(&mut env);
+
+    let mut evm = Evm::new(env.clone(), db.clone());
+    assert!(matches!(evm.transact(), Err(EVMError::Transaction(_))));
+
+    relax_rule(&mut env.cfg);
+    let mut evm = Evm::new(env, db);
+    assert!(evm.transact().unwrap().result.is_success());
+}
+
+#[rstest]
+#[case(None, true)]
+#[case(Some(2), true)]
+#[case(Some(1), false)]
+fn contract_code_size_limit_can_be_configured(
+    #[case] limit: Option<usize>,
+    #[case] is_deployed: bool,
+) {
+    // Init code returning 2 bytes of runtime code
+    let init_code = vec![0x60, 0x02, 0x5f, 0xf3];
+    let mut env = Env::default();
+    env.tx.transact_to = TransactTo::Create;
+    env.tx.gas_limit = 999_999;
+    env.tx.data = Bytes::from(init_code);
+    env.cfg.limit_contract_code_size = limit;
+
+    let mut evm = Evm::new(env, Db::new());
+    let result = evm.transact_commit().unwrap();
+    if is_deployed {
+        assert!(result.is_success());
+    } else {
+        assert!(matches!(
+            result,
+            ExecutionResult::Halt {
+                reason: HaltReason::CreateContractSizeLimit,
+                ..
+            }
+        ));
+    }
+}
+
+#[test]
+fn initcode_size_limit_can_be_configured() {
+    let mut env = Env::default();
+    env.tx.transact_to = TransactTo::Create;
+    env.tx.gas_limit = 999_999;
+    env.tx.data = Bytes::from(vec![0x00; 4]);
+    env.cfg.limit_initcode_size = Some(3);
+
+    let mut evm = Evm::new(env, Db::new());
+    assert_eq!(
+        evm.transact().unwrap_err(),
+        EVMError::Transaction(InvalidTransaction::CreateInitCodeSizeLimit)
+    );
+}
+
+#[rstest]
+#[case(None, 1_u8)]
+#[case(Some(0), 0_u8)]
+fn call_depth_limit_can_be_overridden(#[case] limit: Option<usize>, #[case] call_result: u8) {
+    let mut operations = call_ops_with_value(Operation::Call, 0, Address::from_low_u64_be(9090));
+    append_return_result_operations(&mut operations);
+    let (mut env, db) = default_env_and_db_setup(operations);
+    env.cfg.limit_call_depth = limit;
+
+    run_program_assert_num_result(env, db, call_result.into());
+}
+
+#[test]
+fn gasprice_is_the_effective_gas_price_for_eip1559_transactions() {
+    let mut operations = vec![Operation::Gasprice];
+    append_return_result_operations(&mut operations);
+    let (mut env, mut db) = default_env_and_db_setup(operations);
+    env.block.basefee = EU256::from(7);
+    env.tx.gas_price = EU256::from(20);
+    env.tx.gas_priority_fee = Some(EU256::from(2));
+    db.set_balance(env.tx.caller, env.tx.gas_price * env.tx.gas_limit);
+    run_program_assert_num_result(env, db, BigUint::from(9_u8));
+}
+
 #[test]
 fn callvalue_gas_check() {
     let operations = vec![Operation::Callvalue];
@@ -942,8 +1403,10 @@ fn basefee() {
     let basefee = 10_u8;
     let mut operations = vec![Operation::Basefee];
     append_return_result_operations(&mut operations);
-    let (mut env, db) = default_env_and_db_setup(operations);
+    let (mut env, mut db) = default_env_and_db_setup(operations);
     env.block.basefee = EU256::from(basefee);
+    env.tx.gas_price = EU256::from(basefee);
+    db.set_balance(env.tx.caller, EU256::from(basefee) * env.tx.gas_limit);
     let expected_result = BigUint::from(basefee);
     run_program_assert_num_result(env, db, expected_result);
 }
@@ -1043,8 +1506,9 @@ fn gasprice_happy_path() {
     let gas_price: u32 = 33192;
     let mut operations = vec![Operation::Gasprice];
     append_return_result_operations(&mut operations);
-    let (mut env, db) = default_env_and_db_setup(operations);
+    let (mut env, mut db) = default_env_and_db_setup(operations);
     env.tx.gas_price = EU256::from(gas_price);
+    db.set_balance(env.tx.caller, env.tx.gas_price * env.tx.gas_limit);
     let expected_result = BigUint::from(gas_price);
     run_program_assert_num_result(env, db, expected_result);
 }
@@ -1130,7 +1594,21 @@ fn sload_gas_consumption() {
         Operation::Push((1_u8, BigUint::from(1_u8))),
         Operation::Sload,
     ];
-    let result = gas_cost::PUSHN + gas_cost::SLOAD;
+    let result = gas_cost::PUSHN + gas_cost::COLD_SLOAD_COST;
+    let env = Env::default();
+
+    run_program_assert_gas_exact(program, env, result as _);
+}
+
+#[test]
+fn sload_warm_key_gas_consumption() {
+    let program = vec![
+        Operation::Push((1_u8, BigUint::from(1_u8))),
+        Operation::Sload,
+        Operation::Push((1_u8, BigUint::from(1_u8))),
+        Operation::Sload,
+    ];
+    let result = gas_cost::PUSHN * 2 + gas_cost::COLD_SLOAD_COST + gas_cost::WARM_STORAGE_READ_COST;
     let env = Env::default();
 
     run_program_assert_gas_exact(program, env, result as _);
@@ -1249,7 +1727,6 @@ fn balance_with_invalid_address() {
         Address::from_slice(&a.to_bytes_be()[0..20]),
         Bytecode::from(program.to_bytecode()),
     );
-    env.tx.caller = address;
     env.tx.transact_to = TransactTo::Call(address);
     let mut db = Db::new().with_contract(address, bytecode);
 
@@ -1297,7 +1774,6 @@ fn balance_with_existing_account() {
         Address::from_str("0x9bbfed6889322e016e0a02ee459d306fc19545d8").unwrap(),
         Bytecode::from(program.to_bytecode()),
     );
-    env.tx.caller = address;
     env.tx.transact_to = TransactTo::Call(address);
     let mut db = Db::new().with_contract(address, bytecode);
 
@@ -1327,8 +1803,31 @@ fn balance_static_gas_check() {
         Operation::Balance,
     ];
     let env = Env::default();
-    let needed_gas = gas_cost::PUSHN + gas_cost::BALANCE;
+    // Precompile addresses are always warm
+    let needed_gas = gas_cost::PUSHN + gas_cost::WARM_STORAGE_READ_COST;
+
+    run_program_assert_gas_exact(operations, env, needed_gas as _);
+}
+
+#[test]
+fn balance_gas_check_with_cold_and_access_list_addresses() {
+    let address = Address::from_low_u64_be(1000);
+    let operations = vec![
+        Operation::Push((20_u8, BigUint::from_bytes_be(address.as_bytes()))),
+        Operation::Balance,
+    ];
+
+    // Cold access
+    let env = Env::default();
+    let needed_gas = gas_cost::PUSHN + gas_cost::COLD_ACCOUNT_ACCESS_COST;
+    run_program_assert_gas_exact(operations.clone(), env, needed_gas as _);
 
+    // The access list pre-warms the address
+    let mut env = Env::default();
+    env.tx.access_list = vec![(address, vec![])];
+    let needed_gas = gas_cost::PUSHN
+        + gas_cost::WARM_STORAGE_READ_COST
+        + gas_cost::TX_ACCESS_LIST_ADDRESS_COST as i64;
     run_program_assert_gas_exact(operations, env, needed_gas as _);
 }
 
@@ -1418,15 +1917,24 @@ fn blobbasefee_stack_overflow() {
 
 #[test]
 fn gaslimit_happy_path() {
-    let gaslimit: u64 = 300;
+    let gaslimit: u64 = 30_000_000;
     let mut operations = vec![Operation::Gaslimit];
     append_return_result_operations(&mut operations);
     let (mut env, db) = default_env_and_db_setup(operations);
-    env.tx.gas_limit = gaslimit + gas_cost::TX_BASE_COST;
+    env.block.gas_limit = EU256::from(gaslimit);
     let expected_result = BigUint::from(gaslimit);
     run_program_assert_num_result(env, db, expected_result);
 }
 
+#[test]
+fn gaslimit_is_the_block_gas_limit_and_not_the_transaction_one() {
+    let mut operations = vec![Operation::Gaslimit];
+    append_return_result_operations(&mut operations);
+    let (env, db) = default_env_and_db_setup(operations);
+    let expected_result = BigUint::from_bytes_be(&[0xff; 32]);
+    run_program_assert_num_result(env, db, expected_result);
+}
+
 #[test]
 fn gaslimit_gas_check() {
     let operations = vec![Operation::Gaslimit];
@@ -1569,51 +2077,187 @@ fn sstore_gas_cost_update_warm_from_zero() {
 }
 
 #[test]
-fn extcodecopy() {
-    // insert the program in the db with address = 100
-    // and then copy the program bytecode in memory
-    // with extcodecopy(address=100, dest_offset, offset, size)
-    let size = 14_u8;
-    let offset = 0_u8;
-    let dest_offset = 0_u8;
-    let address = 100_u8;
-    let program: Program = vec![
-        Operation::Push((1_u8, BigUint::from(size))),
-        Operation::Push((1_u8, BigUint::from(offset))),
-        Operation::Push((1_u8, BigUint::from(dest_offset))),
-        Operation::Push((1_u8, BigUint::from(address))),
-        Operation::ExtcodeCopy,
-        Operation::Push((1_u8, BigUint::from(size))),
-        Operation::Push((1_u8, BigUint::from(dest_offset))),
-        Operation::Return,
-    ]
-    .into();
+fn sstore_refund_is_capped_to_a_fifth_of_the_gas_used() {
+    let original_value = 10_u8;
+    let (key_a, key_b) = (80_u8, 81_u8);
 
-    let mut env = Env::default();
-    let (address, bytecode) = (
-        Address::from_low_u64_be(address.into()),
-        Bytecode::from(program.clone().to_bytecode()),
-    );
-    env.tx.transact_to = TransactTo::Call(address);
-    let db = Db::new().with_contract(address, bytecode);
-    let expected_result = program.to_bytecode();
-    run_program_assert_bytes_result(env, db, &expected_result);
+    // Each sstore clears a cold slot: gas_cost = 5_000, gas_refund = 4_800
+    let used_gas = 2 * (5_000 + gas_cost::PUSH0 + gas_cost::PUSHN) as u64;
+    let needed_gas = used_gas + gas_cost::SSTORE_MIN_REMAINING_GAS as u64;
+    let refunded_gas = (gas_cost::TX_BASE_COST + used_gas) / gas_cost::MAX_REFUND_QUOTIENT;
+    assert!(refunded_gas < 2 * 4_800);
+
+    let program = vec![
+        Operation::Push0,
+        Operation::Push((1_u8, BigUint::from(key_a))),
+        Operation::Sstore,
+        Operation::Push0,
+        Operation::Push((1_u8, BigUint::from(key_b))),
+        Operation::Sstore,
+    ];
+    let (env, mut db) = default_env_and_db_setup(program);
+    let callee = env.tx.get_address();
+    db.write_storage(callee, EU256::from(key_a), EU256::from(original_value));
+    db.write_storage(callee, EU256::from(key_b), EU256::from(original_value));
+
+    run_program_assert_gas_and_refund(env, db, needed_gas, used_gas, refunded_gas);
 }
 
 #[test]
-fn extcodecopy_with_offset_out_of_bounds() {
-    // copies to memory the bytecode from the 8th byte (offset = 8) with size = 12
-    // so the result must be [EXTCODECOPY, PUSH, size, PUSH, dest_offset, RETURN, 0,0,0,0,0,0]
-    let size = 12_u8;
-    let offset = 8_u8;
-    let dest_offset = 0_u8;
-    let address = 100_u8;
-    let program: Program = vec![
-        Operation::Push((1_u8, BigUint::from(size))),
-        Operation::Push((1_u8, BigUint::from(offset))),
-        Operation::Push((1_u8, BigUint::from(dest_offset))),
-        Operation::Push((1_u8, BigUint::from(address))),
-        Operation::ExtcodeCopy, // 8th byte
+fn sstore_refund_can_go_negative_in_a_nested_frame() {
+    let original_value = 10_u8;
+    let key = 80_u8;
+    let caller_address = Address::from_low_u64_be(4000);
+    let callee_address = Address::from_low_u64_be(5000);
+
+    // Restores the slot cleared by the caller: gas_refund = -2_000
+    let callee_ops = vec![
+        Operation::Push((1_u8, BigUint::from(original_value))),
+        Operation::Push((1_u8, BigUint::from(key))),
+        Operation::Sstore,
+    ];
+    let callee_bytecode = Bytecode::from(Program::from(callee_ops).to_bytecode());
+
+    // Clears the slot: gas_refund = 4_800
+    let mut caller_ops = vec![
+        Operation::Push0,
+        Operation::Push((1_u8, BigUint::from(key))),
+        Operation::Sstore,
+        Operation::Push0, //Ret size
+        Operation::Push0, //Ret offset
+        Operation::Push0, //Args size
+        Operation::Push0, //Args offset
+        Operation::Push((20_u8, BigUint::from_bytes_be(callee_address.as_bytes()))), //Address
+        Operation::Push((3_u8, BigUint::from(100_000_u32))), //Gas
+        Operation::DelegateCall,
+    ];
+    append_return_result_operations(&mut caller_ops);
+    let caller_bytecode = Bytecode::from(Program::from(caller_ops).to_bytecode());
+
+    let mut env = Env::default();
+    env.tx.gas_limit = 999_999;
+    env.tx.transact_to = TransactTo::Call(caller_address);
+    let mut db = Db::new()
+        .with_contract(caller_address, caller_bytecode)
+        .with_contract(callee_address, callee_bytecode);
+    db.write_storage(
+        caller_address,
+        EU256::from(key),
+        EU256::from(original_value),
+    );
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+
+    assert!(result.is_success());
+    assert_eq!(
+        result.output().unwrap().as_ref(),
+        &[[0_u8; 31], [1]].concat()
+    );
+    assert_eq!(result.gas_refunded(), 4_800 - 2_000);
+    assert_eq!(
+        evm.db.read_storage(caller_address, EU256::from(key)),
+        EU256::from(original_value)
+    );
+}
+
+#[test]
+fn sstore_refund_of_a_reverted_frame_is_discarded() {
+    let original_value = 10_u8;
+    let key = 80_u8;
+    let caller_address = Address::from_low_u64_be(4000);
+    let callee_address = Address::from_low_u64_be(5000);
+
+    // Clears its slot (gas_refund = 4_800) and then reverts
+    let callee_ops = vec![
+        Operation::Push0,
+        Operation::Push((1_u8, BigUint::from(key))),
+        Operation::Sstore,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Revert,
+    ];
+    let callee_bytecode = Bytecode::from(Program::from(callee_ops).to_bytecode());
+
+    let mut caller_ops = vec![
+        Operation::Push0, //Ret size
+        Operation::Push0, //Ret offset
+        Operation::Push0, //Args size
+        Operation::Push0, //Args offset
+        Operation::Push0, //Value
+        Operation::Push((20_u8, BigUint::from_bytes_be(callee_address.as_bytes()))), //Address
+        Operation::Push((3_u8, BigUint::from(100_000_u32))), //Gas
+        Operation::Call,
+    ];
+    append_return_result_operations(&mut caller_ops);
+    let caller_bytecode = Bytecode::from(Program::from(caller_ops).to_bytecode());
+
+    let mut env = Env::default();
+    env.tx.gas_limit = 999_999;
+    env.tx.transact_to = TransactTo::Call(caller_address);
+    let mut db = Db::new()
+        .with_contract(caller_address, caller_bytecode)
+        .with_contract(callee_address, callee_bytecode);
+    db.write_storage(
+        callee_address,
+        EU256::from(key),
+        EU256::from(original_value),
+    );
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+
+    assert!(result.is_success());
+    assert_eq!(result.output().unwrap().as_ref(), &[0_u8; 32]);
+    assert_eq!(result.gas_refunded(), 0);
+}
+
+#[test]
+fn extcodecopy() {
+    // insert the program in the db with address = 100
+    // and then copy the program bytecode in memory
+    // with extcodecopy(address=100, dest_offset, offset, size)
+    let size = 14_u8;
+    let offset = 0_u8;
+    let dest_offset = 0_u8;
+    let address = 100_u8;
+    let program: Program = vec![
+        Operation::Push((1_u8, BigUint::from(size))),
+        Operation::Push((1_u8, BigUint::from(offset))),
+        Operation::Push((1_u8, BigUint::from(dest_offset))),
+        Operation::Push((1_u8, BigUint::from(address))),
+        Operation::ExtcodeCopy,
+        Operation::Push((1_u8, BigUint::from(size))),
+        Operation::Push((1_u8, BigUint::from(dest_offset))),
+        Operation::Return,
+    ]
+    .into();
+
+    let mut env = Env::default();
+    let (address, bytecode) = (
+        Address::from_low_u64_be(address.into()),
+        Bytecode::from(program.clone().to_bytecode()),
+    );
+    env.tx.transact_to = TransactTo::Call(address);
+    let db = Db::new().with_contract(address, bytecode);
+    let expected_result = program.to_bytecode();
+    run_program_assert_bytes_result(env, db, &expected_result);
+}
+
+#[test]
+fn extcodecopy_with_offset_out_of_bounds() {
+    // copies to memory the bytecode from the 8th byte (offset = 8) with size = 12
+    // so the result must be [EXTCODECOPY, PUSH, size, PUSH, dest_offset, RETURN, 0,0,0,0,0,0]
+    let size = 12_u8;
+    let offset = 8_u8;
+    let dest_offset = 0_u8;
+    let address = 100_u8;
+    let program: Program = vec![
+        Operation::Push((1_u8, BigUint::from(size))),
+        Operation::Push((1_u8, BigUint::from(offset))),
+        Operation::Push((1_u8, BigUint::from(dest_offset))),
+        Operation::Push((1_u8, BigUint::from(address))),
+        Operation::ExtcodeCopy, // 8th byte
         Operation::Push((1_u8, BigUint::from(size))),
         Operation::Push((1_u8, BigUint::from(dest_offset))),
         Operation::Return,
@@ -1757,9 +2401,38 @@ fn prevrandao_with_stack_overflow() {
 #[test]
 fn prevrandao_when_randao_is_not_set() {
     let program = vec![Operation::Prevrandao];
-    let (env, db) = default_env_and_db_setup(program);
-    let expected_result = 0_u8;
-    run_program_assert_num_result(env, db, expected_result.into());
+    let (mut env, db) = default_env_and_db_setup(program);
+    env.block.prevrandao = None;
+
+    let mut evm = Evm::new(env, db);
+    assert_eq!(
+        evm.transact().unwrap_err(),
+        EVMError::Header(InvalidHeader::PrevrandaoNotSet)
+    );
+}
+
+#[test]
+fn transaction_is_rejected_when_excess_blob_gas_is_not_set() {
+    let (mut env, db) = default_env_and_db_setup(vec![Operation::Stop]);
+    env.block.excess_blob_gas = None;
+
+    let mut evm = Evm::new(env, db);
+    assert_eq!(
+        evm.transact().unwrap_err(),
+        EVMError::Header(InvalidHeader::ExcessBlobGasNotSet)
+    );
+}
+
+#[test]
+fn transaction_is_rejected_when_blob_gasprice_is_not_set() {
+    let (mut env, db) = default_env_and_db_setup(vec![Operation::Stop]);
+    env.block.blob_gasprice = None;
+
+    let mut evm = Evm::new(env, db);
+    assert_eq!(
+        evm.transact().unwrap_err(),
+        EVMError::Header(InvalidHeader::BlobGasPriceNotSet)
+    );
 }
 
 #[test]
@@ -1792,14 +2465,13 @@ fn extcodesize_with_stack_underflow() {
 
 #[test]
 fn extcodesize_gas_check() {
-    // in this case we are not considering cold and warm accesses
-    // we assume every access is warm
+    // the address is accessed for the first time, so it's cold
     let address = 40_u8;
     let operations = vec![
         Operation::Push((1_u8, address.into())),
         Operation::ExtcodeSize,
     ];
-    let needed_gas = gas_cost::PUSHN + gas_cost::EXTCODESIZE_WARM;
+    let needed_gas = gas_cost::PUSHN + gas_cost::COLD_ACCOUNT_ACCESS_COST;
     let env = Env::default();
     run_program_assert_gas_exact(operations, env, needed_gas as _);
 }
@@ -1969,7 +2641,6 @@ fn call_simple_callee_call(#[case] call_type: Operation) {
     let mut env = Env::default();
     env.tx.gas_limit = 999_999;
     env.tx.transact_to = TransactTo::Call(caller_address);
-    env.tx.caller = caller_address;
     let db = db.with_contract(caller_address, bytecode);
 
     let mut evm = Evm::new(env, db);
@@ -2060,7 +2731,6 @@ fn call_addition_with_value_transfer(#[case] call_type: Operation) {
     let mut env = Env::default();
     env.tx.gas_limit = 999_999;
     env.tx.transact_to = TransactTo::Call(caller_address);
-    env.tx.caller = caller_address;
     let caller_balance = 100_u8;
     let mut db = db.with_contract(caller_address, bytecode);
     db.set_account(caller_address, 0, caller_balance.into(), Default::default());
@@ -2071,9 +2741,14 @@ fn call_addition_with_value_transfer(#[case] call_type: Operation) {
 
     let res_bytes: &[u8] = result.output().unwrap();
 
+    // CALLCODE sends the value to the caller itself
+    let transferred_value = match call_type {
+        Operation::Call => value,
+        _ => 0,
+    };
     let expected_contract_data_result = a + b;
-    let expected_caller_balance_result = (caller_balance - value).into();
-    let expected_callee_balance_result = (callee_balance + value).into();
+    let expected_caller_balance_result = (caller_balance - transferred_value).into();
+    let expected_callee_balance_result = (callee_balance + transferred_value).into();
     let expected_contract_status_result = 1_u8.into();
 
     let contract_data_result = BigUint::from_bytes_be(&res_bytes[..32]);
@@ -2150,7 +2825,6 @@ fn call_without_enough_balance(#[case] call_type: Operation) {
     let mut env = Env::default();
     env.tx.gas_limit = 999_999;
     env.tx.transact_to = TransactTo::Call(caller_address);
-    env.tx.caller = caller_address;
     let mut db = db.with_contract(caller_address, bytecode);
     db.set_account(caller_address, 0, caller_balance.into(), Default::default());
 
@@ -2208,10 +2882,8 @@ fn call_gas_check_with_value_zero_args_return_and_non_empty_callee(#[case] call_
         Operation::Return,
     ];
 
-    let callee_gas_cost = gas_cost::PUSHN
-        + gas_cost::PUSH0 * 3
-        + gas_cost::MSTORE
-        + gas_cost::memory_expansion_cost(0, 32);
+    let callee_gas_cost =
+        gas_cost::PUSHN + gas_cost::PUSH0 * 3 + gas_cost::MSTORE + memory_expansion_cost(0, 32);
 
     let program = Program::from(callee_ops);
     let (callee_address, bytecode) = (
@@ -2270,8 +2942,8 @@ fn call_gas_check_with_value_zero_args_return_and_non_empty_callee(#[case] call_
     let caller_gas_cost = gas_cost::PUSHN * (3 + nargs)
         + gas_cost::PUSH0
         + gas_cost::MSTORE * 2
-        + call_opcode::WARM_MEMORY_ACCESS_COST as i64
-        + gas_cost::memory_expansion_cost(0, 64);
+        + gas_cost::COLD_ACCOUNT_ACCESS_COST
+        + memory_expansion_cost(0, 64);
 
     let available_gas = 1e6;
     let needed_gas = caller_gas_cost + callee_gas_cost;
@@ -2282,7 +2954,6 @@ fn call_gas_check_with_value_zero_args_return_and_non_empty_callee(#[case] call_
     let bytecode = Bytecode::from(program.to_bytecode());
     let mut env = Env::default();
     env.tx.transact_to = TransactTo::Call(caller_address);
-    env.tx.caller = caller_address;
     let mut db = db.with_contract(caller_address, bytecode);
     db.set_account(caller_address, 0, caller_balance.into(), Default::default());
 
@@ -2382,7 +3053,6 @@ fn call_return_with_offset_and_size(
     let bytecode = Bytecode::from(program.to_bytecode());
     let mut env = Env::default();
     env.tx.transact_to = TransactTo::Call(caller_address);
-    env.tx.caller = caller_address;
     let db = db.with_contract(caller_address, bytecode);
 
     run_program_assert_bytes_result(env, db, expected_result);
@@ -2449,9 +3119,14 @@ fn call_gas_check_with_value_and_empty_account(#[case] call_type: Operation) {
     .concat();
 
     //address_access_cost + positive_value_cost + value_to_empty_account_cost
-    let caller_call_cost = call_opcode::WARM_MEMORY_ACCESS_COST
+    //CALLCODE doesn't send the value to the callee, so no account is created
+    let empty_callee_cost = match call_type {
+        Operation::Call => call_opcode::EMPTY_CALLEE_COST,
+        _ => 0,
+    };
+    let caller_call_cost = gas_cost::COLD_ACCOUNT_ACCESS_COST as u64
         + call_opcode::NOT_ZERO_VALUE_COST
-        + call_opcode::EMPTY_CALLEE_COST;
+        + empty_callee_cost;
     let needed_gas = gas_cost::PUSHN * 7 + caller_call_cost as i64;
 
     let caller_balance: u8 = 5;
@@ -2459,7 +3134,6 @@ fn call_gas_check_with_value_and_empty_account(#[case] call_type: Operation) {
     let bytecode = Bytecode::from(program.to_bytecode());
     let mut env = Env::default();
     env.tx.transact_to = TransactTo::Call(caller_address);
-    env.tx.caller = caller_address;
     let mut db = db.with_contract(caller_address, bytecode);
     db.set_account(caller_address, 0, caller_balance.into(), Default::default());
 
@@ -2535,6 +3209,7 @@ fn call_callee_returns_new_value(#[case] call_type: Operation) {
     env.tx.transact_to = TransactTo::Call(caller_address);
     env.tx.caller = origin;
     env.tx.value = origin_value.into();
+    db.set_balance(origin, origin_value.into());
 
     let expected_result = match call_type {
         Operation::StaticCall => 0,
@@ -2616,7 +3291,6 @@ fn call_callee_returns_caller(#[case] call_type: Operation) {
     run_program_assert_bytes_result(env, db, expected_result.as_fixed_bytes());
 }
 
-#[ignore] //This should be run when storage fix on CALL is made
 #[test]
 fn call_callee_storage_modified() {
     let db = Db::new();
@@ -2662,10 +3336,11 @@ fn call_callee_storage_modified() {
     let program = Program::from(caller_ops);
     let caller_bytecode = Bytecode::from(program.to_bytecode());
     let mut env = Env::default();
-    let db = db.with_contract(caller_address, caller_bytecode);
+    let mut db = db.with_contract(caller_address, caller_bytecode);
     env.tx.transact_to = TransactTo::Call(caller_address);
     env.tx.caller = origin;
     env.tx.value = origin_value.into();
+    db.set_balance(origin, origin_value.into());
 
     let mut evm = Evm::new(env, db);
     let res = evm.transact_commit().unwrap();
@@ -2675,6 +3350,237 @@ fn call_callee_storage_modified() {
     assert_eq!(stored_value, EU256::from(value));
 }
 
+#[rstest]
+#[case(Operation::CallCode)]
+#[case(Operation::DelegateCall)]
+fn call_with_caller_context_modifies_caller_storage(#[case] call_type: Operation) {
+    let key = 80_u8;
+    let value = 11_u8;
+    let callee_ops = vec![
+        Operation::Push((1_u8, BigUint::from(value))),
+        Operation::Push((1_u8, BigUint::from(key))),
+        Operation::Sstore,
+    ];
+    let program = Program::from(callee_ops);
+    let callee_address = Address::from_low_u64_be(8080);
+    let db = Db::new().with_contract(callee_address, Bytecode::from(program.to_bytecode()));
+
+    let caller_address = Address::from_low_u64_be(4040);
+    let value_op_vec = match call_type {
+        Operation::CallCode => vec![Operation::Push0],
+        _ => vec![],
+    };
+    let caller_ops = [
+        vec![
+            Operation::Push0, //Ret size
+            Operation::Push0, //Ret offset
+            Operation::Push0, //Args size
+            Operation::Push0, //Args offset
+        ],
+        value_op_vec,
+        vec![
+            Operation::Push((20_u8, BigUint::from_bytes_be(callee_address.as_bytes()))), //Address
+            Operation::Push((32_u8, BigUint::from(100_000_u32))),                        //Gas
+            call_type,
+        ],
+    ]
+    .concat();
+    let program = Program::from(caller_ops);
+    let db = db.with_contract(caller_address, Bytecode::from(program.to_bytecode()));
+    let mut env = Env::default();
+    env.tx.transact_to = TransactTo::Call(caller_address);
+
+    let mut evm = Evm::new(env, db);
+    let res = evm.transact_commit().unwrap();
+    assert!(res.is_success());
+
+    assert_eq!(
+        evm.db.read_storage(caller_address, key.into()),
+        EU256::from(value)
+    );
+    assert_eq!(
+        evm.db.read_storage(callee_address, key.into()),
+        EU256::zero()
+    );
+}
+
+#[test]
+fn origin_is_unchanged_in_nested_frames() {
+    let origin = Address::from_low_u64_be(79);
+    let mut callee_ops = vec![Operation::Origin];
+    append_return_result_operations(&mut callee_ops);
+    let (mut env, db) = staticcall_returning_callee_output(Db::new(), callee_ops);
+    env.tx.caller = origin;
+
+    let expected_result = BigUint::from_bytes_be(origin.as_bytes());
+    run_program_assert_num_result(env, db, expected_result);
+}
+
+#[test]
+fn codecopy_in_nested_frame_copies_callee_code() {
+    let callee_ops = vec![
+        Operation::Push((1_u8, 32_u8.into())),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Codecopy,
+        Operation::Push((1_u8, 32_u8.into())),
+        Operation::Push0,
+        Operation::Return,
+    ];
+    let callee_bytecode = Program::from(callee_ops.clone()).to_bytecode();
+    let (env, db) = staticcall_returning_callee_output(Db::new(), callee_ops);
+
+    let mut expected_result = [0_u8; 32];
+    expected_result[..callee_bytecode.len()].copy_from_slice(&callee_bytecode);
+    run_program_assert_bytes_result(env, db, &expected_result);
+}
+
+#[rstest]
+#[case::all_ones(BigUint::from_bytes_be(&[0xff; 32]))]
+// Wrapped to 64 bits, it would forward just 5 gas
+#[case::over_u64_max((BigUint::from(1_u8) << 64) + 5_u8)]
+fn call_forwards_all_but_one_64th_of_the_remaining_gas(#[case] gas: BigUint) {
+    let mut callee_ops = vec![Operation::Gas];
+    append_return_result_operations(&mut callee_ops);
+    let program = Program::from(callee_ops);
+    let callee_address = Address::from_low_u64_be(8080);
+    let db = Db::new().with_contract(callee_address, Bytecode::from(program.to_bytecode()));
+
+    let caller_address = Address::from_low_u64_be(4040);
+    let mut caller_ops = vec![
+        Operation::Push((1_u8, 32_u8.into())), //Ret size
+        Operation::Push0,                      //Ret offset
+        Operation::Push0,                      //Args size
+        Operation::Push0,                      //Args offset
+        Operation::Push0,                      //Value
+        Operation::Push((20_u8, BigUint::from_bytes_be(callee_address.as_bytes()))), //Address
+        Operation::Push((32_u8, gas)),         //Gas
+        Operation::Call,
+        Operation::Pop,
+        Operation::Push0,
+        Operation::Mload,
+    ];
+    append_return_result_operations(&mut caller_ops);
+    let program = Program::from(caller_ops);
+    let db = db.with_contract(caller_address, Bytecode::from(program.to_bytecode()));
+    let gas_limit = 1_000_000;
+    let mut env = Env::default();
+    env.tx.gas_limit = gas_limit;
+    env.tx.transact_to = TransactTo::Call(caller_address);
+
+    let gas_before_call = gas_limit as i64
+        - gas_cost::TX_BASE_COST as i64
+        - gas_cost::PUSHN * 3
+        - gas_cost::PUSH0 * 4
+        - memory_expansion_cost(0, 32)
+        - gas_cost::COLD_ACCOUNT_ACCESS_COST;
+    let forwarded_gas = gas_before_call - gas_before_call / 64;
+    let expected_result = forwarded_gas - gas_cost::GAS;
+
+    run_program_assert_num_result(env, db, BigUint::from(expected_result as u64));
+}
+
+#[test]
+fn call_with_empty_ranges_does_not_expand_memory() {
+    let callee_address = Address::from_low_u64_be(8080);
+    let mut operations = vec![
+        Operation::Push0,                           //Ret size
+        Operation::Push((2_u8, 0xffff_u16.into())), //Ret offset
+        Operation::Push0,                           //Args size
+        Operation::Push((2_u8, 0xffff_u16.into())), //Args offset
+        Operation::Push0,                           //Value
+        Operation::Push((20_u8, BigUint::from_bytes_be(callee_address.as_bytes()))), //Address
+        Operation::Push((2_u8, 1000_u16.into())),   //Gas
+        Operation::Call,
+        Operation::Pop,
+        Operation::Msize,
+    ];
+    append_return_result_operations(&mut operations);
+    let (env, db) = default_env_and_db_setup(operations);
+    let db = db.with_contract(callee_address, Bytecode::default());
+
+    run_program_assert_num_result(env, db, BigUint::ZERO);
+}
+
+#[test]
+fn call_with_value_to_non_existent_account_creates_it() {
+    let callee_address = Address::from_low_u64_be(9090);
+    let value = 7_u8;
+    let mut operations = call_ops_with_value(Operation::Call, value, callee_address);
+    append_return_result_operations(&mut operations);
+    let (env, mut db) = default_env_and_db_setup(operations);
+    db.set_account(
+        Address::from_low_u64_be(40),
+        0,
+        value.into(),
+        Default::default(),
+    );
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    assert!(result.is_success());
+    assert_eq!(
+        BigUint::from_bytes_be(result.output().unwrap()),
+        BigUint::from(1_u8)
+    );
+    let callee_balance = evm.db.basic(callee_address).unwrap().unwrap().balance;
+    assert_eq!(callee_balance, EU256::from(value));
+}
+
+#[test]
+fn call_recursion_up_to_max_depth_on_default_thread_stack() {
+    // The contract counts the frames it runs in and the calls that failed, and calls itself
+    // forwarding all its gas, until the call depth limit is hit
+    let frames_key = 0_u8;
+    let failed_calls_key = 1_u8;
+    let operations = vec![
+        // frames += 1
+        Operation::Push((1_u8, BigUint::from(frames_key))),
+        Operation::Sload,
+        Operation::Push((1_u8, BigUint::from(1_u8))),
+        Operation::Add,
+        Operation::Push((1_u8, BigUint::from(frames_key))),
+        Operation::Sstore,
+        Operation::Push0,   //Ret size
+        Operation::Push0,   //Ret offset
+        Operation::Push0,   //Args size
+        Operation::Push0,   //Args offset
+        Operation::Push0,   //Value
+        Operation::Address, //Address
+        Operation::Gas,     //Gas
+        Operation::Call,
+        // failed_calls += !success
+        Operation::IsZero,
+        Operation::Push((1_u8, BigUint::from(failed_calls_key))),
+        Operation::Sload,
+        Operation::Add,
+        Operation::Push((1_u8, BigUint::from(failed_calls_key))),
+        Operation::Sstore,
+        Operation::Stop,
+    ];
+    let (mut env, db) = default_env_and_db_setup(operations);
+    env.tx.gas_limit = 10_000_000_000_000;
+    let address = Address::from_low_u64_be(40);
+
+    let (result, frames, failed_calls) = std::thread::Builder::new()
+        .stack_size(8 * 1024 * 1024)
+        .spawn(move || {
+            let mut evm = Evm::new(env, db);
+            let result = evm.transact_commit().unwrap();
+            let frames = evm.db.read_storage(address, EU256::from(frames_key));
+            let failed_calls = evm.db.read_storage(address, EU256::from(failed_calls_key));
+            (result, frames, failed_calls)
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+
+    assert!(result.is_success());
+    // Frames at depths 0 through 1024 ran, and only the call made at depth 1024 failed
+    assert_eq!(frames, EU256::from(1025));
+    assert_eq!(failed_calls, EU256::from(1));
+}
+
 #[test]
 fn staticcall_on_precompile_ecrecover_happy_path() {
     let gas = 100_000_000_u32;
@@ -3051,13 +3957,83 @@ fn staticcall_on_precompile_blake2f_happy_path() {
     run_program_assert_bytes_result(env, db, &expected_result);
 }
 
-#[test]
-fn extcodehash_happy_path() {
-    let address_number = 10;
-    let mut operations = vec![
-        Operation::Push((1, BigUint::from(address_number))),
-        Operation::ExtcodeHash,
-    ];
+#[rstest]
+#[case::ecrecover(ECRECOVER_ADDRESS)]
+#[case::sha2_256(SHA2_256_ADDRESS)]
+fn staticcall_on_precompile_without_enough_gas_fails_consuming_the_gas_sent(
+    #[case] precompile: u64,
+) {
+    let callee_address = Address::from_low_u64_be(precompile);
+    let run_with_call_gas = |gas: u8| {
+        let mut operations = vec![
+            Operation::Push((1_u8, 32_u8.into())),  //Ret size
+            Operation::Push0,                       //Ret offset
+            Operation::Push((1_u8, 128_u8.into())), //Args size
+            Operation::Push0,                       //Args offset
+            Operation::Push((20_u8, BigUint::from_bytes_be(callee_address.as_bytes()))), //Address
+            Operation::Push((1_u8, gas.into())),    //Gas
+            Operation::StaticCall,
+        ];
+        append_return_result_operations(&mut operations);
+        let (env, db) = default_env_and_db_setup(operations);
+
+        let mut evm = Evm::new(env, db);
+        let result = evm.transact_commit().unwrap();
+        assert!(result.is_success());
+        // The call failed
+        assert_eq!(result.output().unwrap().as_ref(), &[0_u8; 32]);
+        result.gas_used()
+    };
+
+    // Neither call pays for the precompile, and both consume all the gas sent
+    assert_eq!(run_with_call_gas(100) - run_with_call_gas(50), 50);
+}
+
+#[rstest]
+#[case::ecadd(ECADD_ADDRESS)]
+#[case::ecmul(ECMUL_ADDRESS)]
+#[case::ecpairing(ECPAIRING_ADDRESS)]
+#[case::point_evaluation(POINT_EVALUATION_ADDRESS)]
+fn call_to_unimplemented_precompile_fails_the_transaction(#[case] precompile: u64) {
+    let callee_address = Address::from_low_u64_be(precompile);
+    let mut operations = vec![
+        Operation::Push0, //Ret size
+        Operation::Push0, //Ret offset
+        Operation::Push0, //Args size
+        Operation::Push0, //Args offset
+        Operation::Push0, //Value
+        Operation::Push((20_u8, BigUint::from_bytes_be(callee_address.as_bytes()))), //Address
+        Operation::Push((3_u8, BigUint::from(100_000_u32))), //Gas
+        Operation::Call,
+    ];
+    append_return_result_operations(&mut operations);
+    let (env, db) = default_env_and_db_setup(operations);
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact();
+    assert!(matches!(result, Err(EVMError::Precompile(_))));
+}
+
+#[test]
+fn unimplemented_precompiles_are_warm() {
+    let callee_address = Address::from_low_u64_be(ECPAIRING_ADDRESS);
+    let operations = vec![
+        Operation::Push((20_u8, BigUint::from_bytes_be(callee_address.as_bytes()))),
+        Operation::Balance,
+    ];
+    let env = Env::default();
+    let needed_gas = gas_cost::PUSHN + gas_cost::WARM_STORAGE_READ_COST;
+
+    run_program_assert_gas_exact(operations, env, needed_gas as _);
+}
+
+#[test]
+fn extcodehash_happy_path() {
+    let address_number = 10;
+    let mut operations = vec![
+        Operation::Push((1, BigUint::from(address_number))),
+        Operation::ExtcodeHash,
+    ];
     append_return_result_operations(&mut operations);
     let (env, mut db) = default_env_and_db_setup(operations);
     let bytecode = Bytecode::from_static(b"60806040");
@@ -3174,7 +4150,6 @@ fn returndatasize_happy_path() {
     let bytecode = Bytecode::from(program.to_bytecode());
     let mut env = Env::default();
     env.tx.transact_to = TransactTo::Call(caller_address);
-    env.tx.caller = caller_address;
     let db = db.with_contract(caller_address, bytecode);
 
     let expected_result = 32_u8.into();
@@ -3193,7 +4168,6 @@ fn returndatasize_no_return_data() {
     let bytecode = Bytecode::from(program.to_bytecode());
     let mut env = Env::default();
     env.tx.transact_to = TransactTo::Call(caller_address);
-    env.tx.caller = caller_address;
     let db = Db::default().with_contract(caller_address, bytecode);
 
     let expected_result = 0_u8.into();
@@ -3261,7 +4235,6 @@ fn returndatacopy_happy_path() {
     let bytecode = Bytecode::from(program.to_bytecode());
     let mut env = Env::default();
     env.tx.transact_to = TransactTo::Call(caller_address);
-    env.tx.caller = caller_address;
     let db = db.with_contract(caller_address, bytecode);
 
     let expected_result = return_value.into();
@@ -3299,7 +4272,6 @@ fn returndatacopy_no_return_data() {
     let bytecode = Bytecode::from(program.to_bytecode());
     let mut env = Env::default();
     env.tx.transact_to = TransactTo::Call(caller_address);
-    env.tx.caller = caller_address;
     let db = Db::default().with_contract(caller_address, bytecode);
 
     // There was no return data, so memory stays the same
@@ -3374,7 +4346,6 @@ fn returndatacopy_size_smaller_than_data() {
     let bytecode = Bytecode::from(program.to_bytecode());
     let mut env = Env::default();
     env.tx.transact_to = TransactTo::Call(caller_address);
-    env.tx.caller = caller_address;
     let db = db.with_contract(caller_address, bytecode);
 
     let expected_result = &[
@@ -3434,12 +4405,79 @@ fn returndatacopy_with_offset_and_size_bigger_than_data() {
     let bytecode = Bytecode::from(program.to_bytecode());
     let mut env = Env::default();
     env.tx.transact_to = TransactTo::Call(caller_address);
-    env.tx.caller = caller_address;
     let db = db.with_contract(caller_address, bytecode);
 
     run_program_assert_halt(env, db);
 }
 
+#[rstest]
+#[case(0_u8.into(), 1_u8.into())]
+#[case(BigUint::from(1_u64 << 32), BigUint::ZERO)]
+#[case(BigUint::from_bytes_be(&[0xff; 32]), 1_u8.into())]
+fn returndatacopy_out_of_bounds_halts_with_out_of_offset(
+    #[case] offset: BigUint,
+    #[case] size: BigUint,
+) {
+    let operations = vec![
+        Operation::Push((32_u8, size)),
+        Operation::Push((32_u8, offset)),
+        Operation::Push0,
+        Operation::ReturnDataCopy,
+        Operation::Stop,
+    ];
+    let (env, db) = default_env_and_db_setup(operations);
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    assert!(matches!(
+        result,
+        ExecutionResult::Halt {
+            reason: HaltReason::OutOfOffset,
+            ..
+        }
+    ));
+}
+
+fn create_after_call_returning_data(init_code: &[u8]) -> (Env, Db) {
+    let mut callee_ops = vec![Operation::Push((1_u8, 1_u8.into()))];
+    append_return_result_operations(&mut callee_ops);
+    let program = Program::from(callee_ops);
+    let callee_address = Address::from_low_u64_be(8080);
+
+    let mut operations = call_ops_with_value(Operation::Call, 0, callee_address);
+    operations.extend([
+        Operation::Pop,
+        // Store the init code at the end of the first word
+        Operation::Push((32_u8, BigUint::from_bytes_be(init_code))),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push((1_u8, init_code.len().into())), //Size
+        Operation::Push((1_u8, (32 - init_code.len()).into())), //Offset
+        Operation::Push0,                                //Value
+        Operation::Create,
+        Operation::Pop,
+        Operation::ReturnDataSize,
+    ]);
+    append_return_result_operations(&mut operations);
+    let (env, db) = default_env_and_db_setup(operations);
+    let db = db.with_contract(callee_address, Bytecode::from(program.to_bytecode()));
+    (env, db)
+}
+
+#[test]
+fn create_clears_the_return_data() {
+    // STOP
+    let (env, db) = create_after_call_returning_data(&[0x00]);
+    run_program_assert_num_result(env, db, BigUint::ZERO);
+}
+
+#[test]
+fn reverted_create_sets_the_return_data_to_its_output() {
+    // PUSH1 0x10 PUSH1 0x00 REVERT
+    let (env, db) = create_after_call_returning_data(&[0x60, 0x10, 0x60, 0x00, 0xfd]);
+    run_program_assert_num_result(env, db, 16_u8.into());
+}
+
 #[test]
 fn returndatacopy_check_stack_underflow() {
     let program = vec![Operation::ReturnDataCopy];
@@ -3501,18 +4539,17 @@ fn returndatacopy_gas_check() {
     let bytecode = Bytecode::from(program.to_bytecode());
     let mut env = Env::default();
     env.tx.transact_to = TransactTo::Call(caller_address);
-    env.tx.caller = caller_address;
     let db = db.with_contract(caller_address, bytecode);
 
     let callee_gas_cost = gas_cost::PUSHN * 2
         + gas_cost::PUSH0 * 2
         + gas_cost::MSTORE
-        + gas_cost::memory_expansion_cost(0, 32_u32); // Return data
+        + memory_expansion_cost(0, 32_u32); // Return data
     let caller_gas_cost = gas_cost::PUSHN * 10
         + gas_cost::CALL
-        + call_opcode::WARM_MEMORY_ACCESS_COST as i64
+        + gas_cost::COLD_ACCOUNT_ACCESS_COST
         + gas_cost::memory_copy_cost(size.into())
-        + gas_cost::memory_expansion_cost(0, (dest_offset + size) as u32)
+        + memory_expansion_cost(0, (dest_offset + size) as u32)
         + gas_cost::RETURNDATACOPY;
 
     let initial_gas = 1e5;
@@ -3549,14 +4586,13 @@ fn create_happy_path() {
         Operation::Create,
     ];
     append_return_result_operations(&mut operations);
-    let (mut env, mut db) = default_env_and_db_setup(operations);
+    let (env, mut db) = default_env_and_db_setup(operations);
     db.set_account(
         sender_addr,
         sender_nonce,
         sender_balance,
         Default::default(),
     );
-    env.tx.value = EU256::from(value);
     let mut evm = Evm::new(env, db);
     let result = evm.transact_commit().unwrap();
     assert!(result.is_success());
@@ -3576,6 +4612,67 @@ fn create_happy_path() {
     assert_eq!(sender_account.balance, sender_balance - value);
 }
 
+#[test]
+fn create_transaction_deploys_returned_code() {
+    let caller = Address::from_low_u64_be(1000);
+    let caller_nonce = 3;
+    let value = EU256::from(10);
+    // Code that returns the value 0xffffffff
+    let initialization_code = hex::decode("63FFFFFFFF6000526004601CF3").unwrap();
+    let runtime_code = Bytes::from(vec![0xff, 0xff, 0xff, 0xff]);
+
+    let mut env = Env::default();
+    env.tx.transact_to = TransactTo::Create;
+    env.tx.caller = caller;
+    env.tx.value = value;
+    env.tx.gas_limit = 999_999;
+    env.tx.data = Bytes::from(initialization_code);
+    let mut db = Db::new();
+    db.set_account(caller, caller_nonce, value, Default::default());
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    assert!(result.is_success());
+
+    let expected_address = compute_contract_address(caller, caller_nonce);
+    let ExecutionResult::Success { output, .. } = result else {
+        unreachable!()
+    };
+    assert_eq!(
+        output,
+        Output::Create(runtime_code.clone(), Some(expected_address))
+    );
+
+    let new_account = evm.db.basic(expected_address).unwrap().unwrap();
+    assert_eq!(new_account.balance, value);
+    assert_eq!(new_account.nonce, 1);
+    let new_account_code = evm.db.code_by_hash(new_account.code_hash).unwrap();
+    assert_eq!(new_account_code, runtime_code);
+
+    let caller_account = evm.db.basic(caller).unwrap().unwrap();
+    assert_eq!(caller_account.nonce, caller_nonce + 1);
+    assert_eq!(caller_account.balance, EU256::zero());
+}
+
+#[test]
+fn create_transaction_on_existing_contract_halts() {
+    let caller = Address::from_low_u64_be(1000);
+    let expected_address = compute_contract_address(caller, 0);
+
+    let mut env = Env::default();
+    env.tx.transact_to = TransactTo::Create;
+    env.tx.caller = caller;
+    env.tx.gas_limit = 999_999;
+    let db = Db::new().with_contract(expected_address, Bytecode::from(vec![0x00]));
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    assert!(result.is_halt());
+    assert_eq!(result.gas_used(), 999_999);
+    let caller_account = evm.db.basic(caller).unwrap().unwrap();
+    assert_eq!(caller_account.nonce, 1);
+}
+
 #[test]
 fn create_with_stack_underflow() {
     let operations = vec![Operation::Create];
@@ -3608,14 +4705,13 @@ fn create_with_balance_underflow() {
         Operation::Create,
     ];
     append_return_result_operations(&mut operations);
-    let (mut env, mut db) = default_env_and_db_setup(operations);
+    let (env, mut db) = default_env_and_db_setup(operations);
     db.set_account(
         sender_addr,
         sender_nonce,
         sender_balance,
         Default::default(),
     );
-    env.tx.value = EU256::from(value);
     let mut evm = Evm::new(env, db);
     let result = evm.transact_commit().unwrap();
 
@@ -3634,10 +4730,11 @@ fn create_with_invalid_initialization_code() {
     let value: u8 = 0;
     let offset: u8 = 19;
     let size: u8 = 13;
+    let sender_nonce = 1;
+    let sender_addr = Address::from_low_u64_be(40);
 
     // Code that halts
     let initialization_code = hex::decode("63ffffffff526004601cf3").unwrap();
-    let initialization_code_hash = B256::from_str(EMPTY_CODE_HASH_STR).unwrap();
 
     let mut operations = vec![
         // Store initialization code in memory
@@ -3651,17 +4748,53 @@ fn create_with_invalid_initialization_code() {
         Operation::Create,
     ];
     append_return_result_operations(&mut operations);
-    let (mut env, db) = default_env_and_db_setup(operations);
-    env.tx.value = EU256::from(value);
+    let (env, db) = default_env_and_db_setup(operations);
     let mut evm = Evm::new(env, db);
     let result = evm.transact_commit().unwrap();
 
-    // Check that contract is created in the returned address with empty bytecode
-    let returned_addr = Address::from_slice(&result.output().unwrap()[12..]);
-    let new_account = evm.db.basic(returned_addr).unwrap().unwrap();
-    assert_eq!(new_account.balance, EU256::from(value));
-    assert_eq!(new_account.nonce, 1);
-    assert_eq!(new_account.code_hash, initialization_code_hash);
+    // Check that the creation failed and no contract was created
+    assert!(result.is_success());
+    assert_eq!(result.output().unwrap().to_vec(), [0_u8; 32].to_vec());
+    let new_address = compute_contract_address(sender_addr, sender_nonce);
+    assert!(evm.db.basic(new_address).unwrap().is_none());
+
+    // Check that the sender's nonce is still increased
+    let sender_account = evm.db.basic(sender_addr).unwrap().unwrap();
+    assert_eq!(sender_account.nonce, sender_nonce + 1);
+}
+
+#[test]
+fn create_with_code_starting_with_ef_fails() {
+    let offset: u8 = 22;
+    let size: u8 = 10;
+    let sender_nonce = 1;
+    let sender_addr = Address::from_low_u64_be(40);
+
+    // Code that returns the single byte 0xEF (EIP-3541)
+    let initialization_code = hex::decode("60EF60005360016000F3").unwrap();
+
+    let mut operations = vec![
+        // Store initialization code in memory
+        Operation::Push((10, BigUint::from_bytes_be(&initialization_code))),
+        Operation::Push0,
+        Operation::Mstore,
+        // Create
+        Operation::Push((1, BigUint::from(size))),
+        Operation::Push((1, BigUint::from(offset))),
+        Operation::Push0,
+        Operation::Create,
+    ];
+    append_return_result_operations(&mut operations);
+    let (env, db) = default_env_and_db_setup(operations);
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+
+    assert!(result.is_success());
+    assert_eq!(result.output().unwrap().to_vec(), [0_u8; 32].to_vec());
+    let new_address = compute_contract_address(sender_addr, sender_nonce);
+    assert!(evm.db.basic(new_address).unwrap().is_none());
+    let sender_account = evm.db.basic(sender_addr).unwrap().unwrap();
+    assert_eq!(sender_account.nonce, sender_nonce + 1);
 }
 
 #[test]
@@ -3679,7 +4812,7 @@ fn create_gas_cost() {
     let needed_gas = gas_cost::PUSHN * 4
         + gas_cost::PUSH0
         + gas_cost::MSTORE
-        + gas_cost::memory_expansion_cost(0, (size + offset).into())
+        + memory_expansion_cost(0, (size + offset).into())
         + gas_cost::CREATE
         + initialization_gas_cost
         + gas_cost::INIT_WORD_COST * minimum_word_size
@@ -3696,10 +4829,17 @@ fn create_gas_cost() {
         Operation::Push((1, BigUint::from(value))),
         Operation::Create,
     ];
-    let (mut env, db) = default_env_and_db_setup(operations);
-    env.tx.value = EU256::from(value);
+    let (env, db) = default_env_and_db_setup(operations);
 
-    run_program_assert_gas_exact_with_db(env, db, needed_gas as _);
+    // The init code only gets all but one 64th of the remaining gas, so the
+    // unused gas is checked instead of running with an exact gas limit
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    assert!(result.is_success());
+    assert_eq!(
+        result.gas_used(),
+        gas_cost::TX_BASE_COST + needed_gas as u64
+    );
 }
 
 #[test]
@@ -3735,14 +4875,13 @@ fn create2_happy_path() {
         Operation::Create2,
     ];
     append_return_result_operations(&mut operations);
-    let (mut env, mut db) = default_env_and_db_setup(operations);
+    let (env, mut db) = default_env_and_db_setup(operations);
     db.set_account(
         sender_addr,
         sender_nonce,
         sender_balance,
         Default::default(),
     );
-    env.tx.value = EU256::from(value);
     let mut evm = Evm::new(env, db);
     let result = evm.transact_commit().unwrap();
     assert!(result.is_success());
@@ -3805,7 +4944,6 @@ fn staticcall_state_modifying_revert_with_callee_ops(callee_ops: Vec<Operation>)
     let bytecode = Bytecode::from(program.to_bytecode());
     let mut env = Env::default();
     env.tx.transact_to = TransactTo::Call(caller_address);
-    env.tx.caller = caller_address;
     let caller_balance = 100_u8;
     let mut db = db.with_contract(caller_address, bytecode);
     db.set_account(caller_address, 0, caller_balance.into(), Default::default());
@@ -3834,6 +4972,16 @@ fn staticcall_with_sstore_reverts() {
     staticcall_state_modifying_revert_with_callee_ops(operations);
 }
 
+#[test]
+fn staticcall_with_tstore_reverts() {
+    let operations = vec![
+        Operation::Push((1_u8, 1_u8.into())),
+        Operation::Push((1_u8, 1_u8.into())),
+        Operation::Tstore,
+    ];
+    staticcall_state_modifying_revert_with_callee_ops(operations);
+}
+
 #[test]
 fn staticcall_with_call_with_value_not_zero_reverts() {
     let operations = vec![
@@ -3909,6 +5057,96 @@ fn staticcall_with_create2_reverts() {
     staticcall_state_modifying_revert_with_callee_ops(operations)
 }
 
+fn staticcall_returning_callee_output(db: Db, callee_ops: Vec<Operation>) -> (Env, Db) {
+    let caller_address = Address::from_low_u64_be(4040);
+    let callee_address = Address::from_low_u64_be(8080);
+    let program = Program::from(callee_ops);
+    let mut db = db.with_contract(callee_address, Bytecode::from(program.to_bytecode()));
+    db.set_account(callee_address, 0, 100_u8.into(), Default::default());
+
+    let mut caller_ops = vec![
+        Operation::Push((1_u8, 32_u8.into())), //Ret size
+        Operation::Push0,                      //Ret offset
+        Operation::Push0,                      //Args size
+        Operation::Push0,                      //Args offset
+        Operation::Push((16_u8, BigUint::from_bytes_be(callee_address.as_bytes()))), //Address
+        Operation::Push((10_u8, 1_000_000_u32.into())), //Gas
+        Operation::StaticCall,
+        Operation::Pop,
+        Operation::Push0,
+        Operation::Mload,
+    ];
+    append_return_result_operations(&mut caller_ops);
+
+    let program = Program::from(caller_ops);
+    let mut env = Env::default();
+    env.tx.transact_to = TransactTo::Call(caller_address);
+    let db = db.with_contract(caller_address, Bytecode::from(program.to_bytecode()));
+    (env, db)
+}
+
+fn call_ops_with_value(call: Operation, value: u8, address: Address) -> Vec<Operation> {
+    vec![
+        Operation::Push0,                                                     //Ret size
+        Operation::Push0,                                                     //Ret offset
+        Operation::Push0,                                                     //Args size
+        Operation::Push0,                                                     //Args offset
+        Operation::Push((1_u8, value.into())),                                //Value
+        Operation::Push((16_u8, BigUint::from_bytes_be(address.as_bytes()))), //Address
+        Operation::Push((10_u8, 100_000_u32.into())),                         //Gas
+        call,
+    ]
+}
+
+#[test]
+fn staticcall_with_callcode_with_value_not_zero_succeeds() {
+    // CALLCODE doesn't transfer value to another account, so it's allowed in a static context
+    let mut operations =
+        call_ops_with_value(Operation::CallCode, 1, Address::from_low_u64_be(9090));
+    append_return_result_operations(&mut operations);
+    let (env, db) = staticcall_returning_callee_output(Db::new(), operations);
+
+    run_program_assert_num_result(env, db, 1_u8.into());
+}
+
+#[test]
+fn staticcall_with_call_with_value_zero_succeeds() {
+    let mut operations = call_ops_with_value(Operation::Call, 0, Address::from_low_u64_be(9090));
+    append_return_result_operations(&mut operations);
+    let (env, db) = staticcall_returning_callee_output(Db::new(), operations);
+
+    run_program_assert_num_result(env, db, 1_u8.into());
+}
+
+#[rstest]
+#[case(Operation::Call)]
+#[case(Operation::CallCode)]
+#[case(Operation::DelegateCall)]
+fn staticcall_propagates_static_context_to_nested_calls(#[case] call: Operation) {
+    let storage_writer_address = Address::from_low_u64_be(9090);
+    let storage_writer_ops = vec![
+        Operation::Push((1_u8, 1_u8.into())),
+        Operation::Push((1_u8, 1_u8.into())),
+        Operation::Sstore,
+    ];
+    let program = Program::from(storage_writer_ops);
+    let db = Db::new().with_contract(
+        storage_writer_address,
+        Bytecode::from(program.to_bytecode()),
+    );
+
+    let is_delegatecall = matches!(call, Operation::DelegateCall);
+    let mut operations = call_ops_with_value(call, 0, storage_writer_address);
+    if is_delegatecall {
+        // DELEGATECALL doesn't take a value argument
+        operations.remove(4);
+    }
+    append_return_result_operations(&mut operations);
+    let (env, db) = staticcall_returning_callee_output(db, operations);
+
+    run_program_assert_num_result(env, db, 0_u8.into());
+}
+
 #[test]
 fn staticcall_callee_returns_value() {
     let db = Db::new();
@@ -3953,10 +5191,11 @@ fn staticcall_callee_returns_value() {
     let program = Program::from(caller_ops);
     let caller_bytecode = Bytecode::from(program.to_bytecode());
     let mut env = Env::default();
-    let db = db.with_contract(caller_address, caller_bytecode);
+    let mut db = db.with_contract(caller_address, caller_bytecode);
     env.tx.transact_to = TransactTo::Call(caller_address);
     env.tx.caller = origin;
     env.tx.value = origin_value.into();
+    db.set_balance(origin, origin_value.into());
 
     let expected_result = 0_u8.into(); // Value is set to zero on a static call
 
@@ -4075,6 +5314,24 @@ fn selfdestruct_on_inexistent_address() {
     assert_eq!(receiver.balance, balance);
 }
 
+#[test]
+fn selfdestruct_without_balance_does_not_create_the_receiver() {
+    // EIP-161: an empty account isn't created when no value is sent to it
+    let receiver_address = Address::from_low_u64_be(100);
+    let operations = vec![
+        Operation::Push((20, BigUint::from_bytes_be(receiver_address.as_bytes()))),
+        Operation::SelfDestruct,
+    ];
+    let (env, mut db) = default_env_and_db_setup(operations);
+    let callee_address = env.tx.get_address();
+    db.set_account(callee_address, 1, EU256::zero(), Default::default());
+    let mut evm = Evm::new(env, db);
+
+    let result = evm.transact_commit().unwrap();
+    assert!(result.is_success());
+    assert!(evm.db.basic(receiver_address).unwrap().is_none());
+}
+
 #[test]
 fn selfdestruct_on_already_existing_account() {
     // it should not be destructed, but modified (empty balance)
@@ -4168,7 +5425,6 @@ fn selfdestruct_on_newly_created_account() {
     let mut env = Env::default();
     env.tx.transact_to = TransactTo::Call(caller_address);
     env.tx.caller = origin;
-    env.tx.value = transfer_value.into();
     let mut db = Db::new().with_contract(caller_address, bytecode);
     db.set_account(
         caller_address,
@@ -4197,10 +5453,57 @@ fn selfdestruct_on_newly_created_account() {
     assert_eq!(receiver_balance, expected_receiver_balance);
 }
 
+#[test]
+fn selfdestruct_to_itself_on_already_existing_account_keeps_balance() {
+    // EIP-6780: the account isn't destructed and sending the balance to itself is a no-op
+    let init_balance = EU256::from(50);
+
+    let operations = vec![Operation::Address, Operation::SelfDestruct];
+    let (env, mut db) = default_env_and_db_setup(operations);
+    let contract_address = env.tx.get_address();
+    db.set_account(contract_address, 1, init_balance, Default::default());
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    assert!(matches!(
+        result,
+        ExecutionResult::Success {
+            reason: SuccessReason::SelfDestruct,
+            ..
+        }
+    ));
+
+    let contract = evm.db.basic(contract_address).unwrap().unwrap();
+    assert_eq!(contract.balance, init_balance);
+    assert!(contract.has_code());
+}
+
+#[test]
+fn selfdestruct_halts_execution() {
+    let operations = vec![
+        Operation::Push((1, BigUint::from(100_u8))),
+        Operation::SelfDestruct,
+        Operation::Push((1, BigUint::from(1_u8))),
+        Operation::Push0,
+        Operation::Sstore,
+    ];
+    let (env, db) = default_env_and_db_setup(operations);
+    let contract_address = env.tx.get_address();
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    assert!(result.is_success());
+    assert!(result.output().is_none());
+    assert_eq!(
+        evm.db.read_storage(contract_address, EU256::zero()),
+        EU256::zero()
+    );
+}
+
 #[test]
 fn selfdestruct_gas_cost_on_empty_account() {
     let receiver_address: u8 = 100;
-    let needed_gas = gas_cost::PUSHN + gas_cost::SELFDESTRUCT;
+    let needed_gas = gas_cost::PUSHN + gas_cost::SELFDESTRUCT + gas_cost::COLD_ACCOUNT_ACCESS_COST;
 
     let operations = vec![
         Operation::Push((20, BigUint::from(receiver_address))),
@@ -4214,7 +5517,10 @@ fn selfdestruct_gas_cost_on_empty_account() {
 fn selfdestruct_gas_cost_on_non_empty_account() {
     let receiver_address: u8 = 100;
     let balance = EU256::from(231);
-    let needed_gas = gas_cost::PUSHN + gas_cost::SELFDESTRUCT + gas_cost::SELFDESTRUCT_DYNAMIC_GAS;
+    let needed_gas = gas_cost::PUSHN
+        + gas_cost::SELFDESTRUCT
+        + gas_cost::SELFDESTRUCT_DYNAMIC_GAS
+        + gas_cost::COLD_ACCOUNT_ACCESS_COST;
 
     let operations = vec![
         Operation::Push((20, BigUint::from(receiver_address))),
@@ -4294,3 +5600,572 @@ fn tstore_tload_happy_path() {
     let (env, db) = default_env_and_db_setup(operations);
     run_program_assert_num_result(env, db, BigUint::from(value));
 }
+
+#[rstest]
+#[case(false, 7)]
+#[case(true, 5)]
+fn transient_storage_is_shared_with_nested_frames_and_reverted_with_them(
+    #[case] callee_reverts: bool,
+    #[case] expected_value: u8,
+) {
+    let caller_address = Address::from_low_u64_be(4040);
+    let callee_address = Address::from_low_u64_be(8080);
+    let key = 1_u8;
+
+    // Callee overwrites the key, running in the caller's context
+    let mut callee_ops = vec![
+        Operation::Push((1_u8, BigUint::from(7_u8))),
+        Operation::Push((1_u8, BigUint::from(key))),
+        Operation::Tstore,
+    ];
+    if callee_reverts {
+        callee_ops.extend([Operation::Push0, Operation::Push0, Operation::Revert]);
+    }
+    let callee_bytecode = Bytecode::from(Program::from(callee_ops).to_bytecode());
+
+    let mut caller_ops = vec![
+        Operation::Push((1_u8, BigUint::from(5_u8))),
+        Operation::Push((1_u8, BigUint::from(key))),
+        Operation::Tstore,
+        Operation::Push0, //Ret size
+        Operation::Push0, //Ret offset
+        Operation::Push0, //Args size
+        Operation::Push0, //Args offset
+        Operation::Push((20_u8, BigUint::from_bytes_be(callee_address.as_bytes()))), //Address
+        Operation::Push((3_u8, BigUint::from(100_000_u32))), //Gas
+        Operation::DelegateCall,
+        Operation::Pop,
+        Operation::Push((1_u8, BigUint::from(key))),
+        Operation::Tload,
+    ];
+    append_return_result_operations(&mut caller_ops);
+    let caller_bytecode = Bytecode::from(Program::from(caller_ops).to_bytecode());
+
+    let mut env = Env::default();
+    env.tx.gas_limit = 999_999;
+    env.tx.transact_to = TransactTo::Call(caller_address);
+    let db = Db::new()
+        .with_contract(caller_address, caller_bytecode)
+        .with_contract(callee_address, callee_bytecode);
+
+    run_program_assert_num_result(env, db, BigUint::from(expected_value));
+}
+
+/// A database backend that only forwards reads to an inner [`Db`].
+struct ForwardingDb(Db);
+
+impl Database for ForwardingDb {
+    type Error = <Db as Database>::Error;
+
+    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        self.0.basic(address)
+    }
+
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        self.0.code_by_hash(code_hash)
+    }
+
+    fn storage(&mut self, address: Address, index: EU256) -> Result<EU256, Self::Error> {
+        self.0.storage(address, index)
+    }
+
+    fn block_hash(&mut self, number: EU256) -> Result<B256, Self::Error> {
+        self.0.block_hash(number)
+    }
+}
+
+#[test]
+fn transact_works_with_any_database_backend() {
+    let key = 1_u8;
+    let value = 42_u8;
+    let operations = vec![
+        Operation::Push((1_u8, BigUint::from(value))),
+        Operation::Push((1_u8, BigUint::from(key))),
+        Operation::Sstore,
+    ];
+    let (env, db) = default_env_and_db_setup(operations);
+    let contract_address = env.tx.get_address();
+
+    let mut evm = Evm::new(env, ForwardingDb(db));
+    let ResultAndState { result, state } = evm.transact().unwrap();
+
+    assert!(result.is_success());
+    let slot = &state.get(&contract_address).unwrap().storage[&EU256::from(key)];
+    assert_eq!(slot.present_value, EU256::from(value));
+}
+
+/// A database backend whose storage reads always fail.
+struct FailingStorageDb(Db);
+
+impl Database for FailingStorageDb {
+    type Error = DatabaseError;
+
+    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        Ok(self.0.basic(address).unwrap())
+    }
+
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        Ok(self.0.code_by_hash(code_hash).unwrap())
+    }
+
+    fn storage(&mut self, _address: Address, _index: EU256) -> Result<EU256, Self::Error> {
+        Err(DatabaseError)
+    }
+
+    fn block_hash(&mut self, number: EU256) -> Result<B256, Self::Error> {
+        Ok(self.0.block_hash(number).unwrap())
+    }
+}
+
+#[test]
+fn database_errors_abort_the_transaction() {
+    let operations = vec![
+        Operation::Push((1_u8, BigUint::from(1_u8))),
+        Operation::Sload,
+    ];
+    let (env, db) = default_env_and_db_setup(operations);
+
+    let mut evm = Evm::new(env, FailingStorageDb(db));
+    let result = evm.transact();
+
+    assert_eq!(result, Err(EVMError::Database(DatabaseError)));
+}
+
+#[rstest]
+#[case(Operation::Mstore, BigUint::from(1_u64 << 32))]
+#[case(Operation::Mstore, BigUint::from(u64::MAX) + 1_u8)]
+#[case(Operation::Mstore8, BigUint::from_bytes_be(&[0xff; 32]))]
+fn memory_store_at_huge_offset_halts_with_out_of_gas(
+    #[case] operation: Operation,
+    #[case] offset: BigUint,
+) {
+    let operations = vec![
+        Operation::Push0,
+        Operation::Push((32_u8, offset)),
+        operation,
+        Operation::Stop,
+    ];
+    let (env, db) = default_env_and_db_setup(operations);
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    assert!(matches!(
+        result,
+        ExecutionResult::Halt {
+            reason: HaltReason::OutOfGas(OutOfGasError::Basic),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn calldatacopy_with_huge_size_halts_with_out_of_gas() {
+    let operations = vec![
+        Operation::Push((32_u8, BigUint::from(1_u64 << 32) + 1_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::CallDataCopy,
+        Operation::Stop,
+    ];
+    let (env, db) = default_env_and_db_setup(operations);
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    assert!(matches!(
+        result,
+        ExecutionResult::Halt {
+            reason: HaltReason::OutOfGas(OutOfGasError::Basic),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn return_with_huge_offset_and_zero_size_succeeds() {
+    let operations = vec![
+        Operation::Push0,
+        Operation::Push((32_u8, BigUint::from_bytes_be(&[0xff; 32]))),
+        Operation::Return,
+    ];
+    let (env, db) = default_env_and_db_setup(operations);
+
+    run_program_assert_bytes_result(env, db, &[]);
+}
+
+#[test]
+fn memory_expansion_that_cant_be_paid_for_halts_with_out_of_gas_under_a_memory_limit() {
+    let operations = vec![
+        Operation::Push0,
+        Operation::Push((4_u8, BigUint::from(1_u64 << 31))),
+        Operation::Mstore,
+        Operation::Stop,
+    ];
+    let (mut env, db) = default_env_and_db_setup(operations);
+    env.cfg.memory_limit = 64;
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    assert!(matches!(
+        result,
+        ExecutionResult::Halt {
+            reason: HaltReason::OutOfGas(OutOfGasError::Basic),
+            ..
+        }
+    ));
+}
+
+#[rstest]
+#[case(32, true)]
+#[case(33, false)]
+fn memory_expansion_is_capped_by_the_configured_memory_limit(
+    #[case] offset: u8,
+    #[case] fits_in_limit: bool,
+) {
+    let operations = vec![
+        Operation::Push0,
+        Operation::Push((1_u8, BigUint::from(offset))),
+        Operation::Mstore,
+        Operation::Stop,
+    ];
+    let (mut env, db) = default_env_and_db_setup(operations);
+    env.cfg.memory_limit = 64;
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    if fits_in_limit {
+        assert!(result.is_success());
+    } else {
+        assert!(matches!(
+            result,
+            ExecutionResult::Halt {
+                reason: HaltReason::OutOfGas(OutOfGasError::MemoryLimit),
+                ..
+            }
+        ));
+    }
+}
+
+#[rstest]
+#[case::push0(vec![Operation::Push0], SpecId::Merge, SpecId::Shanghai)]
+#[case::basefee(vec![Operation::Basefee], SpecId::Berlin, SpecId::London)]
+#[case::tload(vec![Operation::Push0, Operation::Tload], SpecId::Shanghai, SpecId::Cancun)]
+#[case::shl(
+    vec![Operation::Push((1, 1_u8.into())), Operation::Push((1, 1_u8.into())), Operation::Shl],
+    SpecId::Byzantium,
+    SpecId::Constantinople
+)]
+fn opcodes_are_invalid_before_the_spec_adding_them(
+    #[case] operations: Vec<Operation>,
+    #[case] spec_before: SpecId,
+    #[case] spec_adding: SpecId,
+) {
+    let (mut env, db) = default_env_and_db_setup(operations);
+    env.cfg.spec_id = spec_before;
+    run_program_assert_halt(env.clone(), db.clone());
+
+    env.cfg.spec_id = spec_adding;
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    assert!(result.is_success());
+}
+
+#[rstest]
+#[case(SpecId::Frontier, 50)]
+#[case(SpecId::Tangerine, 200)]
+#[case(SpecId::Istanbul, 800)]
+#[case(SpecId::Berlin, gas_cost::COLD_SLOAD_COST)]
+fn sload_gas_depends_on_the_spec(#[case] spec_id: SpecId, #[case] sload_cost: i64) {
+    let program = vec![
+        Operation::Push((1_u8, BigUint::from(1_u8))),
+        Operation::Sload,
+    ];
+    let mut env = Env::default();
+    env.cfg.spec_id = spec_id;
+
+    run_program_assert_gas_exact(program, env, (gas_cost::PUSHN + sload_cost) as _);
+}
+
+#[test]
+fn cached_code_is_compiled_again_for_another_spec() {
+    let program = vec![
+        Operation::Push((1_u8, BigUint::from(1_u8))),
+        Operation::Sload,
+    ];
+    let (mut env, db) = default_env_and_db_setup(program);
+    env.cfg.spec_id = SpecId::Istanbul;
+    let mut evm = Evm::new(env, db);
+    let istanbul_gas = evm.transact().unwrap().result.gas_used();
+
+    evm.env.cfg.spec_id = SpecId::Berlin;
+    let berlin_gas = evm.transact().unwrap().result.gas_used();
+
+    assert_eq!(
+        berlin_gas - istanbul_gas,
+        (gas_cost::COLD_SLOAD_COST - gas_cost::sload_cost(SpecId::Istanbul)) as u64
+    );
+}
+
+#[test]
+fn cached_code_reads_the_static_flag_of_each_frame() {
+    let callee_address = Address::from_low_u64_be(8080);
+    let callee_ops = vec![
+        Operation::Push((1_u8, BigUint::from(1_u8))),
+        Operation::Push0,
+        Operation::Sstore,
+        Operation::Stop,
+    ];
+    let callee_bytecode = Bytecode::from(Program::from(callee_ops).to_bytecode());
+    let callee = BigUint::from_bytes_be(callee_address.as_bytes());
+
+    let mut operations = vec![
+        // The first call compiles the callee's code, and the second one reuses it
+        Operation::Push0,                                    //Ret size
+        Operation::Push0,                                    //Ret offset
+        Operation::Push0,                                    //Args size
+        Operation::Push0,                                    //Args offset
+        Operation::Push((20_u8, callee.clone())),            //Address
+        Operation::Push((3_u8, BigUint::from(100_000_u32))), //Gas
+        Operation::StaticCall,
+        Operation::Push0,                                    //Ret size
+        Operation::Push0,                                    //Ret offset
+        Operation::Push0,                                    //Args size
+        Operation::Push0,                                    //Args offset
+        Operation::Push0,                                    //Value
+        Operation::Push((20_u8, callee)),                    //Address
+        Operation::Push((3_u8, BigUint::from(100_000_u32))), //Gas
+        Operation::Call,
+        // Only the second call succeeds: 2 * call_result + staticcall_result
+        Operation::Push((1_u8, BigUint::from(2_u8))),
+        Operation::Mul,
+        Operation::Add,
+    ];
+    append_return_result_operations(&mut operations);
+    let (env, db) = default_env_and_db_setup(operations);
+    let db = db.with_contract(callee_address, callee_bytecode);
+
+    run_program_assert_num_result(env, db, BigUint::from(2_u8));
+}
+
+#[rstest]
+#[case(SpecId::Frontier, 20)]
+#[case(SpecId::Tangerine, 400)]
+#[case(SpecId::Istanbul, 700)]
+#[case(SpecId::Berlin, gas_cost::COLD_ACCOUNT_ACCESS_COST)]
+fn balance_gas_depends_on_the_spec(#[case] spec_id: SpecId, #[case] balance_cost: i64) {
+    let program = vec![
+        Operation::Push((1_u8, BigUint::from(123_u8))),
+        Operation::Balance,
+    ];
+    let mut env = Env::default();
+    env.cfg.spec_id = spec_id;
+
+    run_program_assert_gas_exact(program, env, (gas_cost::PUSHN + balance_cost) as _);
+}
+
+#[test]
+fn sstore_refund_is_capped_to_half_the_gas_used_before_london() {
+    let original_value = 10_u8;
+    let (key_a, key_b) = (80_u8, 81_u8);
+
+    // Each sstore clears a cold slot: gas_cost = 5_000, gas_refund = 15_000
+    let used_gas = 2 * (5_000 + gas_cost::PUSH0 + gas_cost::PUSHN) as u64;
+    let needed_gas = used_gas + gas_cost::SSTORE_MIN_REMAINING_GAS as u64;
+    let refunded_gas = (gas_cost::TX_BASE_COST + used_gas) / gas_cost::MAX_REFUND_QUOTIENT_FRONTIER;
+    assert!(refunded_gas < 2 * gas_cost::SSTORE_CLEARS_SCHEDULE as u64);
+
+    let program = vec![
+        Operation::Push0,
+        Operation::Push((1_u8, BigUint::from(key_a))),
+        Operation::Sstore,
+        Operation::Push0,
+        Operation::Push((1_u8, BigUint::from(key_b))),
+        Operation::Sstore,
+    ];
+    let (mut env, mut db) = default_env_and_db_setup(program);
+    // PUSH0 is only available since Shanghai
+    env.cfg.spec_id = SpecId::Berlin;
+    let callee = env.tx.get_address();
+    db.write_storage(callee, EU256::from(key_a), EU256::from(original_value));
+    db.write_storage(callee, EU256::from(key_b), EU256::from(original_value));
+
+    run_program_assert_gas_and_refund(env, db, needed_gas, used_gas, refunded_gas);
+}
+
+#[test]
+fn non_zero_calldata_costs_more_before_istanbul() {
+    let (mut env, db) = default_env_and_db_setup(vec![Operation::Stop]);
+    env.tx.data = Bytes::from(vec![1, 0]);
+    env.cfg.spec_id = SpecId::Petersburg;
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+
+    assert!(result.is_success());
+    assert_eq!(
+        result.gas_used(),
+        gas_cost::TX_BASE_COST
+            + gas_cost::TX_DATA_COST_PER_NON_ZERO_FRONTIER
+            + gas_cost::TX_DATA_COST_PER_ZERO
+    );
+}
+
+#[test]
+fn selfdestruct_deletes_already_existing_accounts_before_cancun() {
+    let receiver_address = Address::from_low_u64_be(123);
+    let operations = vec![
+        Operation::Push((20, BigUint::from_bytes_be(receiver_address.as_bytes()))),
+        Operation::SelfDestruct,
+    ];
+    let (mut env, mut db) = default_env_and_db_setup(operations);
+    env.cfg.spec_id = SpecId::Shanghai;
+    let contract_address = env.tx.get_address();
+    db.set_account(contract_address, 1, 50.into(), Default::default());
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+
+    assert!(result.is_success());
+    assert!(evm.db.basic(contract_address).unwrap().is_none());
+    assert_eq!(
+        evm.db.basic(receiver_address).unwrap().unwrap().balance,
+        50.into()
+    );
+}
+
+#[rstest]
+#[case(ChainConfig::mainnet(), 0, 0, SpecId::Frontier)]
+#[case(ChainConfig::mainnet(), 2_463_000, 0, SpecId::Tangerine)]
+#[case(ChainConfig::mainnet(), 7_280_000, 0, SpecId::Petersburg)]
+#[case(ChainConfig::mainnet(), 12_964_999, 0, SpecId::Berlin)]
+#[case(ChainConfig::mainnet(), 15_537_394, 1_663_224_179, SpecId::Merge)]
+#[case(ChainConfig::mainnet(), 17_034_870, 1_681_338_455, SpecId::Shanghai)]
+#[case(ChainConfig::mainnet(), 19_426_587, 1_710_338_135, SpecId::Cancun)]
+#[case(ChainConfig::sepolia(), 0, 0, SpecId::London)]
+#[case(ChainConfig::sepolia(), 1_735_371, 0, SpecId::Merge)]
+#[case(ChainConfig::holesky(), 0, 0, SpecId::Merge)]
+#[case(ChainConfig::holesky(), 0, 1_707_305_664, SpecId::Cancun)]
+fn chain_config_selects_the_spec_by_block_number_and_timestamp(
+    #[case] chain_config: ChainConfig,
+    #[case] number: u64,
+    #[case] timestamp: u64,
+    #[case] expected_spec: SpecId,
+) {
+    assert_eq!(chain_config.spec_id(number, timestamp), expected_spec);
+}
+
+#[test]
+fn evm_uses_the_spec_active_in_the_block() {
+    let (mut env, db) = default_env_and_db_setup(vec![Operation::Push0]);
+    env.cfg.set_chain_config(ChainConfig::mainnet());
+    env.tx.chain_id = Some(1);
+    // The last block before Shanghai, where PUSH0 isn't available yet
+    env.block.number = 17_034_869.into();
+    env.block.timestamp = 1_681_338_443.into();
+    run_program_assert_halt(env.clone(), db.clone());
+
+    env.block.number = 17_034_870.into();
+    env.block.timestamp = 1_681_338_455.into();
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact_commit().unwrap();
+    assert!(result.is_success());
+}
+
+#[test]
+fn evm_rejects_blocks_after_prague() {
+    let (mut env, db) = default_env_and_db_setup(vec![Operation::Stop]);
+    env.cfg.set_chain_config(ChainConfig::mainnet());
+    env.tx.chain_id = Some(1);
+    env.block.number = 22_431_084.into();
+    env.block.timestamp = 1_746_612_311.into();
+
+    let mut evm = Evm::new(env, db);
+    let result = evm.transact();
+
+    assert!(matches!(result, Err(EVMError::Custom(_))));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn chain_config_is_parsed_from_a_genesis_file() {
+    let genesis = r#"{
+        "config": {
+            "chainId": 1337,
+            "homesteadBlock": 0,
+            "eip150Block": 0,
+            "eip155Block": 0,
+            "eip158Block": 0,
+            "byzantiumBlock": 0,
+            "constantinopleBlock": 0,
+            "petersburgBlock": 0,
+            "istanbulBlock": 0,
+            "berlinBlock": 0,
+            "londonBlock": 10,
+            "terminalTotalDifficulty": 58750000000000000000000,
+            "mergeNetsplitBlock": 20,
+            "shanghaiTime": 1000
+        },
+        "difficulty": "0x1",
+        "gasLimit": "0x1c9c380",
+        "alloc": {}
+    }"#;
+
+    let chain_config = ChainConfig::from_json(genesis).unwrap();
+
+    assert_eq!(chain_config.chain_id, 1337);
+    assert_eq!(chain_config.cancun_time, None);
+    assert_eq!(chain_config.spec_id(9, 0), SpecId::Berlin);
+    assert_eq!(chain_config.spec_id(10, 0), SpecId::London);
+    assert_eq!(chain_config.spec_id(20, 0), SpecId::Merge);
+    assert_eq!(chain_config.spec_id(20, 1000), SpecId::Shanghai);
+    assert_eq!(
+        ChainConfig::from_json(r#"{"chainId": 1337, "londonBlock": 10}"#).unwrap(),
+        ChainConfig {
+            chain_id: 1337,
+            london_block: Some(10),
+            ..Default::default()
+        }
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn chain_config_without_netsplit_block_merges_at_genesis_with_zero_terminal_difficulty() {
+    let genesis = r#"{
+        "config": {
+            "chainId": 1337,
+            "homesteadBlock": 0,
+            "eip150Block": 0,
+            "eip158Block": 0,
+            "byzantiumBlock": 0,
+            "constantinopleBlock": 0,
+            "petersburgBlock": 0,
+            "istanbulBlock": 0,
+            "berlinBlock": 0,
+            "londonBlock": 0,
+            "terminalTotalDifficulty": 0,
+            "shanghaiTime": 1000
+        },
+        "difficulty": "0x0",
+        "gasLimit": "0x1c9c380",
+        "alloc": {}
+    }"#;
+
+    let chain_config = ChainConfig::from_json(genesis).unwrap();
+
+    assert_eq!(chain_config.merge_netsplit_block, Some(0));
+    assert_eq!(chain_config.spec_id(0, 0), SpecId::Merge);
+    assert_eq!(chain_config.spec_id(5, 1000), SpecId::Shanghai);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn chain_config_without_netsplit_block_is_rejected_when_the_merge_block_is_unknown() {
+    let config = r#"{
+        "chainId": 1337,
+        "londonBlock": 0,
+        "terminalTotalDifficulty": 58750000000000000000000,
+        "shanghaiTime": 1000
+    }"#;
+
+    assert!(ChainConfig::from_json(config).is_err());
+}
@@ -5,18 +5,20 @@
 use evm_mlir::{
     constants::gas_cost::{self, log_dynamic_gas_cost},
     context::Context,
-    db::Db,
+    db::{AccountInfo, Bytecode, Database, Db},
     env::Env,
     executor::Executor,
     journal::Journal,
-    primitives::Bytes,
+    primitives::{Address, Bytes, B256, U256},
     program::{Operation, Program},
-    result::{ExecutionResult, HaltReason, Output, SuccessReason},
-    syscall::SyscallContext,
+    result::{ExecutionResult, HaltReason, Output, ResultAndState, SuccessReason},
+    spec::SpecId,
+    syscall::{CallFrame, SyscallContext},
 };
 use hex_literal::hex;
 use num_bigint::{BigInt, BigUint};
 use rstest::rstest;
+use std::convert::Infallible;
 
 fn run_program_get_result_with_gas(
     operations: Vec<Operation>,
@@ -27,14 +29,18 @@ fn run_program_get_result_with_gas(
 
     let context = Context::new();
     let module = context
-        .compile(&program, Default::default())
+        .compile(&program, SpecId::default(), Default::default())
         .expect("failed to compile program");
 
     let mut env = Env::default();
     env.tx.gas_limit = initial_gas;
     let mut db = Db::default();
     let journal = Journal::new(&mut db);
-    let mut context = SyscallContext::new(env, journal, Default::default());
+    let call_frame = CallFrame {
+        gas_limit: initial_gas,
+        ..Default::default()
+    };
+    let mut context = SyscallContext::new(env, journal, call_frame);
     let executor = Executor::new(&module, &context, Default::default());
 
     let _result = executor.execute(&mut context, initial_gas);
@@ -107,6 +113,15 @@ pub fn biguint_256_from_bigint(value: BigInt) -> BigUint {
     }
 }
 
+/// Gas cost of expanding the memory from `last_size` to `new_size` bytes
+fn memory_expansion_cost(last_size: u32, new_size: u32) -> i64 {
+    let memory_cost = |size: u32| {
+        let words = (size as i64 + 31) / 32;
+        words * words / 512 + 3 * words
+    };
+    memory_cost(new_size) - memory_cost(last_size)
+}
+
 #[test]
 fn test_keccak256() {
     let program = vec![
@@ -169,7 +184,7 @@ fn test_keccak_gas_cost() {
         Operation::Push((1, offset.into())),
         Operation::Keccak256,
     ];
-    let dynamic_gas = gas_cost::memory_expansion_cost(0, 32) + 2 * gas_cost::memory_copy_cost(32);
+    let dynamic_gas = memory_expansion_cost(0, 32) + 2 * gas_cost::memory_copy_cost(32);
     let static_gas = gas_cost::KECCAK256 + 2 * gas_cost::PUSHN;
     let gas_needed = static_gas + dynamic_gas;
 
@@ -189,7 +204,7 @@ fn test_return_with_gas() {
         Operation::Push((1, 2_u8.into())),
         Operation::Return,
     ];
-    let dynamic_gas = gas_cost::memory_expansion_cost(0, 32);
+    let dynamic_gas = memory_expansion_cost(0, 32);
     let needed_gas = gas_cost::PUSHN * 2 + dynamic_gas;
 
     run_program_assert_gas_exact(program, needed_gas as _);
@@ -202,7 +217,7 @@ fn test_revert_with_gas() {
         Operation::Push((1, 2_u8.into())),
         Operation::Revert,
     ];
-    let dynamic_gas = gas_cost::memory_expansion_cost(0, 32);
+    let dynamic_gas = memory_expansion_cost(0, 32);
     let needed_gas = gas_cost::PUSHN * 2 + dynamic_gas;
 
     // When gas is not enough, exits as halt instead of revert.
@@ -2375,7 +2390,7 @@ fn mstore_gas_cost_with_memory_extension() {
         Operation::Push((1_u8, BigUint::from(64_u8))), // offset
         Operation::Mstore,
     ];
-    let dynamic_gas = gas_cost::memory_expansion_cost(0, 96);
+    let dynamic_gas = memory_expansion_cost(0, 96);
     let needed_gas = gas_cost::PUSHN * 2 + gas_cost::MSTORE + dynamic_gas;
     run_program_assert_gas_exact(program, needed_gas as _);
 }
@@ -2387,7 +2402,7 @@ fn mstore8_gas_cost_with_memory_extension() {
         Operation::Push((1_u8, BigUint::from(31_u8))), // offset
         Operation::Mstore8,
     ];
-    let dynamic_gas = gas_cost::memory_expansion_cost(0, 32);
+    let dynamic_gas = memory_expansion_cost(0, 32);
     let needed_gas = gas_cost::PUSHN * 2 + gas_cost::MSTORE8 + dynamic_gas;
     run_program_assert_gas_exact(program, needed_gas as _);
 }
@@ -2398,7 +2413,7 @@ fn mload_gas_cost_with_memory_extension() {
         Operation::Push0, // offset
         Operation::Mload,
     ];
-    let dynamic_gas = gas_cost::memory_expansion_cost(0, 32);
+    let dynamic_gas = memory_expansion_cost(0, 32);
     let needed_gas = gas_cost::PUSH0 + gas_cost::MLOAD + dynamic_gas;
     run_program_assert_gas_exact(program, needed_gas as _);
 }
@@ -2409,7 +2424,7 @@ fn mload_gas_cost_with_memory_extension2() {
         Operation::Push((1_u8, BigUint::from(1_u8))), // offset
         Operation::Mload,
     ];
-    let dynamic_gas = gas_cost::memory_expansion_cost(0, 64);
+    let dynamic_gas = memory_expansion_cost(0, 64);
     let needed_gas = gas_cost::PUSHN + gas_cost::MLOAD + dynamic_gas;
     run_program_assert_gas_exact(program, needed_gas as _);
 }
@@ -2438,7 +2453,7 @@ fn mstore_mcopy_mload_with_zero_address_and_gas() {
         Operation::Push((1_u8, BigUint::from(32_u8))),
         Operation::Mload,
     ];
-    let dynamic_gas = gas_cost::memory_expansion_cost(0, 64) + gas_cost::memory_copy_cost(32);
+    let dynamic_gas = memory_expansion_cost(0, 64) + gas_cost::memory_copy_cost(32);
     let gas_needed = gas_cost::PUSH0 * 2
         + gas_cost::PUSHN * 4
         + gas_cost::MCOPY
@@ -2462,7 +2477,7 @@ fn mcopy_dynamic_gas() {
         Operation::Push((1_u8, BigUint::from(32_u8))),
         Operation::Mload,
     ];
-    let dynamic_gas = gas_cost::memory_expansion_cost(0, 64) + gas_cost::memory_copy_cost(1);
+    let dynamic_gas = memory_expansion_cost(0, 64) + gas_cost::memory_copy_cost(1);
     let gas_needed = gas_cost::PUSH0 * 2
         + gas_cost::PUSHN * 4
         + gas_cost::MLOAD
@@ -2486,7 +2501,7 @@ fn mcopy_gas_zero_byte_copy() {
         Operation::Push0,
         Operation::Mload,
     ];
-    let dynamic_gas = gas_cost::memory_expansion_cost(0, 32) + gas_cost::memory_copy_cost(0);
+    let dynamic_gas = memory_expansion_cost(0, 32) + gas_cost::memory_copy_cost(0);
     let gas_needed = gas_cost::PUSH0 * 5
         + gas_cost::PUSHN
         + gas_cost::MLOAD
@@ -2586,7 +2601,7 @@ fn codecopy_with_gas_cost() {
 
     let static_gas = gas_cost::CODECOPY + gas_cost::PUSHN * 3;
     let dynamic_gas = gas_cost::memory_copy_cost(size.into())
-        + gas_cost::memory_expansion_cost(0, (dest_offset + size) as u32);
+        + memory_expansion_cost(0, (dest_offset + size) as u32);
     let expected_gas = static_gas + dynamic_gas;
     run_program_assert_gas_exact(program, expected_gas as _);
 }
@@ -2612,8 +2627,8 @@ fn log_with_gas_cost(#[case] n: u8) {
     program.push(Operation::Log(n));
     let topic_count = n as i64;
     let static_gas = gas_cost::LOG + gas_cost::PUSHN * (2 + topic_count);
-    let dynamic_gas = log_dynamic_gas_cost(size as u32, topic_count as u32)
-        + gas_cost::memory_expansion_cost(0, 32_u32);
+    let dynamic_gas =
+        log_dynamic_gas_cost(size as u32, topic_count as u32) + memory_expansion_cost(0, 32_u32);
     let gas_needed = static_gas + dynamic_gas;
     run_program_assert_gas_exact(program, gas_needed as _);
 }
@@ -2648,8 +2663,8 @@ fn extcodecopy_gas_check() {
 
     let static_gas = gas_cost::PUSHN * 4;
     let dynamic_gas = gas_cost::memory_copy_cost(size.into())
-        + gas_cost::memory_expansion_cost(0, (dest_offset + size) as u32)
-        + gas_cost::EXTCODECOPY_WARM;
+        + memory_expansion_cost(0, (dest_offset + size) as u32)
+        + gas_cost::COLD_ACCOUNT_ACCESS_COST;
     let expected_gas = static_gas + dynamic_gas;
     run_program_assert_gas_exact(program, expected_gas as _);
 }
@@ -2675,3 +2690,81 @@ fn invalid_gas_check() {
     };
     assert_eq!(result, expected_result);
 }
+
+/// Wraps a [`Db`], panicking when the storage slot `failing_key` is read.
+struct FailingStorageDb {
+    db: Db,
+    failing_key: U256,
+}
+
+impl Database for FailingStorageDb {
+    type Error = Infallible;
+
+    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        self.db.basic(address)
+    }
+
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        self.db.code_by_hash(code_hash)
+    }
+
+    fn storage(&mut self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        assert_ne!(index, self.failing_key, "storage read failed");
+        self.db.storage(address, index)
+    }
+
+    fn block_hash(&mut self, number: U256) -> Result<B256, Self::Error> {
+        self.db.block_hash(number)
+    }
+}
+
+#[test]
+fn failing_syscall_halts_before_the_next_operation() {
+    let (failing_key, written_key) = (1_u8, 2_u8);
+    let program = Program::from(vec![
+        Operation::Push((1_u8, failing_key.into())),
+        Operation::Sload,
+        // none of the operations below should be executed
+        Operation::Push((1_u8, 1_u8.into())),
+        Operation::Push((1_u8, written_key.into())),
+        Operation::Sstore,
+        Operation::Stop,
+    ]);
+    let gas = 1e7 as u64;
+
+    let context = Context::new();
+    let module = context
+        .compile(&program, SpecId::default(), Default::default())
+        .expect("failed to compile program");
+
+    let mut env = Env::default();
+    env.tx.gas_limit = gas;
+    // The executing account must exist, so the storage read reaches the database
+    let mut db = FailingStorageDb {
+        db: Db::new().with_contract(
+            Address::zero(),
+            Bytecode::from(program.clone().to_bytecode()),
+        ),
+        failing_key: U256::from(failing_key),
+    };
+    let journal = Journal::new(&mut db);
+    let call_frame = CallFrame {
+        gas_limit: gas,
+        ..Default::default()
+    };
+    let mut context = SyscallContext::new(env, journal, call_frame);
+    let executor = Executor::new(&module, &context, Default::default());
+    let _result = executor.execute(&mut context, gas);
+
+    let ResultAndState { result, state } = context.get_result().unwrap();
+    let expected_result = ExecutionResult::Halt {
+        reason: HaltReason::FatalExternalError,
+        gas_used: gas,
+    };
+    assert_eq!(result, expected_result);
+    let written_key = U256::from(written_key);
+    assert!(state.contains_key(&Address::zero()));
+    assert!(state
+        .values()
+        .all(|account| !account.storage.contains_key(&written_key)));
+}